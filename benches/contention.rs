@@ -0,0 +1,328 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Latency and throughput of lock acquisitions and renewals, with and without contention.
+//!
+//! Run with `cargo bench`. Every scenario runs against the in-memory `MockDriver`, so
+//! that the benchmarks run without AWS, and against DynamoDB Local as well when the
+//! `DYNALOCK_DYNAMODB_LOCAL` environment variable holds its endpoint (e.g.,
+//! "http://localhost:8000", see src/providers/dynamodb/local_tests.rs).
+//!
+//! The benchmarks only depend on the standard library rather than on a harness such
+//! as criterion, whose dependencies don't build with the oldest Rust release this crate
+//! supports and would break `cargo test` there. Each scenario prints the mean latency
+//! of an operation and the throughput, compare them before and after a change.
+
+extern crate dynalock;
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Barrier};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use dynalock::mock::{MockDriver, MockStore};
+use dynalock::{DistLock, Locking};
+
+/// The number of operations timed by the uncontended scenarios.
+const MOCK_ROUNDS: u32 = 100_000;
+/// How long the contended scenarios run for, in seconds.
+const CONTENDED_SECS: u64 = 1;
+/// The numbers of threads fighting for the same lock in the contended scenarios.
+const CONTENDERS: [usize; 3] = [2, 8, 32];
+
+/// The outcome of a scenario.
+struct Report {
+    threads: usize,
+    attempts: u64,
+    successes: u64,
+    elapsed: Duration,
+}
+
+impl Report {
+    /// Print the mean latency of an attempt within a thread, the overall throughput of
+    /// attempts and successes, and the share of successful attempts.
+    fn print(&self, name: &str) {
+        let secs = self.elapsed.as_secs() as f64 + f64::from(self.elapsed.subsec_nanos()) / 1e9;
+        let attempts = self.attempts as f64;
+
+        println!(
+            "{:<40} {:>10.2} us/op {:>12.0} ops/s {:>12.0} successes/s {:>6.1}% successful",
+            name,
+            secs * self.threads as f64 * 1e6 / attempts,
+            attempts / secs,
+            self.successes as f64 / secs,
+            self.successes as f64 * 100.0 / attempts
+        );
+    }
+}
+
+/// Time `rounds` acquisitions of a free lock, each followed by a release.
+fn bench_acquire_release<L>(
+    lock: &mut L,
+    acquire: &L::AcquireLockInputType,
+    release: &L::ReleaseLockInputType,
+    rounds: u32,
+) -> Report
+where
+    L: Locking,
+{
+    let mut successes = 0;
+    let start = Instant::now();
+
+    for _ in 0..rounds {
+        if lock.acquire_lock(acquire).is_ok() && lock.release_lock(release).is_ok() {
+            successes += 1;
+        }
+    }
+
+    Report {
+        threads: 1,
+        attempts: u64::from(rounds),
+        successes: successes,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Time `rounds` renewals of a held lock with `acquire_lock`.
+fn bench_renew<L>(lock: &mut L, acquire: &L::AcquireLockInputType, rounds: u32) -> Report
+where
+    L: Locking,
+{
+    lock.acquire_lock(acquire).unwrap();
+
+    let mut successes = 0;
+    let start = Instant::now();
+
+    for _ in 0..rounds {
+        if lock.acquire_lock(acquire).is_ok() {
+            successes += 1;
+        }
+    }
+
+    Report {
+        threads: 1,
+        attempts: u64::from(rounds),
+        successes: successes,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Time `rounds` reads of the lock item with `refresh_lock`.
+fn bench_refresh<L>(lock: &mut L, refresh: &L::RefreshLockInputType, rounds: u32) -> Report
+where
+    L: Locking,
+{
+    let mut successes = 0;
+    let start = Instant::now();
+
+    for _ in 0..rounds {
+        if lock.refresh_lock(refresh).is_ok() {
+            successes += 1;
+        }
+    }
+
+    Report {
+        threads: 1,
+        attempts: u64::from(rounds),
+        successes: successes,
+        elapsed: start.elapsed(),
+    }
+}
+
+/// Have `threads` processors fight for the same lock for `CONTENDED_SECS`, each creating
+/// its lock with `new_lock` and releasing the lock right after acquiring it.
+///
+/// Failed acquisitions count as attempts, so the success share tells how much work the
+/// contention wastes.
+fn bench_contended<L, F, I>(threads: usize, new_lock: Arc<F>, input: I) -> Report
+where
+    L: Locking<AcquireLockInputType = I, RefreshLockInputType = I, ReleaseLockInputType = I>,
+    F: Fn() -> L + Send + Sync + 'static,
+    I: Clone + Send + 'static,
+{
+    let barrier = Arc::new(Barrier::new(threads + 1));
+    let running = Arc::new(AtomicBool::new(true));
+
+    let contenders: Vec<_> = (0..threads)
+        .map(|_| {
+            let new_lock = new_lock.clone();
+            let barrier = barrier.clone();
+            let running = running.clone();
+            let input = input.clone();
+
+            thread::spawn(move || {
+                let mut lock = new_lock();
+                let mut attempts = 0;
+                let mut successes = 0;
+
+                barrier.wait();
+                while running.load(Ordering::Relaxed) {
+                    attempts += 1;
+                    if lock.acquire_lock(&input).is_ok() {
+                        successes += 1;
+                        let _ = lock.release_lock(&input);
+                    } else {
+                        // Observe the holder's fence token like a waiter would
+                        let _ = lock.refresh_lock(&input);
+                    }
+                }
+
+                (attempts, successes)
+            })
+        })
+        .collect();
+
+    barrier.wait();
+    let start = Instant::now();
+    thread::sleep(Duration::from_secs(CONTENDED_SECS));
+    running.store(false, Ordering::Relaxed);
+
+    let mut report = Report {
+        threads: threads,
+        attempts: 0,
+        successes: 0,
+        elapsed: Duration::from_secs(0),
+    };
+    for contender in contenders {
+        let (attempts, successes) = contender.join().unwrap();
+        report.attempts += attempts;
+        report.successes += successes;
+    }
+    report.elapsed = start.elapsed();
+
+    report
+}
+
+fn bench_mock() {
+    let mut lock = DistLock::new(MockDriver::new(), Duration::from_secs(10));
+    bench_acquire_release(&mut lock, &(), &(), MOCK_ROUNDS).print("mock/acquire_release");
+
+    let mut lock = DistLock::new(MockDriver::new(), Duration::from_secs(10));
+    bench_renew(&mut lock, &(), MOCK_ROUNDS).print("mock/renew");
+    bench_refresh(&mut lock, &(), MOCK_ROUNDS).print("mock/refresh");
+
+    for &threads in CONTENDERS.iter() {
+        let store = MockStore::default();
+        let new_lock = Arc::new(move || {
+            DistLock::new(
+                MockDriver::with_store(store.clone(), "contended"),
+                Duration::from_secs(10),
+            )
+        });
+
+        bench_contended(threads, new_lock, ())
+            .print(&format!("mock/contended_acquire/{}", threads));
+    }
+}
+
+#[cfg(feature = "dynamodb")]
+mod local {
+    use std::env;
+    use std::sync::Arc;
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use dynalock::dynamodb::{
+        parse_region, DynamoDbDriver, DynamoDbDriverInput, DynamoDbLockInput,
+    };
+    use dynalock::rusoto_dynamodb::{
+        AttributeDefinition, CreateTableInput, DynamoDb, DynamoDbClient, KeySchemaElement,
+        ProvisionedThroughput,
+    };
+    use dynalock::{DistLock, Locking};
+
+    use super::{bench_acquire_release, bench_contended, bench_refresh, bench_renew, CONTENDERS};
+
+    /// The environment variable holding the DynamoDB Local endpoint.
+    const ENDPOINT_VAR: &'static str = "DYNALOCK_DYNAMODB_LOCAL";
+    /// The number of operations timed by the uncontended scenarios.
+    const LOCAL_ROUNDS: u32 = 500;
+
+    /// Create a lock table named `table_name` on DynamoDB Local and return the input of
+    /// drivers using it.
+    fn create_table(region: &str, table_name: &str) -> DynamoDbDriverInput {
+        let create_input = CreateTableInput {
+            table_name: table_name.to_string(),
+            attribute_definitions: vec![AttributeDefinition {
+                attribute_name: String::from("lock_id"),
+                attribute_type: String::from("S"),
+            }],
+            key_schema: vec![KeySchemaElement {
+                attribute_name: String::from("lock_id"),
+                key_type: String::from("HASH"),
+            }],
+            provisioned_throughput: ProvisionedThroughput {
+                read_capacity_units: 5,
+                write_capacity_units: 5,
+            },
+            ..Default::default()
+        };
+        DynamoDbClient::simple(parse_region(region).unwrap())
+            .create_table(&create_input)
+            .sync()
+            .unwrap();
+
+        DynamoDbDriverInput {
+            table_name: table_name.to_string(),
+            partition_key_field_name: String::from("lock_id"),
+            ..Default::default()
+        }
+    }
+
+    pub fn bench_local() {
+        let endpoint = match env::var(ENDPOINT_VAR) {
+            Ok(endpoint) => endpoint,
+            Err(_) => {
+                println!("{} is not set, skipping DynamoDB Local", ENDPOINT_VAR);
+                return;
+            }
+        };
+        let region = format!("custom:local:{}", endpoint);
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+        let table = create_table(
+            &region,
+            &format!("dynalock_bench_{}", since_epoch.as_secs()),
+        );
+        let input = DynamoDbLockInput {
+            consistent_read: Some(true),
+            ..Default::default()
+        };
+
+        let new_lock = Arc::new(move || {
+            let driver = DynamoDbDriver::new_in_region(&region, &table).unwrap();
+            DistLock::new(driver, Duration::from_secs(10))
+        });
+
+        let mut lock = new_lock();
+        bench_acquire_release(&mut lock, &input, &input, LOCAL_ROUNDS)
+            .print("dynamodb_local/acquire_release");
+
+        // Every scenario locks the same item, free it for the contended ones
+        let mut lock = new_lock();
+        bench_renew(&mut lock, &input, LOCAL_ROUNDS).print("dynamodb_local/renew");
+        bench_refresh(&mut lock, &input, LOCAL_ROUNDS).print("dynamodb_local/refresh");
+        lock.release_lock(&input).unwrap();
+
+        for &threads in CONTENDERS.iter() {
+            bench_contended(threads, new_lock.clone(), input.clone())
+                .print(&format!("dynamodb_local/contended_acquire/{}", threads));
+        }
+    }
+}
+
+fn main() {
+    bench_mock();
+
+    #[cfg(feature = "dynamodb")]
+    local::bench_local();
+}
@@ -0,0 +1,216 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A circuit breaker driven by the monotonic clock.
+//!
+//! Providers use the `CircuitBreaker` structure to stop calling a failing storage
+//! service. After `failure_threshold` consecutive failures within `window` the
+//! circuit opens and operations fail fast for `cooldown`. The circuit then turns
+//! half-open and lets a single probe through: a success closes the circuit while
+//! a failure opens it again for another `cooldown`.
+
+use std::time::{Duration, Instant};
+
+/// The thresholds of a `CircuitBreaker`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct CircuitConfig {
+    /// The number of consecutive failures that opens the circuit (default: 5).
+    pub failure_threshold: u32,
+    /// The period within which the consecutive failures must happen (default: 30 seconds).
+    pub window: Duration,
+    /// How long the circuit stays open before a probe is let through (default: 10 seconds).
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitConfig {
+    fn default() -> Self {
+        CircuitConfig {
+            failure_threshold: 5,
+            window: Duration::from_secs(30),
+            cooldown: Duration::from_secs(10),
+        }
+    }
+}
+
+/// The states of a `CircuitBreaker`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum CircuitState {
+    /// Operations go through, `failures` consecutive failures were seen since `since`.
+    Closed { failures: u32, since: Instant },
+    /// Operations fail fast until the `until` point in time.
+    Open { until: Instant },
+    /// A single probe operation is let through.
+    HalfOpen,
+}
+
+/// A circuit breaker that opens after repeated failures.
+#[derive(Debug, Clone)]
+pub struct CircuitBreaker {
+    config: CircuitConfig,
+    state: CircuitState,
+}
+
+impl CircuitBreaker {
+    /// Create a new closed circuit breaker with the given `config`, starting to count
+    /// from `now`.
+    pub fn new(config: CircuitConfig, now: Instant) -> Self {
+        CircuitBreaker {
+            config: config,
+            state: CircuitState::Closed {
+                failures: 0,
+                since: now,
+            },
+        }
+    }
+
+    /// Return the current state of the circuit.
+    pub fn state(&self) -> CircuitState {
+        self.state
+    }
+
+    /// Check whether an operation may be attempted at the point in time `now`.
+    ///
+    /// On failure the duration left until the circuit turns half-open is returned in
+    /// the `Err` variant.
+    pub fn check(&mut self, now: Instant) -> Result<(), Duration> {
+        if let CircuitState::Open { until } = self.state {
+            if now < until {
+                return Err(until.duration_since(now));
+            }
+
+            debug!("circuit cooldown elapsed, letting a probe through");
+            self.state = CircuitState::HalfOpen;
+        }
+
+        Ok(())
+    }
+
+    /// Record a successful operation, which closes the circuit.
+    pub fn record_success(&mut self, now: Instant) {
+        self.state = CircuitState::Closed {
+            failures: 0,
+            since: now,
+        };
+    }
+
+    /// Record a failed operation at the point in time `now`.
+    pub fn record_failure(&mut self, now: Instant) {
+        let failures = match self.state {
+            CircuitState::Closed { failures, since }
+                if now.duration_since(since) <= self.config.window =>
+            {
+                failures + 1
+            }
+            CircuitState::Closed { .. } => {
+                self.state = CircuitState::Closed {
+                    failures: 1,
+                    since: now,
+                };
+                1
+            }
+            // A failed probe or a late failure keeps the circuit open
+            CircuitState::HalfOpen | CircuitState::Open { .. } => self.config.failure_threshold,
+        };
+
+        if failures >= self.config.failure_threshold {
+            warn!(
+                "circuit opened after {} consecutive failure(s), cooling down for {:?}",
+                failures, self.config.cooldown
+            );
+            self.state = CircuitState::Open {
+                until: now + self.config.cooldown,
+            };
+        } else if let CircuitState::Closed { since, .. } = self.state {
+            self.state = CircuitState::Closed {
+                failures: failures,
+                since: since,
+            };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> CircuitConfig {
+        CircuitConfig {
+            failure_threshold: 2,
+            window: Duration::from_secs(10),
+            cooldown: Duration::from_secs(5),
+        }
+    }
+
+    #[test]
+    fn test_circuit_opens_after_threshold_success() {
+        let now = Instant::now();
+        let mut circuit = CircuitBreaker::new(config(), now);
+
+        assert!(circuit.check(now).is_ok());
+        circuit.record_failure(now);
+        assert!(circuit.check(now).is_ok());
+        circuit.record_failure(now + Duration::from_secs(1));
+
+        let wait = circuit.check(now + Duration::from_secs(2)).unwrap_err();
+        assert_eq!(wait, Duration::from_secs(4));
+    }
+
+    #[test]
+    fn test_circuit_success_resets_failures_success() {
+        let now = Instant::now();
+        let mut circuit = CircuitBreaker::new(config(), now);
+
+        circuit.record_failure(now);
+        circuit.record_success(now);
+        circuit.record_failure(now);
+        assert!(circuit.check(now).is_ok());
+    }
+
+    #[test]
+    fn test_circuit_failures_outside_window_success() {
+        let now = Instant::now();
+        let mut circuit = CircuitBreaker::new(config(), now);
+
+        circuit.record_failure(now);
+        circuit.record_failure(now + Duration::from_secs(11));
+        assert!(circuit.check(now + Duration::from_secs(11)).is_ok());
+    }
+
+    #[test]
+    fn test_circuit_half_open_probe_success() {
+        let now = Instant::now();
+        let mut circuit = CircuitBreaker::new(config(), now);
+        circuit.record_failure(now);
+        circuit.record_failure(now);
+
+        // A failed probe opens the circuit again
+        let later = now + Duration::from_secs(5);
+        assert!(circuit.check(later).is_ok());
+        assert_eq!(circuit.state(), CircuitState::HalfOpen);
+        circuit.record_failure(later);
+        assert!(circuit.check(later).is_err());
+
+        // A successful probe closes it
+        let later = later + Duration::from_secs(5);
+        assert!(circuit.check(later).is_ok());
+        circuit.record_success(later);
+        assert_eq!(
+            circuit.state(),
+            CircuitState::Closed {
+                failures: 0,
+                since: later,
+            }
+        );
+    }
+}
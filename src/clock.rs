@@ -0,0 +1,117 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Pluggable monotonic time for lease computations.
+//!
+//! Drivers read the monotonic clock through a `Clock`, e.g., to compute the lease left
+//! in `remaining`. The `MonotonicClock` reads `Instant::now`, while the `MockClock`
+//! only moves when told to, which lets tests cross lease boundaries exactly and
+//! without real delays.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// The Clock trait abstracts reading the monotonic clock.
+pub trait Clock {
+    /// Return the current point in time.
+    fn now(&self) -> Instant;
+
+    /// Return the time elapsed since `instant`, or zero if `instant` is in the future.
+    fn elapsed(&self, instant: Instant) -> Duration {
+        let now = self.now();
+
+        if now > instant {
+            now - instant
+        } else {
+            Duration::from_secs(0)
+        }
+    }
+}
+
+/// A clock reading `Instant::now`.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that stands still until it is advanced.
+///
+/// Clones share the same time, so a clone can be handed over to a driver while the
+/// original is kept to advance the time.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use dynalock::clock::{Clock, MockClock};
+///
+/// let clock = MockClock::default();
+/// let start = clock.now();
+/// clock.advance(Duration::from_secs(3));
+///
+/// assert_eq!(clock.clone().elapsed(start), Duration::from_secs(3));
+/// ```
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+}
+
+impl MockClock {
+    /// Create a clock standing at the current time.
+    pub fn new() -> Self {
+        MockClock {
+            now: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    /// Move the clock forward by `duration`.
+    pub fn advance(&self, duration: Duration) {
+        *self.now.lock().unwrap() += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        MockClock::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mock_clock_elapsed_success() {
+        let clock = MockClock::new();
+        let start = clock.now();
+        assert_eq!(clock.elapsed(start), Duration::from_secs(0));
+
+        clock.advance(Duration::from_millis(1500));
+        assert_eq!(clock.elapsed(start), Duration::from_millis(1500));
+
+        // Points in time ahead of the clock are not reached yet
+        let ahead = clock.now() + Duration::from_secs(1);
+        assert_eq!(clock.elapsed(ahead), Duration::from_secs(0));
+    }
+}
@@ -0,0 +1,187 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! An executable version of the `Locking` trait contract.
+//!
+//! Provider implementations can call `assert_locking_contract` from their tests to
+//! check that they behave the way the Dynalock algorithm expects. Every lock the
+//! factory returns plays the role of a separate processor contending for the same
+//! resource, so the factory must return locks on the same (initially free) resource
+//! that share the same storage.
+
+use std::thread;
+use std::time::Duration;
+
+use {DynaErrorKind, Locking};
+
+/// Run the standard battery of checks against a `Locking` implementation, panicking on
+/// the first violation.
+///
+/// The following is asserted, in order:
+///
+/// 1. The first `acquire_lock` on a free resource succeeds.
+/// 2. `acquire_lock` by another processor fails with `DynaErrorKind::LockAlreadyAcquired`.
+/// 3. `refresh_lock` by the other processor succeeds, observing the holder's token.
+/// 4. `remaining` of the holder's lease decreases monotonically.
+/// 5. `release_lock` by the holder succeeds, after which a third processor acquires.
+pub fn assert_locking_contract<L, F>(
+    mut new_lock: F,
+    acquire_input: &L::AcquireLockInputType,
+    refresh_input: &L::RefreshLockInputType,
+    release_input: &L::ReleaseLockInputType,
+) where
+    L: Locking,
+    F: FnMut() -> L,
+{
+    let mut holder = new_lock();
+    let acquired = match holder.acquire_lock(acquire_input) {
+        Ok(instant) => instant,
+        Err(err) => panic!("first acquire_lock failed: {}", err),
+    };
+
+    let mut contender = new_lock();
+    match contender.acquire_lock(acquire_input) {
+        Err(ref err) if err.kind() == DynaErrorKind::LockAlreadyAcquired => {}
+        Err(err) => panic!(
+            "contended acquire_lock failed with the wrong error: {}",
+            err
+        ),
+        Ok(_) => panic!("contended acquire_lock succeeded while the lock is held"),
+    }
+
+    if let Err(err) = contender.refresh_lock(refresh_input) {
+        panic!("refresh_lock of a held lock failed: {}", err);
+    }
+
+    let before = holder
+        .remaining(acquired)
+        .expect("remaining returned None right after acquire_lock");
+    thread::sleep(Duration::from_millis(10));
+    let after = holder.remaining(acquired).unwrap_or_default();
+    assert!(
+        after < before,
+        "remaining did not decrease ({:?} then {:?})",
+        before,
+        after
+    );
+
+    if let Err(err) = holder.release_lock(release_input) {
+        panic!("release_lock of a held lock failed: {}", err);
+    }
+
+    let mut successor = new_lock();
+    if let Err(err) = successor.acquire_lock(acquire_input) {
+        panic!("acquire_lock after release_lock failed: {}", err);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+    use std::time::Instant;
+    use {DistLock, DynaError};
+
+    /// A driver keeping the fence token in memory shared by all its clones.
+    #[derive(Debug, Clone, Default)]
+    struct MemoryDriver {
+        stored: Arc<Mutex<Option<u64>>>,
+        next: Arc<Mutex<u64>>,
+        current: Option<u64>,
+    }
+
+    impl Locking for DistLock<MemoryDriver> {
+        type AcquireLockInputType = ();
+        type RefreshLockInputType = ();
+        type ReleaseLockInputType = ();
+
+        fn acquire_lock(&mut self, _input: &()) -> Result<Instant, DynaError> {
+            let mut stored = self.driver.stored.lock().unwrap();
+            if stored.is_some() && *stored != self.driver.current {
+                return Err(DynaError::new(DynaErrorKind::LockAlreadyAcquired, None));
+            }
+
+            let mut next = self.driver.next.lock().unwrap();
+            *next += 1;
+            *stored = Some(*next);
+            self.driver.current = Some(*next);
+
+            Ok(Instant::now())
+        }
+
+        fn refresh_lock(&mut self, _input: &()) -> Result<(), DynaError> {
+            self.driver.current = *self.driver.stored.lock().unwrap();
+            Ok(())
+        }
+
+        fn remaining(&self, instant: Instant) -> Option<Duration> {
+            self.duration.checked_sub(instant.elapsed())
+        }
+
+        fn release_lock(&mut self, _input: &()) -> Result<(), DynaError> {
+            let mut stored = self.driver.stored.lock().unwrap();
+            if *stored == self.driver.current {
+                *stored = None;
+            }
+            self.driver.current = None;
+
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_assert_locking_contract_success() {
+        let driver = MemoryDriver::default();
+
+        assert_locking_contract(
+            || DistLock::new(driver.clone(), Duration::from_secs(10)),
+            &(),
+            &(),
+            &(),
+        );
+    }
+
+    /// A driver violating the contract by never reporting contention.
+    #[derive(Debug)]
+    struct CarelessDriver;
+
+    impl Locking for DistLock<CarelessDriver> {
+        type AcquireLockInputType = ();
+        type RefreshLockInputType = ();
+        type ReleaseLockInputType = ();
+
+        fn acquire_lock(&mut self, _input: &()) -> Result<Instant, DynaError> {
+            Ok(Instant::now())
+        }
+
+        fn refresh_lock(&mut self, _input: &()) -> Result<(), DynaError> {
+            Ok(())
+        }
+
+        fn remaining(&self, instant: Instant) -> Option<Duration> {
+            self.duration.checked_sub(instant.elapsed())
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "contended acquire_lock succeeded")]
+    fn test_assert_locking_contract_fail() {
+        assert_locking_contract(
+            || DistLock::new(CarelessDriver, Duration::from_secs(10)),
+            &(),
+            &(),
+            &(),
+        );
+    }
+}
@@ -27,6 +27,38 @@ pub enum DynaErrorKind {
     ProviderError,
     /// Lock has been acquired by another processor.
     LockAlreadyAcquired,
+    /// Operation was rejected by the provider's client-side rate limiter.
+    RateLimited,
+    /// The provider was given an invalid configuration.
+    InvalidConfiguration,
+    /// The lock item read back after a write did not contain our fence token.
+    WriteVerificationFailed,
+    /// The lock is being drained and can't be acquired.
+    Draining,
+    /// A lock operation was given an invalid input.
+    InvalidInput,
+    /// Operation was rejected by the provider's open circuit breaker.
+    CircuitOpen,
+    /// The lock has moved on to a newer epoch than the one presented.
+    StaleEpoch,
+    /// The lock could not be acquired before giving up.
+    AcquireTimeout,
+    /// The requested lease exceeds the provider's maximum lease.
+    InvalidLease,
+    /// The lock table replicates writes across regions.
+    CrossRegionReplication,
+    /// The stored lease leaves less time than required.
+    LeaseShortened,
+    /// The operation was cancelled by the caller.
+    Cancelled,
+    /// The lock metadata could not be encoded or decoded.
+    InvalidMetadata,
+    /// The fence token presented is not the one the lock currently holds.
+    TokenMismatch,
+    /// The lock item was written by a newer, incompatible version of the lock schema.
+    IncompatibleSchema,
+    /// The provider throttled the operation, retrying it later may succeed.
+    Throttled,
 }
 
 impl DynaErrorKind {
@@ -36,6 +68,22 @@ impl DynaErrorKind {
             DynaErrorKind::UnhandledError => "unhandled internal error",
             DynaErrorKind::ProviderError => "provider error",
             DynaErrorKind::LockAlreadyAcquired => "lock has been acquired by another processor",
+            DynaErrorKind::RateLimited => "operation rate limit exceeded",
+            DynaErrorKind::InvalidConfiguration => "invalid provider configuration",
+            DynaErrorKind::WriteVerificationFailed => "lock write verification failed",
+            DynaErrorKind::Draining => "lock is being drained",
+            DynaErrorKind::InvalidInput => "invalid lock input",
+            DynaErrorKind::CircuitOpen => "circuit breaker is open",
+            DynaErrorKind::StaleEpoch => "lock epoch is stale",
+            DynaErrorKind::AcquireTimeout => "lock acquisition timed out",
+            DynaErrorKind::InvalidLease => "lease exceeds the maximum lease",
+            DynaErrorKind::CrossRegionReplication => "lock table is replicated across regions",
+            DynaErrorKind::LeaseShortened => "lease was shortened below the required time",
+            DynaErrorKind::Cancelled => "operation was cancelled",
+            DynaErrorKind::InvalidMetadata => "lock metadata could not be encoded or decoded",
+            DynaErrorKind::TokenMismatch => "fence token does not match the held lock",
+            DynaErrorKind::IncompatibleSchema => "lock item schema version is incompatible",
+            DynaErrorKind::Throttled => "operation was throttled by the provider",
         }
     }
 }
@@ -48,10 +96,14 @@ impl fmt::Display for DynaErrorKind {
 
 /// The DynaError type is the only error type that can be returned from this
 /// crate's API.
-#[derive(Debug, PartialEq)]
+///
+/// Errors converted from a provider's client errors keep the original error as their
+/// cause, which can be recovered with `downcast_cause`.
+#[derive(Debug)]
 pub struct DynaError {
     kind: DynaErrorKind,
     description: Option<String>,
+    cause: Option<Box<Error + Send + Sync>>,
 }
 
 impl DynaError {
@@ -60,13 +112,40 @@ impl DynaError {
         DynaError {
             kind: kind,
             description: description.map(|desc| desc.to_string()),
+            cause: None,
         }
     }
 
+    /// Attach the error that caused this error.
+    pub fn with_cause<E>(mut self, cause: E) -> Self
+    where
+        E: Error + Send + Sync + 'static,
+    {
+        self.cause = Some(Box::new(cause));
+        self
+    }
+
     /// Get DynaErrorKind for this error.
     pub fn kind(&self) -> DynaErrorKind {
         self.kind
     }
+
+    /// Return the error that caused this error if it is of type `E`.
+    pub fn downcast_cause<E>(&self) -> Option<&E>
+    where
+        E: Error + 'static,
+    {
+        self.cause
+            .as_ref()
+            .and_then(|cause| cause.downcast_ref::<E>())
+    }
+}
+
+/// Errors are equal when their kinds and descriptions are, regardless of their causes.
+impl PartialEq for DynaError {
+    fn eq(&self, other: &DynaError) -> bool {
+        self.kind == other.kind && self.description == other.description
+    }
 }
 
 impl fmt::Display for DynaError {
@@ -82,6 +161,10 @@ impl Error for DynaError {
     fn description(&self) -> &str {
         self.kind.as_str()
     }
+
+    fn cause(&self) -> Option<&Error> {
+        self.cause.as_ref().map(|cause| &**cause as &Error)
+    }
 }
 
 impl From<DynaErrorKind> for DynaError {
@@ -89,6 +172,7 @@ impl From<DynaErrorKind> for DynaError {
         DynaError {
             kind: kind,
             description: None,
+            cause: None,
         }
     }
 }
@@ -108,6 +192,58 @@ mod tests {
             DynaErrorKind::LockAlreadyAcquired.as_str(),
             "lock has been acquired by another processor"
         );
+        assert_eq!(
+            DynaErrorKind::RateLimited.as_str(),
+            "operation rate limit exceeded"
+        );
+        assert_eq!(
+            DynaErrorKind::InvalidConfiguration.as_str(),
+            "invalid provider configuration"
+        );
+        assert_eq!(
+            DynaErrorKind::WriteVerificationFailed.as_str(),
+            "lock write verification failed"
+        );
+        assert_eq!(DynaErrorKind::Draining.as_str(), "lock is being drained");
+        assert_eq!(DynaErrorKind::InvalidInput.as_str(), "invalid lock input");
+        assert_eq!(
+            DynaErrorKind::CircuitOpen.as_str(),
+            "circuit breaker is open"
+        );
+        assert_eq!(DynaErrorKind::StaleEpoch.as_str(), "lock epoch is stale");
+        assert_eq!(
+            DynaErrorKind::AcquireTimeout.as_str(),
+            "lock acquisition timed out"
+        );
+        assert_eq!(
+            DynaErrorKind::InvalidLease.as_str(),
+            "lease exceeds the maximum lease"
+        );
+        assert_eq!(
+            DynaErrorKind::CrossRegionReplication.as_str(),
+            "lock table is replicated across regions"
+        );
+        assert_eq!(
+            DynaErrorKind::LeaseShortened.as_str(),
+            "lease was shortened below the required time"
+        );
+        assert_eq!(DynaErrorKind::Cancelled.as_str(), "operation was cancelled");
+        assert_eq!(
+            DynaErrorKind::InvalidMetadata.as_str(),
+            "lock metadata could not be encoded or decoded"
+        );
+        assert_eq!(
+            DynaErrorKind::TokenMismatch.as_str(),
+            "fence token does not match the held lock"
+        );
+        assert_eq!(
+            DynaErrorKind::IncompatibleSchema.as_str(),
+            "lock item schema version is incompatible"
+        );
+        assert_eq!(
+            DynaErrorKind::Throttled.as_str(),
+            "operation was throttled by the provider"
+        );
     }
 
     #[test]
@@ -119,6 +255,16 @@ mod tests {
         assert_eq!(err.description(), "provider error");
     }
 
+    #[test]
+    fn test_dynaerror_with_cause_success() {
+        let err = DynaError::new(DynaErrorKind::ProviderError, None).with_cause(fmt::Error);
+
+        assert_eq!(err.downcast_cause::<fmt::Error>(), Some(&fmt::Error));
+        assert!(err.downcast_cause::<DynaError>().is_none());
+        assert!(err.cause().is_some());
+        assert_eq!(err, DynaError::new(DynaErrorKind::ProviderError, None));
+    }
+
     #[test]
     fn test_from_dynaerrorkind_to_dynaerror_success() {
         let err = DynaError::new(DynaErrorKind::UnhandledError, None);
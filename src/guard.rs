@@ -0,0 +1,189 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A lock guard releasing the lock when it goes out of scope.
+//!
+//! `DistLock::acquire_guard` returns a `LockGuard` that calls `release_lock` when it
+//! is dropped, e.g., on an early return or while unwinding from a panic. Releasing on
+//! drop is best-effort: errors are logged and the lease is left to expire on its own,
+//! just like it would if the processor crashed. Call `LockGuard::release` to find out
+//! whether the release succeeded.
+
+use std::time::{Duration, Instant};
+
+use {DistLock, DynaError, Locking};
+
+/// A lock acquired by `DistLock::acquire_guard`, released when the guard is dropped.
+pub struct LockGuard<'a, Driver: 'a>
+where
+    DistLock<Driver>: Locking,
+{
+    lock: &'a mut DistLock<Driver>,
+    acquired: Instant,
+    release_input: Option<<DistLock<Driver> as Locking>::ReleaseLockInputType>,
+}
+
+impl<'a, Driver> LockGuard<'a, Driver>
+where
+    DistLock<Driver>: Locking,
+{
+    /// Return the `Instant` the lease of the guarded lock starts at, as returned by
+    /// `acquire_lock`.
+    pub fn acquired(&self) -> Instant {
+        self.acquired
+    }
+
+    /// Return the lease left on the guarded lock, `None` when it expired and the shared
+    /// resource must not be mutated anymore.
+    ///
+    /// Locks without a lease (see `Locking::has_lease`) always report `None` here, whether
+    /// they are still held must be asked from the provider through `lock`, e.g., with
+    /// `is_current_holder` of the DynamoDB driver in generation mode.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.lock.remaining(self.acquired)
+    }
+
+    /// Return a mutable reference to the guarded lock, e.g., to refresh it.
+    pub fn lock(&mut self) -> &mut DistLock<Driver> {
+        self.lock
+    }
+
+    /// Release the lock now and return the result of `release_lock`.
+    ///
+    /// Nothing is attempted anymore when the guard is dropped afterwards.
+    pub fn release(mut self) -> Result<(), DynaError> {
+        match self.release_input.take() {
+            Some(input) => self.lock.release_lock(&input),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<'a, Driver> Drop for LockGuard<'a, Driver>
+where
+    DistLock<Driver>: Locking,
+{
+    fn drop(&mut self) {
+        if let Some(input) = self.release_input.take() {
+            if let Err(err) = self.lock.release_lock(&input) {
+                warn!(
+                    "{} lock release on drop failed, leaving the lease to expire: {}",
+                    self.lock.provider_name(),
+                    err
+                );
+            }
+        }
+    }
+}
+
+impl<Driver> DistLock<Driver>
+where
+    DistLock<Driver>: Locking,
+{
+    /// Acquire the lock and return a `LockGuard` that releases it with `release_input`
+    /// when dropped.
+    ///
+    /// The guard borrows the lock for as long as it is held. Releasing on drop is
+    /// best-effort: a failed release is logged and the lease is left to expire
+    /// naturally, so other processors can only acquire the lock once the lease ends.
+    pub fn acquire_guard<'a>(
+        &'a mut self,
+        input: &<Self as Locking>::AcquireLockInputType,
+        release_input: <Self as Locking>::ReleaseLockInputType,
+    ) -> Result<LockGuard<'a, Driver>, DynaError> {
+        let acquired = self.acquire_lock(input)?;
+
+        Ok(LockGuard::<Driver> {
+            lock: self,
+            acquired: acquired,
+            release_input: Some(release_input),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DynaErrorKind;
+
+    /// A driver that counts releases and fails them on demand.
+    #[derive(Debug, Default)]
+    struct ReleaseCountingDriver {
+        released: u32,
+        fail_release: bool,
+    }
+
+    impl Locking for DistLock<ReleaseCountingDriver> {
+        type AcquireLockInputType = ();
+        type RefreshLockInputType = ();
+        type ReleaseLockInputType = ();
+
+        fn acquire_lock(&mut self, _input: &()) -> Result<Instant, DynaError> {
+            Ok(Instant::now())
+        }
+
+        fn refresh_lock(&mut self, _input: &()) -> Result<(), DynaError> {
+            Ok(())
+        }
+
+        fn remaining(&self, instant: Instant) -> Option<Duration> {
+            self.duration.checked_sub(instant.elapsed())
+        }
+
+        fn release_lock(&mut self, _input: &()) -> Result<(), DynaError> {
+            self.driver.released += 1;
+
+            if self.driver.fail_release {
+                return Err(DynaError::new(DynaErrorKind::ProviderError, None));
+            }
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_lock_guard_releases_on_drop_success() {
+        let mut lock = DistLock::new(ReleaseCountingDriver::default(), Duration::from_secs(10));
+
+        {
+            let guard = lock.acquire_guard(&(), ()).unwrap();
+            assert!(guard.remaining().unwrap() <= Duration::from_secs(10));
+        }
+
+        assert_eq!(lock.driver.released, 1);
+    }
+
+    #[test]
+    fn test_lock_guard_release_once_success() {
+        let mut lock = DistLock::new(ReleaseCountingDriver::default(), Duration::from_secs(10));
+
+        let guard = lock.acquire_guard(&(), ()).unwrap();
+        assert_eq!(guard.release(), Ok(()));
+
+        assert_eq!(lock.driver.released, 1);
+    }
+
+    #[test]
+    fn test_lock_guard_release_on_drop_fail() {
+        let driver = ReleaseCountingDriver {
+            fail_release: true,
+            ..Default::default()
+        };
+        let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+        // The failed release is only logged
+        drop(lock.acquire_guard(&(), ()).unwrap());
+
+        assert_eq!(lock.driver.released, 1);
+    }
+}
@@ -35,23 +35,55 @@ extern crate core;
 #[macro_use]
 extern crate log;
 
+#[cfg(feature = "dynamodb")]
 #[macro_use]
 extern crate maplit;
 
+#[cfg(feature = "iso8601")]
+extern crate chrono;
+
+#[cfg(feature = "gzip")]
+extern crate flate2;
+
+#[cfg(feature = "maintained")]
+extern crate futures;
+
+#[cfg(all(feature = "dynamodb", any(test, feature = "test-util")))]
+extern crate serde_json;
+
+#[cfg(feature = "serde")]
+#[macro_use]
+extern crate serde;
+
 #[cfg(feature = "dynamodb")]
 pub extern crate rusoto_core;
 #[cfg(feature = "dynamodb")]
 pub extern crate rusoto_dynamodb;
 #[cfg(feature = "dynamodb")]
+extern crate rand;
+#[cfg(feature = "dynamodb")]
 extern crate uuid;
 
+pub mod circuit;
+pub mod clock;
+pub mod contract;
 pub mod error;
+pub mod guard;
+#[cfg(feature = "maintained")]
+pub mod maintained;
 pub mod providers;
+pub mod ratelimit;
+pub mod refresher;
+pub mod retry;
+pub mod sleep;
+pub mod stats;
 
 pub use error::{DynaError, DynaErrorKind};
 pub use providers::*;
 
-use std::time::{Duration, Instant};
+use retry::RetryBudget;
+
+use std::time::{Duration, Instant, SystemTime};
 
 /// The Locking trait provides a contractual API that providers implement the Dynalock
 /// algorithm using the particular provider's primitives.
@@ -97,6 +129,91 @@ pub trait Locking {
     fn release_lock(&mut self, _input: &Self::ReleaseLockInputType) -> Result<(), DynaError> {
         Ok(())
     }
+
+    /// Return the fence token known from the last `acquire_lock` or `refresh_lock` call,
+    /// or `None` when no token is known and the lock looks free.
+    ///
+    /// Helpers waiting for another processor's lease (e.g., `acquire_blocking`) rely on
+    /// this method to tell whether the holder renewed its lease: only a token observed
+    /// unchanged for a whole lease duration is safe to condition an acquisition on.
+    /// Providers should override this method, with the default implementation returning
+    /// `None` such helpers retry right away instead of waiting leases out.
+    fn observed_token(&self) -> Option<String> {
+        None
+    }
+
+    /// Return whether the lock is held for a lease measured with a clock, the one
+    /// `remaining` reports on.
+    ///
+    /// Providers that don't trust any clock (e.g., the DynamoDB driver in generation mode)
+    /// return `false`, in which case `remaining` always returns `None` and only the
+    /// provider can tell whether the lock is still held. Helpers built on `remaining`,
+    /// e.g., `spawn_refresher`, fall back to the result of the last renewal for such
+    /// locks. The default implementation returns `true`.
+    fn has_lease(&self) -> bool {
+        true
+    }
+
+    /// Return the number of other processors the last `refresh_lock` call observed waiting
+    /// for the lock, e.g., through the lock intents of the DynamoDB provider.
+    ///
+    /// The retrying helpers (e.g., `acquire_blocking`) spread their polls over that many
+    /// more poll intervals, as described in the `retry` module. The default
+    /// implementation returns 0, which leaves the poll intervals as they are.
+    fn observed_contenders(&self) -> u32 {
+        0
+    }
+
+    /// Return a short name of the provider (e.g., "dynamodb"), meant to label logs and
+    /// metrics when a process uses several providers. Providers should override this
+    /// method, the default implementation returns "unknown".
+    fn provider_name(&self) -> &'static str {
+        "unknown"
+    }
+}
+
+/// The states of a lock from the point of view of a single processor, as described by
+/// the Dynalock algorithm.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LockState {
+    /// No fence token is known, as far as we know the lock is free.
+    Unlocked,
+    /// We acquired the lock and its lease is still valid.
+    Held,
+    /// We know the fence token of another processor (e.g., through `refresh_lock`)
+    /// but we do not hold the lock.
+    Observed,
+    /// We acquired the lock but its lease has expired, the shared resource must not
+    /// be mutated anymore.
+    Expired,
+}
+
+/// A point-in-time view of a lock, meant for debugging dumps and postmortems.
+///
+/// Providers build snapshots from their driver state. With the `serde` feature the
+/// structure implements `Serialize`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct LockSnapshot {
+    /// The name of the provider (e.g., "dynamodb").
+    pub provider: &'static str,
+    /// The key of the shared resource.
+    pub resource: String,
+    /// The fence token we know of, if any.
+    pub token: Option<String>,
+    /// Whether we wrote the fence token, i.e., acquired the lock.
+    pub owned: bool,
+    /// The configured lease duration in milliseconds.
+    pub lease_millis: u64,
+    /// The lease left in milliseconds, None when unknown or expired.
+    pub remaining_millis: Option<u64>,
+    /// The number of in-place renewals of the current lease.
+    pub renew_count: u32,
+}
+
+/// Return `duration` in whole milliseconds.
+pub(crate) fn as_millis(duration: Duration) -> u64 {
+    duration.as_secs() * 1000 + u64::from(duration.subsec_nanos() / 1_000_000)
 }
 
 /// The distributed lock structure that holds all the internal lock state and information.
@@ -121,6 +238,7 @@ pub trait Locking {
 pub struct DistLock<Driver> {
     driver: Driver,
     duration: Duration,
+    retry_budget: Option<RetryBudget>,
 }
 
 impl<Driver> DistLock<Driver> {
@@ -132,6 +250,7 @@ impl<Driver> DistLock<Driver> {
         DistLock {
             driver: driver,
             duration: duration,
+            retry_budget: None,
         }
     }
 
@@ -144,12 +263,124 @@ impl<Driver> DistLock<Driver> {
     pub fn duration(&self) -> Duration {
         self.duration
     }
+
+    /// Return the wall-clock time at which the lease acquired at `acquired` ends.
+    ///
+    /// The `Instant` returned by `acquire_lock` is monotonic and meaningless outside of
+    /// the current process. This method translates it into a `SystemTime` that can be
+    /// logged or handed over to other processes, computed as
+    /// `SystemTime::now() - acquired.elapsed() + duration`. Both clocks are read right
+    /// after one another, but the result is still off by the time between the two reads
+    /// and subject to wall-clock adjustments, so it must not be used for safety
+    /// decisions. Use `remaining` for those. The result is in the past once the lease
+    /// expired, see `expires_at_or_now` to clamp it. For locks without a lease (see
+    /// `Locking::has_lease`) the result is only a hint of when a renewal is due.
+    pub fn expires_at(&self, acquired: Instant) -> SystemTime {
+        let elapsed = acquired.elapsed();
+        let now = SystemTime::now();
+
+        now - elapsed + self.duration
+    }
+
+    /// Return the wall-clock time at which the lease acquired at `acquired` ends, or the
+    /// current time if the lease already expired.
+    ///
+    /// The same caveats as for `expires_at` apply.
+    pub fn expires_at_or_now(&self, acquired: Instant) -> SystemTime {
+        let elapsed = acquired.elapsed();
+        let now = SystemTime::now();
+
+        match self.duration.checked_sub(elapsed) {
+            Some(left) => now + left,
+            None => now,
+        }
+    }
+
+    /// Return the wall-clock deadline of the lease acquired at `acquired`, e.g., to hand
+    /// the lock state over to another process.
+    ///
+    /// This is `expires_at`: the deadline is computed as
+    /// `SystemTime::now() - acquired.elapsed() + duration`, and reading the two clocks one
+    /// after another makes it off by the time between both reads.
+    pub fn deadline_systemtime(&self, acquired: Instant) -> SystemTime {
+        self.expires_at(acquired)
+    }
+
+    /// Return the latest point in time at which the lease acquired at `acquired` should be
+    /// renewed.
+    ///
+    /// This is `acquired + duration - margin - lead`, where `margin` is the safety margin
+    /// kept before the lease ends and `lead` is the time a renewal is expected to take.
+    /// A timer-driven renewer can fire at exactly this instant instead of polling
+    /// `remaining`. The result is clamped to `acquired` when the lease is shorter than
+    /// `margin + lead`, in which case the lease should be renewed right away.
+    pub fn next_renew_by(&self, acquired: Instant, lead: Duration, margin: Duration) -> Instant {
+        let slack = margin.checked_add(lead);
+
+        match slack.and_then(|slack| self.duration.checked_sub(slack)) {
+            Some(window) => acquired + window,
+            None => acquired,
+        }
+    }
+}
+
+impl<Driver> DistLock<Driver>
+where
+    DistLock<Driver>: Locking,
+{
+    /// Try to acquire the lock only if `predicate` returns `true`.
+    ///
+    /// The predicate is evaluated before any call is made to the provider. If it
+    /// returns `false` no acquisition is attempted and `Ok(None)` is returned, which
+    /// saves a wasted write when a cheap local check already tells us the work
+    /// protected by the lock should not run.
+    pub fn acquire_if<F>(
+        &mut self,
+        input: &<Self as Locking>::AcquireLockInputType,
+        predicate: F,
+    ) -> Result<Option<Instant>, DynaError>
+    where
+        F: Fn() -> bool,
+    {
+        if !predicate() {
+            debug!("acquire predicate returned false, skipping lock acquisition");
+            return Ok(None);
+        }
+
+        self.acquire_lock(input).map(Some)
+    }
+
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    /// A driver that counts the calls made to it and never fails.
+    #[derive(Debug, Default)]
+    struct CountingDriver {
+        acquired: u32,
+    }
+
+    impl Locking for DistLock<CountingDriver> {
+        type AcquireLockInputType = ();
+        type RefreshLockInputType = ();
+        type ReleaseLockInputType = ();
+
+        fn acquire_lock(&mut self, _input: &()) -> Result<Instant, DynaError> {
+            self.driver.acquired += 1;
+            Ok(Instant::now())
+        }
+
+        fn refresh_lock(&mut self, _input: &()) -> Result<(), DynaError> {
+            Ok(())
+        }
+
+        fn remaining(&self, instant: Instant) -> Option<Duration> {
+            self.duration.checked_sub(instant.elapsed())
+        }
+    }
+
     #[test]
     fn test_distlock_new_success() {
         let mut lock = DistLock::new("test driver", Duration::from_secs(10));
@@ -158,4 +389,75 @@ mod tests {
         assert_eq!(*lock.driver(), "test driver");
         assert_eq!(lock.duration(), Duration::from_secs(10));
     }
+
+    #[test]
+    fn test_locking_provider_name_default_success() {
+        let lock = DistLock::new(CountingDriver::default(), Duration::from_secs(10));
+        assert_eq!(lock.provider_name(), "unknown");
+    }
+
+    #[test]
+    fn test_distlock_acquire_if_skips_when_predicate_false_success() {
+        let mut lock = DistLock::new(CountingDriver::default(), Duration::from_secs(10));
+
+        assert_eq!(lock.acquire_if(&(), || false), Ok(None));
+        assert_eq!(lock.driver.acquired, 0);
+
+        assert!(lock.acquire_if(&(), || true).unwrap().is_some());
+        assert_eq!(lock.driver.acquired, 1);
+    }
+
+    #[test]
+    fn test_distlock_deadline_systemtime_success() {
+        let lock = DistLock::new("test driver", Duration::from_secs(10));
+        let acquired = Instant::now() - Duration::from_secs(4);
+
+        let deadline = lock.deadline_systemtime(acquired);
+        let left = deadline.duration_since(SystemTime::now()).unwrap();
+
+        assert!(left <= Duration::from_secs(6));
+        assert!(left > Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_distlock_expires_at_success() {
+        let lock = DistLock::new("test driver", Duration::from_secs(10));
+        let acquired = Instant::now() - Duration::from_secs(4);
+
+        let left = lock.expires_at(acquired).duration_since(SystemTime::now()).unwrap();
+        assert!(left <= Duration::from_secs(6));
+        assert!(left > Duration::from_secs(5));
+
+        let left = lock.expires_at_or_now(acquired).duration_since(SystemTime::now()).unwrap();
+        assert!(left <= Duration::from_secs(6));
+        assert!(left > Duration::from_secs(5));
+    }
+
+    #[test]
+    fn test_distlock_expires_at_expired_success() {
+        let lock = DistLock::new("test driver", Duration::from_secs(1));
+        let acquired = Instant::now() - Duration::from_secs(3);
+
+        // The expiry of an expired lease is in the past
+        let before = SystemTime::now();
+        assert!(lock.expires_at(acquired) < before);
+
+        // Unless it is clamped to the current time
+        let clamped = lock.expires_at_or_now(acquired);
+        assert!(clamped >= before);
+        assert!(clamped <= SystemTime::now());
+    }
+
+    #[test]
+    fn test_distlock_next_renew_by_success() {
+        let lock = DistLock::new("test driver", Duration::from_secs(10));
+        let acquired = Instant::now();
+
+        let renew_by = lock.next_renew_by(acquired, Duration::from_secs(2), Duration::from_secs(1));
+        assert_eq!(renew_by, acquired + Duration::from_secs(7));
+
+        // Leases shorter than the lead and margin are due right away
+        let renew_by = lock.next_renew_by(acquired, Duration::from_secs(8), Duration::from_secs(3));
+        assert_eq!(renew_by, acquired);
+    }
 }
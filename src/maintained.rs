@@ -0,0 +1,217 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A held lock renewed in the background, telling the moment its lease is lost.
+//!
+//! `DistLock::run_maintained` hands a held lock over to a refresher, like
+//! `spawn_refresher` does, and returns a `MaintainedLock` that is alive while the lock
+//! is held along with a `LeaseLost` future resolving the moment it isn't anymore. This
+//! suits leader election, where the leader must step down as soon as its lease is lost.
+//!
+//! The future is a futures 0.1 `Future`, like those of rusoto, which any executor
+//! (e.g., tokio) can drive. This module requires the `maintained` feature.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::sync::oneshot::{self, Canceled, Receiver};
+use futures::{Future, Poll};
+
+use refresher::{LeaseLoss, RefreshHandle};
+use {DistLock, DynaError, Locking};
+
+/// A lock renewed in the background by `DistLock::run_maintained`.
+pub struct MaintainedLock<Driver> {
+    handle: RefreshHandle<Driver>,
+}
+
+impl<Driver> MaintainedLock<Driver>
+where
+    DistLock<Driver>: Locking,
+{
+    /// Return true while the lease of the lock is valid.
+    pub fn is_alive(&self) -> bool {
+        self.handle.is_valid()
+    }
+
+    /// Return the fence token of the last renewal, `None` before the first one completed.
+    pub fn token(&self) -> Option<String> {
+        self.handle.token()
+    }
+
+    /// Return the handle on the refresher renewing the lease, e.g., to pause renewals.
+    pub fn handle(&self) -> &RefreshHandle<Driver> {
+        &self.handle
+    }
+
+    /// Stop renewing the lease and release the lock with `input`.
+    ///
+    /// The `LeaseLost` future fails with `Canceled` from then on, unless the lease was
+    /// lost before.
+    pub fn stop(
+        self,
+        input: &<DistLock<Driver> as Locking>::ReleaseLockInputType,
+    ) -> Result<(), DynaError> {
+        let mut lock = self.handle.stop();
+        lock.release_lock(input)
+    }
+}
+
+/// A future resolving with the `LeaseLoss` of a `MaintainedLock` the moment its lease
+/// is lost.
+///
+/// It fails with `Canceled` once the lock was stopped without losing its lease.
+#[derive(Debug)]
+pub struct LeaseLost {
+    receiver: Receiver<LeaseLoss>,
+}
+
+impl Future for LeaseLost {
+    type Item = LeaseLoss;
+    type Error = Canceled;
+
+    fn poll(&mut self) -> Poll<LeaseLoss, Canceled> {
+        self.receiver.poll()
+    }
+}
+
+impl<Driver> DistLock<Driver>
+where
+    DistLock<Driver>: Locking,
+    Driver: Send + 'static,
+    <DistLock<Driver> as Locking>::AcquireLockInputType: Send + 'static,
+{
+    /// Move the held lock to a background thread renewing its lease with `acquire_lock`
+    /// right away and then every `interval`, and return a `MaintainedLock` along with a
+    /// `LeaseLost` future.
+    ///
+    /// Renewals happen like those of `spawn_refresher`, and the lock is reported alive
+    /// from the start. The future resolves with `LeaseLoss::TakenOver` as soon as a
+    /// renewal finds the lock taken over by another processor, which stops the renewals,
+    /// or with `LeaseLoss::Expired` once the lease of the last renewal ends, e.g., while
+    /// renewals keep failing. A later renewal may still succeed if no other processor
+    /// took the lock over in the meantime, but the shared resource wasn't protected in
+    /// between. Locks without a lease (see `Locking::has_lease`) are only ever reported
+    /// taken over.
+    pub fn run_maintained(
+        self,
+        input: <Self as Locking>::AcquireLockInputType,
+        interval: Duration,
+    ) -> (MaintainedLock<Driver>, LeaseLost) {
+        let (sender, receiver) = oneshot::channel();
+        let mut sender = Some(sender);
+
+        let handle = self.spawn_refresher_thread(
+            input,
+            interval,
+            Arc::new(AtomicBool::new(true)),
+            None::<fn(Instant)>,
+            Some(move |loss: LeaseLoss| {
+                // Only the first loss is reported, and the future may be gone already
+                if let Some(sender) = sender.take() {
+                    let _ = sender.send(loss);
+                }
+            }),
+        );
+
+        (
+            MaintainedLock { handle: handle },
+            LeaseLost { receiver: receiver },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::MockClock;
+    use providers::mock::{MockDriver, MockStore};
+    use std::thread;
+    use DynaErrorKind;
+
+    #[test]
+    fn test_run_maintained_taken_over_fail() {
+        let store = MockStore::default();
+        let clock = MockClock::new();
+        let mut lock = DistLock::new(
+            MockDriver::with_store(store.clone(), "leader").with_clock(clock.clone()),
+            Duration::from_secs(10),
+        );
+        let mut contender = DistLock::new(
+            MockDriver::with_store(store.clone(), "leader").with_clock(clock.clone()),
+            Duration::from_secs(10),
+        );
+
+        assert!(lock.acquire_lock(&()).is_ok());
+        let (maintained, lost) = lock.run_maintained((), Duration::from_millis(20));
+        assert!(maintained.is_alive());
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(
+            maintained.token(),
+            store.token("leader").map(|t| t.to_string())
+        );
+
+        // The contender takes the lock over between two renewals
+        clock.advance(Duration::from_secs(11));
+        assert!(contender.acquire_lock(&()).is_ok());
+
+        assert_eq!(lost.wait(), Ok(LeaseLoss::TakenOver));
+        assert!(!maintained.is_alive());
+        assert_eq!(
+            maintained.stop(&()).unwrap_err().kind(),
+            DynaErrorKind::LockAlreadyAcquired
+        );
+    }
+
+    #[test]
+    fn test_run_maintained_expired_fail() {
+        let clock = MockClock::new();
+        let mut lock = DistLock::new(
+            MockDriver::new().with_clock(clock.clone()),
+            Duration::from_secs(10),
+        );
+
+        assert!(lock.acquire_lock(&()).is_ok());
+        let (maintained, lost) = lock.run_maintained((), Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(20));
+
+        // No renewal happens while paused, so the lease runs out
+        maintained.handle().pause();
+        thread::sleep(Duration::from_millis(20));
+        clock.advance(Duration::from_secs(11));
+
+        assert_eq!(lost.wait(), Ok(LeaseLoss::Expired));
+        assert!(!maintained.is_alive());
+        assert!(maintained.stop(&()).is_ok());
+    }
+
+    #[test]
+    fn test_run_maintained_stop_releases_success() {
+        let store = MockStore::default();
+        let mut lock = DistLock::new(
+            MockDriver::with_store(store.clone(), "leader"),
+            Duration::from_secs(10),
+        );
+
+        assert!(lock.acquire_lock(&()).is_ok());
+        let (maintained, lost) = lock.run_maintained((), Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(30));
+        assert!(maintained.is_alive());
+
+        assert!(maintained.stop(&()).is_ok());
+        assert_eq!(store.token("leader"), None);
+        assert_eq!(lost.wait(), Err(Canceled));
+    }
+}
@@ -0,0 +1,146 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Optional encoding of the metadata blob stored on lock items.
+//!
+//! A driver configured with a `MetadataCodec` writes the metadata blob as a single
+//! byte identifying the codec followed by the encoded metadata, and decodes it again
+//! when reading the lock item. Blobs prefixed with `RAW_CODEC_ID` are always readable,
+//! so drivers can switch codecs without losing track of items written before.
+//!
+//! Drivers without a codec keep writing and reading the blob as-is. A blob with an
+//! unknown prefix, e.g., one written by such a driver, is read as-is as well.
+
+#[cfg(feature = "gzip")]
+use std::io::{Read, Write};
+
+#[cfg(feature = "gzip")]
+use flate2::read::GzDecoder;
+#[cfg(feature = "gzip")]
+use flate2::write::GzEncoder;
+#[cfg(feature = "gzip")]
+use flate2::Compression;
+
+use {DynaError, DynaErrorKind};
+
+/// The identifier of blobs stored without encoding.
+pub const RAW_CODEC_ID: u8 = 0;
+
+/// The identifier of blobs compressed by the `GzipCodec`.
+pub const GZIP_CODEC_ID: u8 = 1;
+
+/// The MetadataCodec trait encodes metadata before it is written on the lock item and
+/// decodes it after it is read back.
+pub trait MetadataCodec {
+    /// Return the identifier prefixed to blobs encoded with this codec. It must be
+    /// unique among the codecs used with a table and must not be `RAW_CODEC_ID`.
+    fn id(&self) -> u8;
+
+    /// Encode `metadata` for storage.
+    fn encode(&self, metadata: &[u8]) -> Result<Vec<u8>, DynaError>;
+
+    /// Decode a `blob` returned by `encode`.
+    fn decode(&self, blob: &[u8]) -> Result<Vec<u8>, DynaError>;
+}
+
+/// A codec storing metadata as-is, behind the `RAW_CODEC_ID` prefix.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct RawCodec;
+
+impl MetadataCodec for RawCodec {
+    fn id(&self) -> u8 {
+        RAW_CODEC_ID
+    }
+
+    fn encode(&self, metadata: &[u8]) -> Result<Vec<u8>, DynaError> {
+        Ok(metadata.to_vec())
+    }
+
+    fn decode(&self, blob: &[u8]) -> Result<Vec<u8>, DynaError> {
+        Ok(blob.to_vec())
+    }
+}
+
+/// A codec compressing metadata with gzip.
+#[cfg(feature = "gzip")]
+#[derive(Debug, Copy, Clone)]
+pub struct GzipCodec {
+    level: u32,
+}
+
+#[cfg(feature = "gzip")]
+impl GzipCodec {
+    /// Create a codec compressing at `level`, from 0 (none) to 9 (best).
+    pub fn new(level: u32) -> Self {
+        GzipCodec { level: level }
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl Default for GzipCodec {
+    fn default() -> Self {
+        GzipCodec::new(6)
+    }
+}
+
+#[cfg(feature = "gzip")]
+impl MetadataCodec for GzipCodec {
+    fn id(&self) -> u8 {
+        GZIP_CODEC_ID
+    }
+
+    fn encode(&self, metadata: &[u8]) -> Result<Vec<u8>, DynaError> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::new(self.level));
+        encoder
+            .write_all(metadata)
+            .and_then(|_| encoder.finish())
+            .map_err(|err| DynaError::new(DynaErrorKind::InvalidMetadata, Some(&err.to_string())))
+    }
+
+    fn decode(&self, blob: &[u8]) -> Result<Vec<u8>, DynaError> {
+        let mut metadata = Vec::new();
+        GzDecoder::new(blob)
+            .read_to_end(&mut metadata)
+            .map_err(|err| {
+                DynaError::new(DynaErrorKind::InvalidMetadata, Some(&err.to_string()))
+            })?;
+
+        Ok(metadata)
+    }
+}
+
+/// Encode `metadata` with `codec` and prefix it with the codec identifier.
+pub fn encode_metadata(codec: &MetadataCodec, metadata: &[u8]) -> Result<Vec<u8>, DynaError> {
+    let encoded = codec.encode(metadata)?;
+
+    let mut blob = Vec::with_capacity(encoded.len() + 1);
+    blob.push(codec.id());
+    blob.extend_from_slice(&encoded);
+
+    Ok(blob)
+}
+
+/// Decode a `blob` written by `encode_metadata` with either `codec` or `RawCodec`.
+///
+/// A `DynaErrorKind::InvalidMetadata` error is returned for blobs with any other prefix.
+pub fn decode_metadata(codec: &MetadataCodec, blob: &[u8]) -> Result<Vec<u8>, DynaError> {
+    match blob.first() {
+        Some(&RAW_CODEC_ID) => RawCodec.decode(&blob[1..]),
+        Some(&id) if id == codec.id() => codec.decode(&blob[1..]),
+        _ => Err(DynaError::new(
+            DynaErrorKind::InvalidMetadata,
+            Some("unknown metadata codec"),
+        )),
+    }
+}
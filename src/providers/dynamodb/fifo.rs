@@ -0,0 +1,310 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Best-effort FIFO acquisition of DynamoDB locks.
+//!
+//! Waiters append an entry to a list attribute on the lock item and only try to
+//! acquire the lock once every entry ahead of them is gone or presumed dead. Each
+//! entry carries the time its waiter was last seen, which the waiter refreshes on
+//! every poll, and an entry is presumed dead when it was last seen more than a lease
+//! duration ago. A waiter can thus wait as long as it takes, provided it polls more
+//! often than once per lease duration. This reduces starvation under heavy contention
+//! but is not a fairness guarantee: a processor calling `acquire_lock` directly
+//! bypasses the queue.
+
+use std::time::{Duration, Instant, UNIX_EPOCH};
+use uuid::Uuid;
+
+use rusoto_core::{DispatchSignedRequest, ProvideAwsCredentials};
+use rusoto_dynamodb::{AttributeValue, DynamoDb, UpdateItemInput};
+
+use super::{expressions, DynamoDbDriver, DynamoDbLockInput};
+use {DistLock, DynaError, DynaErrorKind, Locking};
+
+impl<P, D> DistLock<DynamoDbDriver<P, D>>
+where
+    P: ProvideAwsCredentials + 'static,
+    D: DispatchSignedRequest + 'static,
+{
+    /// Try to acquire the lock in the order waiters queued up for it.
+    ///
+    /// The first call queues us on the lock item's waiters list. Every call then reads
+    /// the lock item, refreshes the last seen time of our entry and only attempts
+    /// `acquire_lock` when we are at the head of the queue, otherwise it fails with
+    /// `DynaErrorKind::LockAlreadyAcquired` and should be retried like `acquire_lock`,
+    /// more often than once per lease duration. Once acquired we are removed from the
+    /// queue along with the dead entries ahead of us.
+    pub fn acquire_fifo(&mut self, input: &DynamoDbLockInput) -> Result<Instant, DynaError> {
+        let now_secs = input
+            .system_time_or_now()
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+
+        if self.driver.waiter_entry.is_none() {
+            let entry = format!("{}@{}", Uuid::new_v4().simple(), now_secs);
+            self.driver.enqueue_waiter(&entry, input.timeout)?;
+            self.driver.waiter_entry = Some(entry);
+        }
+        let entry = self.driver.waiter_entry.clone().unwrap_or_default();
+
+        let mut waiters = self
+            .driver
+            .get_lock_item(Some(true), input.timeout)?
+            .and_then(|mut item| item.remove(&self.driver.waiters_field_name))
+            .and_then(|attr| attr.l)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|attr| attr.s)
+            .collect::<Vec<String>>();
+
+        // Look our entry up by waiter id, its last seen time may be newer than we know of
+        let index = match waiters
+            .iter()
+            .position(|waiter| waiter_id(waiter) == waiter_id(&entry))
+        {
+            Some(index) => index,
+            None => {
+                // Somebody removed our entry, queue up again on the next call
+                warn!(
+                    "lock '{}' waiter ({}) was dropped from the queue",
+                    self.driver.partition_key_value, entry
+                );
+                self.driver.waiter_entry = None;
+                return Err(DynaError::new(
+                    DynaErrorKind::LockAlreadyAcquired,
+                    Some("waiter was dropped from the queue"),
+                ));
+            }
+        };
+        let mut entry = waiters[index].clone();
+
+        // Let the waiters behind us know we're still alive
+        if last_seen(&entry).map_or(true, |seen| seen < now_secs) {
+            let seen = format!("{}@{}", waiter_id(&entry), now_secs);
+            match self
+                .driver
+                .touch_waiter(index, &entry, &seen, input.timeout)
+            {
+                Ok(()) => {
+                    waiters[index] = seen.clone();
+                    entry = seen;
+                }
+                // The queue moved since we read it, the next call tries again
+                Err(ref err) if err.kind() == DynaErrorKind::LockAlreadyAcquired => {}
+                Err(err) => return Err(err),
+            }
+        }
+        self.driver.waiter_entry = Some(entry.clone());
+
+        let position = match fifo_position(&waiters, &entry, now_secs, self.duration) {
+            Some(position) => position,
+            None => {
+                debug!(
+                    "lock '{}' waiter ({}) is not at the head of the queue",
+                    self.driver.partition_key_value, entry
+                );
+                return Err(DynaError::new(
+                    DynaErrorKind::LockAlreadyAcquired,
+                    Some("waiting for earlier waiters"),
+                ));
+            }
+        };
+
+        let instant = self.acquire_lock(input)?;
+
+        // Leaving the queue is best effort, a leftover entry is eventually presumed dead
+        if let Err(err) = self
+            .driver
+            .dequeue_waiter(&waiters[..position + 1], input.timeout)
+        {
+            warn!(
+                "lock '{}' waiter ({}) failed to leave the queue: {}",
+                self.driver.partition_key_value, entry, err
+            );
+        }
+        self.driver.waiter_entry = None;
+
+        Ok(instant)
+    }
+}
+
+impl<P, D> DynamoDbDriver<P, D>
+where
+    P: ProvideAwsCredentials,
+    D: DispatchSignedRequest,
+{
+    /// Append `entry` to the waiters list of the lock item.
+    fn enqueue_waiter(&mut self, entry: &str, timeout: Duration) -> Result<(), DynaError> {
+        self.throttle()?;
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.table_name.clone(),
+            update_expression: Some(String::from(expressions::FIFO_ENQUEUE_UPDATE)),
+            expression_attribute_names: Some(hashmap! {
+                String::from("#waiters_field") => self.waiters_field_name.clone(),
+            }),
+            expression_attribute_values: Some(hashmap! {
+                String::from(":empty_list") => AttributeValue { l: Some(vec![]), ..Default::default() },
+                String::from(":waiter") => AttributeValue { l: Some(vec![waiter_value(entry)]), ..Default::default() },
+            }),
+            key: self.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .client
+            .update_item(&update_input)
+            .with_timeout(timeout)
+            .sync()
+            .map_err(DynaError::from);
+        self.record(result)?;
+
+        Ok(())
+    }
+
+    /// Replace `entry` at `position` of the waiters list with `seen`, the same waiter
+    /// with a newer last seen time.
+    ///
+    /// The replacement is conditional on `entry` still being at `position`.
+    fn touch_waiter(
+        &mut self,
+        position: usize,
+        entry: &str,
+        seen: &str,
+        timeout: Duration,
+    ) -> Result<(), DynaError> {
+        self.throttle()?;
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.table_name.clone(),
+            update_expression: Some(format!("SET #waiters_field[{}] = :seen", position)),
+            condition_expression: Some(format!("#waiters_field[{}] = :waiter", position)),
+            expression_attribute_names: Some(hashmap! {
+                String::from("#waiters_field") => self.waiters_field_name.clone(),
+            }),
+            expression_attribute_values: Some(hashmap! {
+                String::from(":waiter") => waiter_value(entry),
+                String::from(":seen") => waiter_value(seen),
+            }),
+            key: self.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .client
+            .update_item(&update_input)
+            .with_timeout(timeout)
+            .sync()
+            .map_err(DynaError::from);
+        self.record(result)?;
+
+        Ok(())
+    }
+
+    /// Remove the leading `entries` of the waiters list, our own entry last.
+    ///
+    /// The removal is conditional on the list still starting with `entries`, so that a
+    /// waiter ahead of us refreshing its last seen time in the meantime keeps its entry.
+    fn dequeue_waiter(&mut self, entries: &[String], timeout: Duration) -> Result<(), DynaError> {
+        self.throttle()?;
+
+        let removals = (0..entries.len())
+            .map(|index| format!("#waiters_field[{}]", index))
+            .collect::<Vec<String>>();
+        let conditions = (0..entries.len())
+            .map(|index| format!("#waiters_field[{}] = :waiter{}", index, index))
+            .collect::<Vec<String>>();
+        let attribute_values = entries
+            .iter()
+            .enumerate()
+            .map(|(index, entry)| (format!(":waiter{}", index), waiter_value(entry)))
+            .collect();
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.table_name.clone(),
+            update_expression: Some(format!("REMOVE {}", removals.join(", "))),
+            condition_expression: Some(conditions.join(" AND ")),
+            expression_attribute_names: Some(hashmap! {
+                String::from("#waiters_field") => self.waiters_field_name.clone(),
+            }),
+            expression_attribute_values: Some(attribute_values),
+            key: self.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .client
+            .update_item(&update_input)
+            .with_timeout(timeout)
+            .sync()
+            .map_err(DynaError::from);
+        self.record(result)?;
+
+        Ok(())
+    }
+}
+
+/// Return the string attribute value of a waiters list entry.
+fn waiter_value(entry: &str) -> AttributeValue {
+    AttributeValue {
+        s: Some(entry.to_string()),
+        ..Default::default()
+    }
+}
+
+/// Return the waiter id of a waiters list entry.
+fn waiter_id(entry: &str) -> &str {
+    match entry.rfind('@') {
+        Some(at) => &entry[..at],
+        None => entry,
+    }
+}
+
+/// Return the last seen time of a waiters list entry, in seconds since the UNIX epoch.
+fn last_seen(entry: &str) -> Option<u64> {
+    entry
+        .rfind('@')
+        .and_then(|at| entry[at + 1..].parse::<u64>().ok())
+}
+
+/// Return the position of `entry` in `waiters` if every entry ahead of it is presumed
+/// dead, i.e., was last seen more than `lease` before `now_secs`.
+///
+/// Entries are in the `<waiter id>@<last seen at>` format, where the last seen time is
+/// in seconds since the UNIX epoch. Entries in any other format are never presumed dead.
+pub(super) fn fifo_position(
+    waiters: &[String],
+    entry: &str,
+    now_secs: u64,
+    lease: Duration,
+) -> Option<usize> {
+    for (position, waiter) in waiters.iter().enumerate() {
+        if waiter == entry {
+            return Some(position);
+        }
+
+        match last_seen(waiter) {
+            Some(seen) if now_secs.saturating_sub(seen) > lease.as_secs() => {}
+            _ => return None,
+        }
+    }
+
+    None
+}
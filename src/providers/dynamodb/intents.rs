@@ -0,0 +1,240 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Advisory lock intents of waiters polling DynamoDB locks.
+//!
+//! A waiter polling a held lock with an `intent_ttl` set on its `DynamoDbLockInput`
+//! registers its intent to acquire the lock: an entry of the intents map of the lock
+//! item, mapping a random intent id to the Unix time the intent expires at. Every
+//! `refresh_lock` counts the live intents of the other waiters, which
+//! `Locking::observed_contenders` reports, and the retrying helpers (e.g.,
+//! `acquire_blocking`) spread their polls over that many more poll intervals. Many
+//! waiters then don't all stampede the lock the moment it frees up.
+//!
+//! Polls renew the intent once half of its TTL went by, and a successful `acquire_lock`
+//! withdraws it. The intent of a waiter giving up or crashing simply expires, unless
+//! it is withdrawn with `withdraw_intent`.
+//!
+//! Intents are a best-effort congestion mitigation, not a fairness guarantee: waiters
+//! are not ordered (see `acquire_fifo` for that), processors not registering intents
+//! are not held back, and the backoff only adapts on the next poll.
+//!
+//! Entries can only be set in an existing map, so the first registration first creates
+//! an empty intents map unless there is one.
+
+use std::collections::HashMap;
+use std::time::{Duration, UNIX_EPOCH};
+use uuid::Uuid;
+
+use rusoto_core::{DispatchSignedRequest, ProvideAwsCredentials};
+use rusoto_dynamodb::{AttributeValue, DynamoDb, UpdateItemInput};
+
+use super::{expressions, number_attribute, seconds_after, DynamoDbDriver, DynamoDbLockInput};
+use {DistLock, DynaError, DynaErrorKind};
+
+impl<P, D> DistLock<DynamoDbDriver<P, D>>
+where
+    P: ProvideAwsCredentials + 'static,
+    D: DispatchSignedRequest + 'static,
+{
+    /// Withdraw our lock intent, if any, e.g., when giving up waiting for the lock.
+    ///
+    /// A successful `acquire_lock` already withdraws it, and an intent left behind
+    /// expires after its TTL anyway.
+    pub fn withdraw_intent(&mut self, input: &DynamoDbLockInput) -> Result<(), DynaError> {
+        let intent_id = match self.driver.intent {
+            Some((ref intent_id, _)) => intent_id.clone(),
+            None => return Ok(()),
+        };
+
+        self.driver.throttle()?;
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.driver.table_name.clone(),
+            update_expression: Some(String::from(expressions::INTENT_WITHDRAW_UPDATE)),
+            condition_expression: Some(String::from(expressions::INTENTS_CONDITION)),
+            expression_attribute_names: Some(hashmap! {
+                String::from("#intents_field") => self.driver.intents_field_name.clone(),
+                String::from("#intent") => intent_id.clone(),
+            }),
+            key: self.driver.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .driver
+            .client
+            .update_item(&update_input)
+            .with_timeout(input.timeout)
+            .sync()
+            .map_err(DynaError::from);
+
+        match self.driver.record(result) {
+            // Without an intents map our intent is gone already
+            Err(ref err) if err.kind() == DynaErrorKind::LockAlreadyAcquired => {}
+            Err(err) => return Err(err),
+            Ok(_) => {}
+        }
+
+        debug!(
+            "lock '{}' intent ({}) withdrawn",
+            self.driver.partition_key_value, intent_id
+        );
+        self.driver.intent = None;
+
+        Ok(())
+    }
+
+    /// Register our lock intent with a TTL of `ttl`, or renew it once half of its TTL
+    /// went by, given whether the lock item already `has_intents`.
+    pub(super) fn register_intent(
+        &mut self,
+        has_intents: bool,
+        ttl: Duration,
+        input: &DynamoDbLockInput,
+    ) -> Result<(), DynaError> {
+        let now_secs = input
+            .system_time_or_now()
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+
+        if let Some((_, expires_at)) = self.driver.intent {
+            if expires_at.saturating_sub(now_secs) > ttl.as_secs() / 2 {
+                return Ok(());
+            }
+        }
+
+        if !has_intents {
+            self.create_intents(input)?;
+        }
+
+        let intent_id = match self.driver.intent {
+            Some((ref intent_id, _)) => intent_id.clone(),
+            None => Uuid::new_v4().simple().to_string(),
+        };
+        let expires_at = seconds_after(now_secs, ttl)?;
+
+        self.driver.throttle()?;
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.driver.table_name.clone(),
+            update_expression: Some(String::from(expressions::INTENT_UPDATE)),
+            condition_expression: Some(String::from(expressions::INTENTS_CONDITION)),
+            expression_attribute_names: Some(hashmap! {
+                String::from("#intents_field") => self.driver.intents_field_name.clone(),
+                String::from("#intent") => intent_id.clone(),
+            }),
+            expression_attribute_values: Some(hashmap! {
+                String::from(":intent_expires_at") => number_attribute(expires_at)?,
+            }),
+            key: self.driver.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .driver
+            .client
+            .update_item(&update_input)
+            .with_timeout(input.timeout)
+            .sync()
+            .map_err(DynaError::from);
+
+        match self.driver.record(result) {
+            // The intents map vanished since we read the lock item, register on the next poll
+            Err(ref err) if err.kind() == DynaErrorKind::LockAlreadyAcquired => {
+                self.driver.intent = None;
+            }
+            Err(err) => return Err(err),
+            Ok(_) => {
+                debug!(
+                    "lock '{}' intent ({}) registered until {}",
+                    self.driver.partition_key_value, intent_id, expires_at
+                );
+                self.driver.intent = Some((intent_id, expires_at));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Create an empty intents map on the lock item, unless it has one.
+    fn create_intents(&mut self, input: &DynamoDbLockInput) -> Result<(), DynaError> {
+        self.driver.throttle()?;
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.driver.table_name.clone(),
+            update_expression: Some(String::from(expressions::INTENTS_CREATE_UPDATE)),
+            expression_attribute_names: Some(hashmap! {
+                String::from("#intents_field") => self.driver.intents_field_name.clone(),
+            }),
+            expression_attribute_values: Some(hashmap! {
+                String::from(":empty_intents") => AttributeValue { m: Some(HashMap::new()), ..Default::default() },
+            }),
+            key: self.driver.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .driver
+            .client
+            .update_item(&update_input)
+            .with_timeout(input.timeout)
+            .sync()
+            .map_err(DynaError::from);
+        self.driver.record(result)?;
+
+        Ok(())
+    }
+}
+
+impl<P, D> DynamoDbDriver<P, D>
+where
+    P: ProvideAwsCredentials,
+    D: DispatchSignedRequest,
+{
+    /// Return the number of intents of other waiters in `item` that are still live at
+    /// `now_secs`, in seconds since the UNIX epoch.
+    pub(super) fn live_intents(
+        &self,
+        item: &HashMap<String, AttributeValue>,
+        now_secs: u64,
+    ) -> u32 {
+        let intents = match item
+            .get(&self.intents_field_name)
+            .and_then(|attr| attr.m.as_ref())
+        {
+            Some(intents) => intents,
+            None => return 0,
+        };
+        let own_id = self.intent.as_ref().map(|&(ref intent_id, _)| intent_id);
+
+        intents
+            .iter()
+            .filter(|&(intent_id, _)| Some(intent_id) != own_id)
+            .filter(|&(_, expires_at)| {
+                expires_at
+                    .n
+                    .as_ref()
+                    .and_then(|n| n.parse::<u64>().ok())
+                    .map_or(false, |expires_at| expires_at > now_secs)
+            })
+            .count() as u32
+    }
+}
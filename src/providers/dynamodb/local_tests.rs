@@ -0,0 +1,187 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Conformance tests against DynamoDB Local.
+//!
+//! These tests only run when the `DYNALOCK_DYNAMODB_LOCAL` environment variable holds
+//! the endpoint of a DynamoDB Local instance (e.g., "http://localhost:8000"), and pass
+//! trivially otherwise. Any credentials are accepted by DynamoDB Local, but some must
+//! be found, e.g., `AWS_ACCESS_KEY_ID=local AWS_SECRET_ACCESS_KEY=local`. Every test
+//! creates its own lock table, which is left behind.
+
+use std::env;
+use std::thread;
+use std::time::Duration;
+
+use rusoto_core::reactor::{CredentialsProvider, RequestDispatcher};
+use rusoto_dynamodb::{
+    AttributeDefinition, CreateTableInput, DynamoDb, DynamoDbClient, KeySchemaElement,
+    ProvisionedThroughput,
+};
+use uuid::Uuid;
+
+use super::{parse_region, DynamoDbDriver, DynamoDbDriverInput, DynamoDbLockInput};
+use contract::assert_locking_contract;
+use {DistLock, DynaErrorKind, Locking};
+
+/// The environment variable holding the DynamoDB Local endpoint.
+const ENDPOINT_VAR: &'static str = "DYNALOCK_DYNAMODB_LOCAL";
+
+type LocalLock = DistLock<DynamoDbDriver<CredentialsProvider, RequestDispatcher>>;
+
+/// A fresh lock table on DynamoDB Local.
+struct LocalTable {
+    region: String,
+    input: DynamoDbDriverInput,
+}
+
+impl LocalTable {
+    /// Create a lock table with a unique name, or return `None` when no DynamoDB Local
+    /// endpoint is configured.
+    fn create() -> Option<Self> {
+        let endpoint = match env::var(ENDPOINT_VAR) {
+            Ok(endpoint) => endpoint,
+            Err(_) => {
+                debug!("{} is not set, skipping", ENDPOINT_VAR);
+                return None;
+            }
+        };
+        let region = format!("custom:local:{}", endpoint);
+        let table_name = format!("dynalock_{}", Uuid::new_v4().simple());
+
+        let create_input = CreateTableInput {
+            table_name: table_name.clone(),
+            attribute_definitions: vec![AttributeDefinition {
+                attribute_name: String::from("lock_id"),
+                attribute_type: String::from("S"),
+            }],
+            key_schema: vec![KeySchemaElement {
+                attribute_name: String::from("lock_id"),
+                key_type: String::from("HASH"),
+            }],
+            provisioned_throughput: ProvisionedThroughput {
+                read_capacity_units: 5,
+                write_capacity_units: 5,
+            },
+            ..Default::default()
+        };
+        DynamoDbClient::simple(parse_region(&region).unwrap())
+            .create_table(&create_input)
+            .sync()
+            .unwrap();
+
+        Some(LocalTable {
+            region: region,
+            input: DynamoDbDriverInput {
+                table_name: table_name,
+                partition_key_field_name: String::from("lock_id"),
+                ..Default::default()
+            },
+        })
+    }
+
+    /// Return a new lock on the table, playing the role of a separate processor.
+    fn lock(&self, duration: Duration) -> LocalLock {
+        let driver = DynamoDbDriver::new_in_region(&self.region, &self.input).unwrap();
+        DistLock::new(driver, duration)
+    }
+}
+
+#[test]
+fn local_locking_contract_success() {
+    let table = match LocalTable::create() {
+        Some(table) => table,
+        None => return,
+    };
+    let input = DynamoDbLockInput {
+        consistent_read: Some(true),
+        ..Default::default()
+    };
+
+    assert_locking_contract(
+        || table.lock(Duration::from_secs(10)),
+        &input,
+        &input,
+        &input,
+    );
+}
+
+#[test]
+fn local_takeover_success() {
+    let table = match LocalTable::create() {
+        Some(table) => table,
+        None => return,
+    };
+    let input = DynamoDbLockInput {
+        consistent_read: Some(true),
+        ..Default::default()
+    };
+
+    let mut holder = table.lock(Duration::from_secs(1));
+    assert!(holder.acquire_lock(&input).is_ok());
+    let holder_token = holder.driver.current_token.clone();
+
+    // The contender observes the holder's fence token, outlives its lease and takes over
+    let mut contender = table.lock(Duration::from_secs(10));
+    assert!(contender.refresh_lock(&input).is_ok());
+    assert_eq!(contender.driver.current_token, holder_token);
+    thread::sleep(Duration::from_secs(1));
+    assert!(contender.acquire_lock(&input).is_ok());
+    assert_eq!(
+        contender.driver.taken_over_token(),
+        Some(holder_token.as_str())
+    );
+
+    // The deposed holder can neither release nor renew the lock anymore
+    assert_eq!(
+        holder.release_lock(&input).err().unwrap().kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+    assert_eq!(
+        holder.acquire_lock(&input).err().unwrap().kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+}
+
+#[test]
+fn local_sweep_expired_success() {
+    let table = match LocalTable::create() {
+        Some(table) => table,
+        None => return,
+    };
+    let input = DynamoDbLockInput {
+        consistent_read: Some(true),
+        ..Default::default()
+    };
+
+    let mut crashed = table.lock(Duration::from_secs(1));
+    assert!(crashed.acquire_lock(&input).is_ok());
+
+    // The stored expiry has a resolution of a second
+    thread::sleep(Duration::from_secs(2));
+
+    let janitor = table.lock(Duration::from_secs(10));
+    assert_eq!(
+        janitor
+            .driver
+            .sweep_expired(Duration::from_secs(0), &input)
+            .unwrap(),
+        1
+    );
+
+    // A newcomer that never observed the crashed holder acquires the swept lock
+    let mut newcomer = table.lock(Duration::from_secs(10));
+    assert!(newcomer.acquire_lock(&input).is_ok());
+    assert_eq!(newcomer.driver.taken_over_token(), None);
+}
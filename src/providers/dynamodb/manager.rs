@@ -0,0 +1,169 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A manager of DynamoDB locks over many shared resources.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rusoto_core::{DispatchSignedRequest, ProvideAwsCredentials};
+use rusoto_dynamodb::DynamoDbClient;
+
+use super::{DynamoDbDriver, DynamoDbDriverInput, DynamoDbLockInput};
+use DistLock;
+
+/// A structure that lazily creates and caches a `DistLock` per shared resource.
+///
+/// Every lock is created from the same `DynamoDbDriverInput` template, with the
+/// `partition_key_value` field set to the resource key. Every lock shares the
+/// manager's DynamoDB client, so that connections and credentials are reused.
+///
+/// Resources needing different leases or timeouts are configured through a resolver
+/// (see `with_resolver`), which is called once per resource when its lock is created.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// extern crate dynalock;
+///
+/// use std::time::Duration;
+///
+/// use dynalock::rusoto_core::Region;
+/// use dynalock::rusoto_dynamodb::DynamoDbClient;
+///
+/// use dynalock::dynamodb::{DynamoDbDriverInput, LockManager};
+///
+/// # fn main() {
+///     let input = DynamoDbDriverInput {
+///          table_name: String::from("locks_table"),
+///          partition_key_field_name: String::from("lock_id"),
+///          ..Default::default()
+///     };
+///
+///     let mut manager = LockManager::new(
+///         DynamoDbClient::simple(Region::UsEast1), input, Duration::from_secs(10));
+///
+///     let lock = manager.lock_for("queue-1");
+/// # }
+/// ```
+pub struct LockManager<P, D>
+where
+    P: ProvideAwsCredentials,
+    D: DispatchSignedRequest,
+{
+    client: Arc<DynamoDbClient<P, D>>,
+    input: DynamoDbDriverInput,
+    duration: Duration,
+    resolver: Option<fn(&str) -> (Duration, DynamoDbLockInput)>,
+    locks: HashMap<String, DistLock<DynamoDbDriver<P, D>>>,
+    lock_inputs: HashMap<String, DynamoDbLockInput>,
+}
+
+impl<P, D> LockManager<P, D>
+where
+    P: ProvideAwsCredentials,
+    D: DispatchSignedRequest,
+{
+    /// Initialize a new LockManager structure.
+    ///
+    /// The `client` is shared by every lock. The `input` parameter is used as a template
+    /// for every lock, its `partition_key_value` field is replaced by the resource key.
+    /// The `duration` parameter is the lease duration of every lock.
+    pub fn new(
+        client: DynamoDbClient<P, D>,
+        input: DynamoDbDriverInput,
+        duration: Duration,
+    ) -> Self {
+        LockManager {
+            client: Arc::new(client),
+            input: input,
+            duration: duration,
+            resolver: None,
+            locks: HashMap::new(),
+            lock_inputs: HashMap::new(),
+        }
+    }
+
+    /// Resolve the lease duration and the `DynamoDbLockInput` of every resource with
+    /// `resolver`, instead of the manager's `duration` and the default lock input.
+    ///
+    /// The resolver is called with the resource key when its lock is created and the
+    /// result is cached along with the lock.
+    pub fn with_resolver(mut self, resolver: fn(&str) -> (Duration, DynamoDbLockInput)) -> Self {
+        self.resolver = Some(resolver);
+        self
+    }
+
+    /// Return the lock of the shared resource `key`, creating it if it doesn't exist yet.
+    pub fn lock_for(&mut self, key: &str) -> &mut DistLock<DynamoDbDriver<P, D>> {
+        self.create(key);
+        self.locks.get_mut(key).unwrap()
+    }
+
+    /// Return the lock of the shared resource `key` along with the `DynamoDbLockInput`
+    /// resolved for it, creating them if they don't exist yet.
+    pub fn lock_with_input_for(
+        &mut self,
+        key: &str,
+    ) -> (&mut DistLock<DynamoDbDriver<P, D>>, &DynamoDbLockInput) {
+        self.create(key);
+        (
+            self.locks.get_mut(key).unwrap(),
+            self.lock_inputs.get(key).unwrap(),
+        )
+    }
+
+    /// Remove the lock of the shared resource `key` from the manager and return it.
+    pub fn remove(&mut self, key: &str) -> Option<DistLock<DynamoDbDriver<P, D>>> {
+        self.lock_inputs.remove(key);
+        self.locks.remove(key)
+    }
+
+    /// Return the number of locks held by the manager.
+    pub fn len(&self) -> usize {
+        self.locks.len()
+    }
+
+    /// Return true if the manager holds no locks.
+    pub fn is_empty(&self) -> bool {
+        self.locks.is_empty()
+    }
+
+    /// Create the lock of the shared resource `key` and resolve its configuration, unless
+    /// it exists already.
+    fn create(&mut self, key: &str) {
+        if self.locks.contains_key(key) {
+            return;
+        }
+
+        debug!("creating a new lock for resource '{}'", key);
+        let (duration, lock_input) = match self.resolver {
+            Some(resolver) => resolver(key),
+            None => (self.duration, DynamoDbLockInput::default()),
+        };
+
+        let input = DynamoDbDriverInput {
+            partition_key_value: key.to_string(),
+            ..self.input.clone()
+        };
+        let lock = DistLock::new(
+            DynamoDbDriver::new_shared(self.client.clone(), &input),
+            duration,
+        );
+
+        self.locks.insert(key.to_string(), lock);
+        self.lock_inputs.insert(key.to_string(), lock_input);
+    }
+}
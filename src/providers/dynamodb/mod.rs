@@ -15,39 +15,99 @@
 //! An implementation of the locking API using DynamoDB as a storage provider
 //!
 //! This implementation fully implements the `Locking` trait for the `DistLock<DynamoDbDriver>`
-//! structure. Lock items on the DynamoDB table are composed of the following attributes:
+//! structure. Lock items on the DynamoDB table are composed of the following attributes,
+//! whose names are set through `DynamoDbDriverInput`:
 //!
 //! - Partition key field
 //! - Fence token field
-//! - Lease duration field
+//! - Lease duration field (unless `write_duration` is disabled)
 //! - TTL field
+//! - Absolute lease expiry field
+//! - Metadata blob and acquisition reason fields, when given on acquisition
+//! - Waiters queue field, used by `acquire_fifo`
+//! - Shared lock readers map field, used by shared acquisitions
+//! - Lock intents map field, used by waiters polling with an `intent_ttl`
+//!
+//! The following attributes are only used when their field name is configured:
+//!
+//! - Drain flag field
+//! - Host identity field
+//! - Renew count field
+//! - Epoch field
+//! - Generation field
+//! - Schema version field
+//! - ISO-8601 acquisition and lease expiry time fields (`iso8601` feature)
+//! - Liveness attribute (`require_attribute`), written by the application rather than
+//!   the driver
 //!
 //! The partition key of the table is used as an identifier of the shared resource,
 //! while the fence token is used to prevent the ABA problem. The duration attribute
 //! is used to specify the lock's duration. The TTL field is used to tell DynamoDB
 //! when to garbage-collect or remove items that has expired, that if TTL is
-//! configured on the table.
+//! configured on the table. The absolute lease expiry is the wall-clock time the lease
+//! ends at, which janitors such as `sweep_expired` rely on. The remaining attributes are
+//! described on their `DynamoDbDriverInput` fields.
 //!
 //! Currently the fence token is implemented by generating a UUID v4 token for
 //! every `acquire_lock` and `release_lock` operation. UUID v4 security and strength depends on
 //! the recent implementation of a reseeded version of the HC-128 CSPRNG in `std::rand`,
 //! as long as this invariant holds, fence token collisions are as rare as the CSPRNG period
 //! allows it to be (i.e., incredibly long period).
+//!
+//! Lock tables must not be DynamoDB global tables replicated to several regions.
+//! Replication between regions is asynchronous and conflicting writes are resolved
+//! with "last writer wins", so two processors using different regions can both
+//! succeed a conditional acquisition of the same lock. Use
+//! `DynamoDbDriver::check_single_region` to reject such a table at start-up.
 
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::HashMap;
 use std::default::Default;
+use std::env;
+use std::fs::{self, File};
+use std::io::Read;
 use std::result::Result;
+use std::str::FromStr;
+use std::sync::Arc;
 use std::time::{Duration, Instant, SystemTime, SystemTimeError, UNIX_EPOCH};
-use uuid::Uuid;
+use uuid::{Uuid, NAMESPACE_OID};
+
+#[cfg(feature = "iso8601")]
+use chrono::{DateTime, Utc};
 
 use rusoto_core::reactor::{CredentialsProvider, RequestDispatcher};
-use rusoto_core::{DispatchSignedRequest, ProvideAwsCredentials};
-use rusoto_dynamodb::{AttributeValue, DynamoDb, DynamoDbClient, GetItemError, GetItemInput,
-                      UpdateItemError, UpdateItemInput};
+use rusoto_core::{DispatchSignedRequest, ParseRegionError, ProvideAwsCredentials, Region};
+use rusoto_dynamodb::{AttributeValue, BatchGetItemError, BatchGetItemInput,
+                      DescribeGlobalTableError, DescribeGlobalTableInput, DescribeTableError,
+                      DescribeTableInput, DescribeTimeToLiveError, DescribeTimeToLiveInput,
+                      DynamoDb, DynamoDbClient, GetItemError, GetItemInput, KeysAndAttributes,
+                      ScanError, ScanInput, UpdateItemError, UpdateItemInput};
 
-use {DistLock, DynaError, DynaErrorKind, Locking};
+use self::codec::MetadataCodec;
+use self::token::TokenGenerator;
+use circuit::{CircuitBreaker, CircuitConfig};
+use clock::{Clock, MonotonicClock};
+use ratelimit::{RateLimitMode, TokenBucket};
+use sleep::{Sleeper, ThreadSleeper};
+use stats::{ContentionStats, ContentionTracker};
+use {as_millis, DistLock, DynaError, DynaErrorKind, LockSnapshot, LockState, Locking};
 
+pub mod codec;
+mod fifo;
+mod intents;
+#[cfg(test)]
+mod local_tests;
+mod manager;
+mod pool;
+#[cfg(any(test, feature = "test-util"))]
+pub mod recording;
+mod shared;
 #[cfg(test)]
 mod tests;
+pub mod token;
+
+pub use self::manager::LockManager;
 
 /// A structure to contain details of the DynamoDB lock implementation.
 ///
@@ -79,15 +139,64 @@ where
     P: ProvideAwsCredentials,
     D: DispatchSignedRequest,
 {
-    client: DynamoDbClient<P, D>,
+    client: Arc<DynamoDbClient<P, D>>,
     table_name: String,
     partition_key_field_name: String,
     token_field_name: String,
     duration_field_name: String,
+    write_duration: bool,
     ttl_field_name: String,
-    ttl_value: u64,
+    ttl_mode: TtlMode,
+    expires_at_field_name: String,
+    #[cfg(feature = "iso8601")]
+    acquired_at_iso_field_name: Option<String>,
+    #[cfg(feature = "iso8601")]
+    expires_at_iso_field_name: Option<String>,
     partition_key_value: String,
     current_token: String,
+    owns_token: bool,
+    drain_field_name: Option<String>,
+    require_attribute: Option<String>,
+    metadata_field_name: String,
+    metadata_codec: Option<Box<MetadataCodec + Send>>,
+    observed_metadata: Option<Vec<u8>>,
+    reason_field_name: String,
+    observed_reason: Option<String>,
+    host_identity_field_name: Option<String>,
+    host_identity: Option<String>,
+    observed_host_identity: Option<String>,
+    waiters_field_name: String,
+    waiter_entry: Option<String>,
+    token_format: TokenFormat,
+    token_generator: Option<Box<TokenGenerator + Send>>,
+    readers_field_name: String,
+    reader_token: Option<String>,
+    stale_readers: Option<HashMap<String, AttributeValue>>,
+    intents_field_name: String,
+    intent: Option<(String, u64)>,
+    observed_intents: u32,
+    renew_count_field_name: Option<String>,
+    renew_count: u32,
+    epoch_field_name: Option<String>,
+    generation_field_name: Option<String>,
+    generation: Option<u64>,
+    schema_version_field_name: Option<String>,
+    taken_over_token: Option<String>,
+    item_collection_size: Option<(f64, f64)>,
+    max_lease: Option<Duration>,
+    max_lease_strict: bool,
+    clock_jump_threshold: Option<Duration>,
+    clock_anchor: Option<(Instant, SystemTime)>,
+    clock_jump: Option<ClockJump>,
+    expected_expires_at: Option<u64>,
+    external_extension: Option<Duration>,
+    expressions: ExpressionSet,
+    rate_limiter: Option<RefCell<TokenBucket>>,
+    rate_limit_mode: RateLimitMode,
+    circuit_breaker: Option<RefCell<CircuitBreaker>>,
+    contention: ContentionTracker,
+    sleeper: Box<Sleeper + Send>,
+    clock: Box<Clock + Send>,
 }
 
 impl<P, D> DynamoDbDriver<P, D>
@@ -98,6 +207,12 @@ where
     /// Initialize a new DynamoDbDriver structure and fill it with the `client`
     /// and `input` variables' contents.
     pub fn new(client: DynamoDbClient<P, D>, input: &DynamoDbDriverInput) -> Self {
+        DynamoDbDriver::new_shared(Arc::new(client), input)
+    }
+
+    /// Initialize a new DynamoDbDriver structure like `new`, with a `client` shared with
+    /// other drivers, e.g., the drivers of the locks of a `LockManager`.
+    pub fn new_shared(client: Arc<DynamoDbClient<P, D>>, input: &DynamoDbDriverInput) -> Self {
         DynamoDbDriver {
             client: client,
             table_name: input.table_name.clone(),
@@ -105,264 +220,3413 @@ where
             partition_key_value: input.partition_key_value.clone(),
             token_field_name: input.token_field_name.clone(),
             duration_field_name: input.duration_field_name.clone(),
+            write_duration: input.write_duration,
             ttl_field_name: input.ttl_field_name.clone(),
-            ttl_value: input.ttl_value,
+            ttl_mode: input.ttl_mode,
+            expires_at_field_name: input.expires_at_field_name.clone(),
+            #[cfg(feature = "iso8601")]
+            acquired_at_iso_field_name: input.acquired_at_iso_field_name.clone(),
+            #[cfg(feature = "iso8601")]
+            expires_at_iso_field_name: input.expires_at_iso_field_name.clone(),
             current_token: String::new(),
+            owns_token: false,
+            drain_field_name: input.drain_field_name.clone(),
+            require_attribute: input.require_attribute.clone(),
+            metadata_field_name: input.metadata_field_name.clone(),
+            metadata_codec: None,
+            observed_metadata: None,
+            reason_field_name: input.reason_field_name.clone(),
+            observed_reason: None,
+            host_identity_field_name: input.host_identity_field_name.clone(),
+            host_identity: input
+                .host_identity_field_name
+                .as_ref()
+                .map(|_| (input.host_identity)()),
+            observed_host_identity: None,
+            waiters_field_name: input.waiters_field_name.clone(),
+            waiter_entry: None,
+            token_format: input.token_format,
+            token_generator: None,
+            readers_field_name: input.readers_field_name.clone(),
+            reader_token: None,
+            stale_readers: None,
+            intents_field_name: input.intents_field_name.clone(),
+            intent: None,
+            observed_intents: 0,
+            renew_count_field_name: input.renew_count_field_name.clone(),
+            renew_count: 0,
+            epoch_field_name: input.epoch_field_name.clone(),
+            generation_field_name: input.generation_field_name.clone(),
+            schema_version_field_name: input.schema_version_field_name.clone(),
+            generation: None,
+            taken_over_token: None,
+            item_collection_size: None,
+            max_lease: input.max_lease,
+            max_lease_strict: input.max_lease_strict,
+            clock_jump_threshold: input.clock_jump_threshold,
+            clock_anchor: None,
+            clock_jump: None,
+            expected_expires_at: None,
+            external_extension: None,
+            expressions: ExpressionSet::default(),
+            rate_limiter: input
+                .max_ops_per_sec
+                .map(|ops| RefCell::new(TokenBucket::new(ops, Instant::now()))),
+            rate_limit_mode: input.rate_limit_mode,
+            circuit_breaker: input
+                .circuit
+                .map(|config| RefCell::new(CircuitBreaker::new(config, Instant::now()))),
+            contention: ContentionTracker::new(Instant::now()),
+            sleeper: Box::new(ThreadSleeper),
+            clock: Box::new(MonotonicClock),
         }
     }
-}
 
-/// The number of seconds in 24 hours.
-pub const DAY_SECONDS: u64 = 86400;
+    /// Replace the base expressions used by the driver with `expressions`.
+    ///
+    /// The expressions are validated with `ExpressionSet::validate` so that a malformed
+    /// set is rejected here rather than by DynamoDB at request time. A driver configured
+    /// not to write the lease duration also requires the acquire update expression to
+    /// hold `expressions::DURATION_UPDATE` as a `SET` action it can leave out.
+    pub fn with_expressions(mut self, expressions: ExpressionSet) -> Result<Self, DynaError> {
+        expressions.validate()?;
+        if !self.write_duration
+            && remove_set_action(&expressions.acquire_update, expressions::DURATION_UPDATE)
+                .is_none()
+        {
+            return Err(DynaError::new(
+                DynaErrorKind::InvalidConfiguration,
+                Some("acquire update can't be written without the lease duration"),
+            ));
+        }
+        self.expressions = expressions;
 
-/// A structure that describes the inputs to `DynamoDbDriver::new`.
-///
-/// This structure's `Default` trait implementation provides sane default
-/// values. Only the `table_name` and the `partition_key_field_name` fields are
-/// required.
-#[derive(Debug)]
-pub struct DynamoDbDriverInput {
-    /// The DynamoDB lock table name to be used.
-    pub table_name: String,
-    /// The partition key field name.
-    pub partition_key_field_name: String,
-    /// The partition key value (default: "singleton"). This field should be provided
-    /// to use the lock driver on multiple shared resources, each represented by a
-    /// partition key value.
-    pub partition_key_value: String,
-    /// The fence token field name (default: "rvn").
-    pub token_field_name: String,
-    /// The lease duration field name (default: "duration").
-    pub duration_field_name: String,
-    /// The TTL field name (default: "ttl").
-    pub ttl_field_name: String,
-    /// The TTL value to be added to the wall clock for expiration (default: 7 days in seconds).
-    pub ttl_value: u64,
-}
+        Ok(self)
+    }
 
-impl Default for DynamoDbDriverInput {
-    fn default() -> Self {
-        DynamoDbDriverInput {
-            table_name: String::new(),
-            partition_key_field_name: String::new(),
-            partition_key_value: String::from("singleton"),
-            token_field_name: String::from("rvn"),
-            duration_field_name: String::from("duration"),
-            ttl_field_name: String::from("ttl"),
-            ttl_value: DAY_SECONDS * 7,
-        }
+    /// Replace the sleeper the driver waits with (default: `ThreadSleeper`), e.g., with a
+    /// `MockSleeper` to test blocking rate limits without real delays.
+    pub fn with_sleeper<S>(mut self, sleeper: S) -> Self
+    where
+        S: Sleeper + Send + 'static,
+    {
+        self.sleeper = Box::new(sleeper);
+        self
     }
-}
 
-/// A struct to hold input variables for the `Locking` trait methods inputs.
-///
-/// The optional field `consistent_read` is not required to be set for the `refresh_lock`
-/// method, this field only exists for convenience.
-#[derive(Debug, Clone)]
-pub struct DynamoDbLockInput {
-    /// After how much time we timeout from a lock acquisition or refresh request to DynamoDB.
-    pub timeout: Duration,
-    /// Whether to carry out a strongly consistent read on the table within a refresh request.
-    pub consistent_read: Option<bool>,
-}
+    /// Replace the clock the lease left is computed with (default: `MonotonicClock`),
+    /// e.g., with a `MockClock` to cross lease boundaries in tests without real delays.
+    ///
+    /// The clock also provides the `Instant` lock operations start at, unless it is
+    /// overridden through the `instant` field of their input, and the window of the
+    /// contention statistics, which restarts at the time of the new clock.
+    pub fn with_clock<C>(mut self, clock: C) -> Self
+    where
+        C: Clock + Send + 'static,
+    {
+        self.contention = ContentionTracker::new(clock.now());
+        self.clock = Box::new(clock);
+        self
+    }
 
-impl Default for DynamoDbLockInput {
-    fn default() -> Self {
-        DynamoDbLockInput {
-            timeout: Duration::from_secs(10),
-            consistent_read: Some(false),
-        }
+    /// Encode the metadata blob with `codec` before writing it on the lock item and decode
+    /// it when reading the lock item (default: the blob is stored as-is).
+    ///
+    /// Encoded blobs are prefixed with the codec identifier. See the `codec` module for
+    /// how blobs written with other codecs, or without any, are read.
+    pub fn with_metadata_codec<C>(mut self, codec: C) -> Self
+    where
+        C: MetadataCodec + Send + 'static,
+    {
+        self.metadata_codec = Some(Box::new(codec));
+        self
     }
-}
 
-mod expressions {
-    pub const ACQUIRE_UPDATE: &'static str =
-        "SET #token_field = :new_token, #duration_field = :lease, #ttl_field = :ttl";
-    pub const ACQUIRE_CONDITION: &'static str =
-        "attribute_not_exists(#token_field) OR #token_field = :cond_current_token";
-    pub const RELEASE_UPDATE: &'static str = "REMOVE #token_field";
-    pub const RELEASE_CONDITION: &'static str =
-        "attribute_exists(#token_field) AND #token_field = :cond_current_token";
-}
+    /// Generate random fence tokens with `generator` instead of the `token_format` (default:
+    /// v4 UUIDs in the `token_format`).
+    ///
+    /// Tokens derived from idempotency keys are still rendered in the `token_format`.
+    pub fn with_token_generator<G>(mut self, generator: G) -> Self
+    where
+        G: TokenGenerator + Send + 'static,
+    {
+        self.token_generator = Some(Box::new(generator));
+        self
+    }
 
-impl<P, D> Locking for DistLock<DynamoDbDriver<P, D>>
-where
-    P: ProvideAwsCredentials + 'static,
-    D: DispatchSignedRequest + 'static,
-{
-    type AcquireLockInputType = DynamoDbLockInput;
-    type RefreshLockInputType = DynamoDbLockInput;
-    type ReleaseLockInputType = DynamoDbLockInput;
+    /// Return the fence token we know of, or an empty string when none is known.
+    ///
+    /// After a successful exclusive `acquire_lock` this is the fence token that was just
+    /// written, to be passed along to the protected resource so that it can reject writes
+    /// from stale holders. It stays the same until the next `acquire_lock`, or until a
+    /// `refresh_lock` observes the fence token of another processor. See
+    /// `DistLock::acquire_with_token` to get the token back from the acquisition itself.
+    pub fn current_token(&self) -> &str {
+        &self.current_token
+    }
 
-    fn acquire_lock(&mut self, input: &Self::AcquireLockInputType) -> Result<Instant, DynaError> {
-        let new_token = Uuid::new_v4().hyphenated().to_string();
+    /// Return the fence token of the holder the last `acquire_lock` took the lock over from.
+    ///
+    /// `None` is returned when the last acquisition found the lock free or renewed our own
+    /// lease. A takeover usually means the previous holder crashed or stalled past its
+    /// lease, which is worth alerting on.
+    pub fn taken_over_token(&self) -> Option<&str> {
+        self.taken_over_token.as_ref().map(|token| token.as_str())
+    }
 
-        // Use new token as current token if this is our first run
-        if self.driver.current_token.is_empty() {
-            self.driver.current_token = new_token.clone();
-        }
+    /// Return the size estimate range, in GB, of the lock item's collection as reported by
+    /// the last successful exclusive `acquire_lock`.
+    ///
+    /// DynamoDB only reports item collection metrics for tables with local secondary
+    /// indexes, whose item collections are limited to 10 GB. `None` is returned otherwise.
+    pub fn last_item_collection_size(&self) -> Option<(f64, f64)> {
+        self.item_collection_size
+    }
 
-        // Get time since EPOCH in seconds and add to it the TTL value
-        let ttl_secs =
-            SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() + self.driver.ttl_value;
+    /// Return the metadata blob last written or observed on the lock item, if any.
+    ///
+    /// The metadata is recorded when `acquire_lock` writes it and is updated from the
+    /// lock item on every `refresh_lock` call.
+    pub fn observed_metadata(&self) -> Option<&[u8]> {
+        self.observed_metadata
+            .as_ref()
+            .map(|metadata| metadata.as_slice())
+    }
 
-        // Prepare update method input
-        let update_input = UpdateItemInput {
-            table_name: self.driver.table_name.clone(),
-            update_expression: Some(String::from(expressions::ACQUIRE_UPDATE)),
-            condition_expression: Some(String::from(expressions::ACQUIRE_CONDITION)),
-            expression_attribute_names: Some(hashmap! {
-                String::from("#token_field") => self.driver.token_field_name.clone(),
-                String::from("#duration_field") => self.driver.duration_field_name.clone(),
-                String::from("#ttl_field") => self.driver.ttl_field_name.clone(),
-            }),
-            expression_attribute_values: Some(hashmap! {
-                String::from(":new_token") => AttributeValue { s: Some(new_token.clone()), ..Default::default() },
-                String::from(":lease") => AttributeValue { n: Some(self.duration.as_secs().to_string()), ..Default::default() },
-                String::from(":ttl") => AttributeValue { n: Some(ttl_secs.to_string()), ..Default::default() },
-                String::from(":cond_current_token") => AttributeValue { s: Some(self.driver.current_token.clone()), ..Default::default() }
-            }),
-            key: hashmap! {
-                self.driver.partition_key_field_name.clone() => AttributeValue {
-                    s: Some(self.driver.partition_key_value.clone()),
-                    ..Default::default()
-                },
-            },
-            ..Default::default()
+    /// Return the host identity last written or observed on the lock item, if a
+    /// `host_identity_field_name` is configured.
+    ///
+    /// Like the reason, it is recorded when `acquire_lock` writes it and is updated from the
+    /// lock item on every `refresh_lock` call.
+    pub fn observed_host_identity(&self) -> Option<&str> {
+        self.observed_host_identity
+            .as_ref()
+            .map(|identity| identity.as_str())
+    }
+
+    /// Return the partition key value of the lock item the driver targets, e.g., the key
+    /// locked by `acquire_any`.
+    pub fn partition_key_value(&self) -> &str {
+        &self.partition_key_value
+    }
+
+    /// Return the reason last written or observed on the lock item, if any.
+    ///
+    /// Like the metadata blob, the reason is recorded when `acquire_lock` writes it and is
+    /// updated from the lock item on every `refresh_lock` call.
+    pub fn observed_reason(&self) -> Option<&str> {
+        self.observed_reason.as_ref().map(|reason| reason.as_str())
+    }
+
+    /// Return how much later than we expect the stored lease expires, as observed by the
+    /// last `refresh_lock` call while holding the lock.
+    ///
+    /// Our lease expiry is only extended by our own `acquire_lock` and `refresh_and_extend`
+    /// calls, so a later stored expiry means another processor wrote our fence token,
+    /// e.g., after a token handoff or because of a bug. The lease is then longer than
+    /// `remaining` reports.
+    pub fn last_external_extension(&self) -> Option<Duration> {
+        self.external_extension
+    }
+
+    /// Return the wall-clock jump detected by the last `refresh_lock` call, if any.
+    ///
+    /// Jumps are only looked for when a `clock_jump_threshold` is configured.
+    pub fn last_clock_jump(&self) -> Option<ClockJump> {
+        self.clock_jump
+    }
+
+    /// Return the generation written by our last successful exclusive `acquire_lock`, when
+    /// a `generation_field_name` is configured.
+    pub fn generation(&self) -> Option<u64> {
+        self.generation
+    }
+
+    /// Return the number of times the current lease was renewed in place.
+    ///
+    /// The count grows with every `acquire_lock` call that renews a lease we hold and
+    /// is reset whenever a lease is freshly acquired, taken over or released. A count
+    /// far above the expected job length hints at a stuck holder.
+    pub fn renew_count(&self) -> u32 {
+        self.renew_count
+    }
+
+    /// Return the contention statistics of the lock collected since the driver was created
+    /// or the statistics were last reset.
+    ///
+    /// Exclusive acquisitions, acquisitions failing with `DynaErrorKind::LockAlreadyAcquired`
+    /// and exclusive releases are counted. Holds are measured with the `instant` of the
+    /// `DynamoDbLockInput` (or the current time), from acquisition to release.
+    pub fn stats(&self) -> ContentionStats {
+        self.contention.stats(self.clock.now())
+    }
+
+    /// Clear the contention statistics and start collecting them again.
+    pub fn reset_stats(&mut self) {
+        self.contention.reset(self.clock.now());
+    }
+
+    /// Return the base expressions used by the driver.
+    pub fn expressions(&self) -> &ExpressionSet {
+        &self.expressions
+    }
+
+    /// Check whether TTL is enabled on the lock table for the configured `ttl_field_name`.
+    ///
+    /// DynamoDB only garbage-collects expired lock items when TTL is enabled on the
+    /// table, which is configured separately from writing the TTL attribute. This
+    /// method returns `Ok(true)` when TTL is enabled (or being enabled) on the TTL
+    /// field, `Ok(false)` when TTL is disabled, and a `DynaErrorKind::InvalidConfiguration`
+    /// error when TTL is enabled on a different attribute than the one we write.
+    pub fn check_ttl_enabled(&self, input: &DynamoDbLockInput) -> Result<bool, DynaError> {
+        self.throttle()?;
+
+        let describe_input = DescribeTimeToLiveInput {
+            table_name: self.table_name.clone(),
         };
 
         // Make a sync call with timeout
-        self.driver
+        let result = self
             .client
-            .update_item(&update_input)
+            .describe_time_to_live(&describe_input)
             .with_timeout(input.timeout)
-            .sync()?;
+            .sync()
+            .map_err(DynaError::from);
+        let output = self.record(result)?;
 
-        ////////// After this point the lock clock starts //////////
-        let start = Instant::now();
+        let description = match output.time_to_live_description {
+            Some(description) => description,
+            None => return Ok(false),
+        };
 
-        // Lock acquired successfully, record the new fence token
-        info!(
-            "lock '{}' acquired successfully, current token ({}) new token ({}) lease ({}s)",
-            self.driver.partition_key_value,
-            self.driver.current_token,
-            new_token,
-            self.duration.as_secs()
-        );
-        self.driver.current_token = new_token.clone();
+        match description.time_to_live_status.as_ref().map(|s| s.as_str()) {
+            Some("ENABLED") | Some("ENABLING") => {}
+            _ => return Ok(false),
+        }
 
-        Ok(start)
+        match description.attribute_name {
+            Some(ref name) if *name == self.ttl_field_name => Ok(true),
+            other => {
+                let msg = format!(
+                    "TTL is enabled on attribute '{}' instead of '{}'",
+                    other.unwrap_or_default(),
+                    self.ttl_field_name
+                );
+                warn!("{}", msg);
+                Err(DynaError::new(
+                    DynaErrorKind::InvalidConfiguration,
+                    Some(&msg),
+                ))
+            }
+        }
     }
 
-    fn refresh_lock(&mut self, input: &Self::RefreshLockInputType) -> Result<(), DynaError> {
-        // Prepare get method input
-        let get_input = GetItemInput {
-            consistent_read: input.consistent_read,
-            table_name: self.driver.table_name.clone(),
-            key: hashmap! {
-                self.driver.partition_key_field_name.clone() => AttributeValue {
-                    s: Some(self.driver.partition_key_value.clone()),
-                    ..Default::default()
-                },
-            },
-            ..Default::default()
+    /// Check that the lock table can be reached, independently of holding any lock.
+    ///
+    /// A cheap `DescribeTable` call is made on the lock table, which makes this method fit
+    /// for health and readiness checks. A missing table (e.g., a wrong table name or
+    /// region) results in a `DynaErrorKind::InvalidConfiguration` error, while connectivity
+    /// and service failures result in a `DynaErrorKind::ProviderError` error.
+    pub fn ping(&self, input: &DynamoDbLockInput) -> Result<(), DynaError> {
+        self.throttle()?;
+
+        let describe_input = DescribeTableInput {
+            table_name: self.table_name.clone(),
         };
 
         // Make a sync call with timeout
-        let output = self.driver
+        let result = self
             .client
-            .get_item(&get_input)
+            .describe_table(&describe_input)
             .with_timeout(input.timeout)
-            .sync()?;
+            .sync()
+            .map_err(DynaError::from);
+        self.record(result)?;
 
-        // A lock item was found
-        if output.item.is_some() {
-            let attr = output
-                .item
-                .as_ref()
-                .unwrap()
-                .get(&self.driver.token_field_name);
+        Ok(())
+    }
 
-            if attr.is_some() {
-                self.driver.current_token = attr.unwrap().s.as_ref().unwrap().clone();
-                info!(
-                    "lock '{}' refreshed successful, found new token ({})",
-                    self.driver.partition_key_value, self.driver.current_token
-                );
-            }
+    /// Check that the lock table is not replicated to more than one region.
+    ///
+    /// A `DescribeGlobalTable` call is made on the lock table. When the table is a global
+    /// table with replicas in several regions, a `DynaErrorKind::CrossRegionReplication`
+    /// error naming the regions is returned: cross-region replication is eventually
+    /// consistent, so a lock acquired through one region isn't visible in the others
+    /// right away and mutual exclusion no longer holds. Tables that aren't global tables,
+    /// or are replicated to a single region, pass the check.
+    pub fn check_single_region(&self, input: &DynamoDbLockInput) -> Result<(), DynaError> {
+        self.throttle()?;
+
+        let describe_input = DescribeGlobalTableInput {
+            global_table_name: self.table_name.clone(),
+        };
+
+        // Make a sync call with timeout
+        let result = match self
+            .client
+            .describe_global_table(&describe_input)
+            .with_timeout(input.timeout)
+            .sync()
+        {
+            Ok(output) => Ok(Some(output)),
+            Err(DescribeGlobalTableError::GlobalTableNotFound(_)) => Ok(None),
+            Err(err) => Err(DynaError::from(err)),
+        };
+        let output = match self.record(result)? {
+            Some(output) => output,
+            None => return Ok(()),
+        };
+
+        let regions: Vec<String> = output
+            .global_table_description
+            .and_then(|description| description.replication_group)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|replica| replica.region_name)
+            .collect();
+
+        if regions.len() > 1 {
+            let msg = format!(
+                "table '{}' is replicated to {}",
+                self.table_name,
+                regions.join(", ")
+            );
+            warn!("{}", msg);
+            return Err(DynaError::new(
+                DynaErrorKind::CrossRegionReplication,
+                Some(&msg),
+            ));
         }
 
         Ok(())
     }
 
-    fn release_lock(&mut self, input: &Self::ReleaseLockInputType) -> Result<(), DynaError> {
+    /// Read the lock items of all `keys` and return a `LockView` of each, or `None` for
+    /// keys without a lock item.
+    ///
+    /// The lock items are fetched with `BatchGetItem`, up to `MAX_BATCH_GET_ITEMS` keys per
+    /// call, which is much cheaper than reading them one by one. Keys DynamoDB leaves
+    /// unprocessed (e.g., when the read capacity is exceeded) are requested again after an
+    /// exponential backoff, and a `DynaErrorKind::ProviderError` error is returned when some
+    /// are still unprocessed after `MAX_UNPROCESSED_RETRIES` retries. Nothing is learned
+    /// about the lock this driver targets, see `refresh_lock` for that. Every call,
+    /// retries included, goes through the rate limiter and the circuit breaker.
+    pub fn peek_many(
+        &self,
+        keys: &[String],
+        input: &DynamoDbLockInput,
+    ) -> Result<HashMap<String, Option<LockView>>, DynaError> {
+        let mut views: HashMap<String, Option<LockView>> =
+            keys.iter().map(|key| (key.clone(), None)).collect();
+
+        for chunk in keys.chunks(MAX_BATCH_GET_ITEMS) {
+            let mut pending = KeysAndAttributes {
+                consistent_read: input.consistent_read,
+                keys: chunk
+                    .iter()
+                    .map(|key| {
+                        hashmap! {
+                            self.partition_key_field_name.clone() => AttributeValue {
+                                s: Some(key.clone()),
+                                ..Default::default()
+                            },
+                        }
+                    })
+                    .collect(),
+                ..Default::default()
+            };
+            let mut retries = 0;
+
+            loop {
+                self.throttle()?;
+
+                // Prepare batch get method input
+                let batch_input = BatchGetItemInput {
+                    request_items: hashmap! {
+                        self.table_name.clone() => pending,
+                    },
+                    ..Default::default()
+                };
+
+                // Make a sync call with timeout
+                let result = self
+                    .client
+                    .batch_get_item(&batch_input)
+                    .with_timeout(input.timeout)
+                    .sync()
+                    .map_err(DynaError::from);
+                let output = self.record(result)?;
+
+                let items = output
+                    .responses
+                    .and_then(|mut responses| responses.remove(&self.table_name))
+                    .unwrap_or_default();
+                for item in items {
+                    if let Some(key) = item
+                        .get(&self.partition_key_field_name)
+                        .and_then(|attr| attr.s.clone())
+                    {
+                        let view = self.lock_view(key.clone(), &item);
+                        views.insert(key, Some(view));
+                    }
+                }
+
+                pending = match output
+                    .unprocessed_keys
+                    .and_then(|mut unprocessed| unprocessed.remove(&self.table_name))
+                {
+                    Some(ref unprocessed) if unprocessed.keys.is_empty() => break,
+                    Some(unprocessed) => unprocessed,
+                    None => break,
+                };
+
+                if retries == MAX_UNPROCESSED_RETRIES {
+                    let msg = format!(
+                        "{} lock item(s) still unprocessed after {} retries",
+                        pending.keys.len(),
+                        retries
+                    );
+                    warn!("{}", msg);
+                    return Err(DynaError::new(DynaErrorKind::ProviderError, Some(&msg)));
+                }
+
+                debug!("{} lock item(s) unprocessed, retrying", pending.keys.len());
+                self.sleeper.sleep(Duration::from_millis(
+                    UNPROCESSED_RETRY_DELAY_MILLIS << retries,
+                ));
+                retries += 1;
+            }
+        }
+
+        Ok(views)
+    }
+
+    /// Set or clear the drain flag on the lock item.
+    ///
+    /// While the flag is set `acquire_lock` refuses to acquire the lock with a
+    /// `DynaErrorKind::Draining` error, even if the lock is free. Processors already
+    /// holding the lock are not affected until they try to acquire it again. This
+    /// method requires the `drain_field_name` field to be configured.
+    pub fn set_draining(
+        &mut self,
+        draining: bool,
+        input: &DynamoDbLockInput,
+    ) -> Result<(), DynaError> {
+        let drain_field = match self.drain_field_name {
+            Some(ref drain_field) => drain_field.clone(),
+            None => {
+                return Err(DynaError::new(
+                    DynaErrorKind::InvalidConfiguration,
+                    Some("no drain field name is configured"),
+                ))
+            }
+        };
+
+        self.throttle()?;
+
         // Prepare update method input
         let update_input = UpdateItemInput {
-            table_name: self.driver.table_name.clone(),
-            update_expression: Some(String::from(expressions::RELEASE_UPDATE)),
-            condition_expression: Some(String::from(expressions::RELEASE_CONDITION)),
+            table_name: self.table_name.clone(),
+            update_expression: Some(String::from(expressions::DRAIN_UPDATE)),
             expression_attribute_names: Some(hashmap! {
-                String::from("#token_field") => self.driver.token_field_name.clone(),
+                String::from("#drain_field") => drain_field,
             }),
             expression_attribute_values: Some(hashmap! {
-                String::from(":cond_current_token") => AttributeValue { s: Some(self.driver.current_token.clone()), ..Default::default() }
+                String::from(":drain") => AttributeValue { bool: Some(draining), ..Default::default() },
             }),
-            key: hashmap! {
-                self.driver.partition_key_field_name.clone() => AttributeValue {
-                    s: Some(self.driver.partition_key_value.clone()),
-                    ..Default::default()
-                },
-            },
+            key: self.key(),
             ..Default::default()
         };
 
         // Make a sync call with timeout
-        self.driver
+        let result = self
             .client
             .update_item(&update_input)
             .with_timeout(input.timeout)
-            .sync()?;
+            .sync()
+            .map_err(DynaError::from);
+        self.record(result)?;
 
-        // Lock released successfully, clear the fence token
         info!(
-            "lock '{}' successfully released for token ({})",
-            self.driver.partition_key_value, self.driver.current_token
+            "lock '{}' drain flag set to ({})",
+            self.partition_key_value, draining
         );
-        self.driver.current_token.clear();
 
         Ok(())
     }
 
-    fn remaining(&self, instant: Instant) -> Option<Duration> {
-        self.duration.checked_sub(instant.elapsed())
-    }
-}
+    /// Clear the fence tokens of all lock items whose lease expired more than `older_than`
+    /// ago and return the number of lock items cleared.
+    ///
+    /// This scans the whole table for lock items whose absolute lease expiry attribute is
+    /// older than the threshold, which is useful for janitors cleaning up after crashed
+    /// processors without waiting for DynamoDB TTL. Every fence token is removed with a
+    /// conditional write, so a lock re-acquired in the meantime is left untouched. The
+    /// scan is expensive on large tables and should be run sparingly.
+    pub fn sweep_expired(
+        &self,
+        older_than: Duration,
+        input: &DynamoDbLockInput,
+    ) -> Result<usize, DynaError> {
+        let now_secs = input
+            .system_time_or_now()
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+        let threshold = AttributeValue {
+            n: Some(now_secs.saturating_sub(older_than.as_secs()).to_string()),
+            ..Default::default()
+        };
+        let attribute_names = hashmap! {
+            String::from("#token_field") => self.token_field_name.clone(),
+            String::from("#expires_at_field") => self.expires_at_field_name.clone(),
+        };
 
-impl From<SystemTimeError> for DynaError {
-    fn from(err: SystemTimeError) -> DynaError {
-        error!("{}", err);
-        DynaError::new(DynaErrorKind::UnhandledError, Some(&err.to_string()))
-    }
-}
+        let mut cleared = 0;
+        let mut start_key = None;
 
-impl From<GetItemError> for DynaError {
-    fn from(err: GetItemError) -> DynaError {
-        error!("{}", err);
-        DynaError::new(DynaErrorKind::ProviderError, Some(&err.to_string()))
+        loop {
+            self.throttle()?;
+
+            // Prepare scan method input
+            let scan_input = ScanInput {
+                table_name: self.table_name.clone(),
+                filter_expression: Some(String::from(expressions::SWEEP_FILTER)),
+                expression_attribute_names: Some(attribute_names.clone()),
+                expression_attribute_values: Some(hashmap! {
+                    String::from(":threshold") => threshold.clone(),
+                }),
+                exclusive_start_key: start_key,
+                ..Default::default()
+            };
+
+            // Make a sync call with timeout
+            let result = self
+                .client
+                .scan(&scan_input)
+                .with_timeout(input.timeout)
+                .sync()
+                .map_err(DynaError::from);
+            let output = self.record(result)?;
+
+            for item in output.items.unwrap_or_default() {
+                let key = match item.get(&self.partition_key_field_name) {
+                    Some(key) => key.clone(),
+                    None => continue,
+                };
+                let token = match item.get(&self.token_field_name) {
+                    Some(token) => token.clone(),
+                    None => continue,
+                };
+
+                self.throttle()?;
+
+                // Prepare update method input
+                let update_input = UpdateItemInput {
+                    table_name: self.table_name.clone(),
+                    update_expression: Some(String::from(expressions::SWEEP_UPDATE)),
+                    condition_expression: Some(String::from(expressions::SWEEP_CONDITION)),
+                    expression_attribute_names: Some(attribute_names.clone()),
+                    expression_attribute_values: Some(hashmap! {
+                        String::from(":cond_current_token") => token,
+                        String::from(":threshold") => threshold.clone(),
+                    }),
+                    key: hashmap! {
+                        self.partition_key_field_name.clone() => key,
+                    },
+                    ..Default::default()
+                };
+
+                // Make a sync call with timeout
+                let result = self
+                    .client
+                    .update_item(&update_input)
+                    .with_timeout(input.timeout)
+                    .sync()
+                    .map_err(DynaError::from);
+
+                match self.record(result) {
+                    Ok(_) => cleared += 1,
+                    // The lock was acquired again since we scanned it
+                    Err(ref err) if err.kind() == DynaErrorKind::LockAlreadyAcquired => {}
+                    Err(err) => return Err(err),
+                }
+            }
+
+            start_key = output.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+
+        info!(
+            "swept {} expired lock(s) from table '{}'",
+            cleared, self.table_name
+        );
+
+        Ok(cleared)
     }
-}
 
-impl From<UpdateItemError> for DynaError {
-    fn from(err: UpdateItemError) -> DynaError {
-        match err {
-            UpdateItemError::ConditionalCheckFailed(_) => {
-                warn!("{}", err);
-                DynaError::new(DynaErrorKind::LockAlreadyAcquired, None)
+    /// Return a `LockView` of every lock item whose lease expired but which still carries
+    /// a fence token.
+    ///
+    /// Such orphaned locks were left behind by holders that crashed or lost connectivity,
+    /// and remain until they are taken over, swept by `sweep_expired`, or removed by
+    /// DynamoDB TTL, which can lag by a long time. This scans the whole table for lock
+    /// items whose absolute lease expiry attribute is in the past, so like `sweep_expired`
+    /// it is expensive on large tables and suits periodic monitoring. Nothing is written,
+    /// but every page goes through the rate limiter and the circuit breaker.
+    pub fn find_orphaned(&self, input: &DynamoDbLockInput) -> Result<Vec<LockView>, DynaError> {
+        let now_secs = input
+            .system_time_or_now()
+            .duration_since(UNIX_EPOCH)?
+            .as_secs();
+
+        let mut orphaned = Vec::new();
+        let mut start_key = None;
+
+        loop {
+            self.throttle()?;
+
+            // Prepare scan method input
+            let scan_input = ScanInput {
+                table_name: self.table_name.clone(),
+                filter_expression: Some(String::from(expressions::ORPHANED_FILTER)),
+                expression_attribute_names: Some(hashmap! {
+                    String::from("#token_field") => self.token_field_name.clone(),
+                    String::from("#expires_at_field") => self.expires_at_field_name.clone(),
+                }),
+                expression_attribute_values: Some(hashmap! {
+                    String::from(":now") => AttributeValue {
+                        n: Some(now_secs.to_string()),
+                        ..Default::default()
+                    },
+                }),
+                consistent_read: input.consistent_read,
+                exclusive_start_key: start_key,
+                ..Default::default()
+            };
+
+            // Make a sync call with timeout
+            let result = self
+                .client
+                .scan(&scan_input)
+                .with_timeout(input.timeout)
+                .sync()
+                .map_err(DynaError::from);
+            let output = self.record(result)?;
+
+            for item in output.items.unwrap_or_default() {
+                if let Some(key) = item
+                    .get(&self.partition_key_field_name)
+                    .and_then(|attr| attr.s.clone())
+                {
+                    orphaned.push(self.lock_view(key, &item));
+                }
             }
-            _ => {
-                error!("{}", err);
-                DynaError::new(DynaErrorKind::ProviderError, Some(&err.to_string()))
+
+            start_key = output.last_evaluated_key;
+            if start_key.is_none() {
+                break;
             }
         }
+
+        if !orphaned.is_empty() {
+            warn!(
+                "found {} orphaned lock(s) in table '{}'",
+                orphaned.len(),
+                self.table_name
+            );
+        }
+
+        Ok(orphaned)
+    }
+
+    /// Move the fence tokens of all lock items from the `old_field` attribute to the
+    /// `new_field` attribute and return the number of lock items migrated.
+    ///
+    /// This is meant for renaming the token field of an existing table: the whole table is
+    /// scanned for lock items carrying the old attribute, and each token is copied to the
+    /// new attribute while the old one is removed in a single conditional write. A lock
+    /// item whose old token changed since the scan, or which already carries a token in
+    /// the new attribute, is left untouched and not counted.
+    ///
+    /// Processors still using the old field would consider migrated locks free, and
+    /// processors already using the new one would consider unmigrated locks free. Run
+    /// the migration during a maintenance window, with every processor stopped, before
+    /// deploying the new `token_field_name`. Like `sweep_expired`, the scan is expensive
+    /// on large tables.
+    pub fn migrate_token_field(
+        &mut self,
+        old_field: &str,
+        new_field: &str,
+        input: &DynamoDbLockInput,
+    ) -> Result<usize, DynaError> {
+        if old_field == new_field {
+            return Err(DynaError::new(
+                DynaErrorKind::InvalidInput,
+                Some("old and new token fields are the same"),
+            ));
+        }
+
+        let attribute_names = hashmap! {
+            String::from("#old_token_field") => String::from(old_field),
+            String::from("#new_token_field") => String::from(new_field),
+        };
+
+        let mut migrated = 0;
+        let mut start_key = None;
+
+        loop {
+            self.throttle()?;
+
+            // Prepare scan method input
+            let scan_input = ScanInput {
+                table_name: self.table_name.clone(),
+                filter_expression: Some(String::from(expressions::MIGRATE_FILTER)),
+                expression_attribute_names: Some(hashmap! {
+                    String::from("#old_token_field") => String::from(old_field),
+                }),
+                exclusive_start_key: start_key,
+                ..Default::default()
+            };
+
+            // Make a sync call with timeout
+            let result = self
+                .client
+                .scan(&scan_input)
+                .with_timeout(input.timeout)
+                .sync()
+                .map_err(DynaError::from);
+            let output = self.record(result)?;
+
+            for item in output.items.unwrap_or_default() {
+                let key = match item.get(&self.partition_key_field_name) {
+                    Some(key) => key.clone(),
+                    None => continue,
+                };
+                let token = match item.get(old_field) {
+                    Some(token) => token.clone(),
+                    None => continue,
+                };
+
+                self.throttle()?;
+
+                // Prepare update method input
+                let update_input = UpdateItemInput {
+                    table_name: self.table_name.clone(),
+                    update_expression: Some(String::from(expressions::MIGRATE_UPDATE)),
+                    condition_expression: Some(String::from(expressions::MIGRATE_CONDITION)),
+                    expression_attribute_names: Some(attribute_names.clone()),
+                    expression_attribute_values: Some(hashmap! {
+                        String::from(":token") => token,
+                    }),
+                    key: hashmap! {
+                        self.partition_key_field_name.clone() => key,
+                    },
+                    ..Default::default()
+                };
+
+                // Make a sync call with timeout
+                let result = self
+                    .client
+                    .update_item(&update_input)
+                    .with_timeout(input.timeout)
+                    .sync()
+                    .map_err(DynaError::from);
+
+                match self.record(result) {
+                    Ok(_) => migrated += 1,
+                    // The lock changed hands or was already migrated since we scanned it
+                    Err(ref err) if err.kind() == DynaErrorKind::LockAlreadyAcquired => {}
+                    Err(err) => return Err(err),
+                }
+            }
+
+            start_key = output.last_evaluated_key;
+            if start_key.is_none() {
+                break;
+            }
+        }
+
+        info!(
+            "migrated {} lock token(s) from '{}' to '{}' in table '{}'",
+            migrated, old_field, new_field, self.table_name
+        );
+
+        Ok(migrated)
+    }
+
+    /// Return the primary key of the lock item.
+    fn key(&self) -> HashMap<String, AttributeValue> {
+        hashmap! {
+            self.partition_key_field_name.clone() => AttributeValue {
+                s: Some(self.partition_key_value.clone()),
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Turn a failed acquisition into the most specific error we can determine.
+    ///
+    /// A failed condition check only tells us that one of the acquire conditions
+    /// did not hold, when the acquire condition has more branches than the fence
+    /// token comparison (or when asked to `diagnose` the failure) we read the lock
+    /// item back to find out which one failed.
+    fn explain_acquire_failure(
+        &mut self,
+        err: DynaError,
+        diagnose: bool,
+        epoch: Option<u64>,
+        timeout: Duration,
+    ) -> DynaError {
+        let fenced = self.epoch_field_name.is_some() && epoch.is_some();
+        let versioned = self.schema_version_field_name.is_some();
+
+        if err.kind() != DynaErrorKind::LockAlreadyAcquired
+            || (self.drain_field_name.is_none() && !fenced && !versioned && !diagnose)
+        {
+            return err;
+        }
+
+        let item = match self.get_lock_item(Some(true), timeout) {
+            Ok(item) => item,
+            Err(_) => return err,
+        };
+
+        let reason = self.contention_reason(item.as_ref(), epoch);
+        warn!(
+            "lock '{}' acquire condition failed: {}",
+            self.partition_key_value, reason
+        );
+
+        DynaError::new(self.contention_kind(item.as_ref(), epoch), Some(&reason))
+    }
+
+    /// Describe which branch of the acquire condition failed given the current lock item
+    /// and the `epoch` presented by the acquisition, if any.
+    fn contention_reason(
+        &self,
+        item: Option<&HashMap<String, AttributeValue>>,
+        epoch: Option<u64>,
+    ) -> String {
+        let item = match item {
+            Some(item) => item,
+            None => return String::from("lock item is absent"),
+        };
+
+        if let Some(version) = self.newer_schema_version(item) {
+            return format!(
+                "incompatible schema version, supported ({}) found ({})",
+                SCHEMA_VERSION, version
+            );
+        }
+
+        if let Some(ref drain_field) = self.drain_field_name {
+            if item.get(drain_field).and_then(|attr| attr.bool) == Some(true) {
+                return String::from("lock is being drained");
+            }
+        }
+
+        if let Some(stored_epoch) = self.newer_epoch(item, epoch) {
+            return format!(
+                "stale epoch, presented ({}) found ({})",
+                epoch.unwrap_or_default(),
+                stored_epoch
+            );
+        }
+
+        let stored_token = item
+            .get(&self.token_field_name)
+            .and_then(|attr| attr.s.as_ref());
+
+        match stored_token {
+            Some(token) if *token != self.current_token => {
+                let liveness = match self.require_attribute {
+                    Some(ref liveness_field) => {
+                        format!(" and liveness attribute '{}' is present", liveness_field)
+                    }
+                    None => String::new(),
+                };
+
+                format!(
+                    "fence token mismatch, expected ({}) found ({}){}",
+                    self.current_token, token, liveness
+                )
+            }
+            Some(_) => String::from("fence token matches, a custom condition failed"),
+            None => String::from("fence token is absent, a custom condition failed"),
+        }
+    }
+
+    /// Compare the time elapsed since the last acquisition on the monotonic clock
+    /// (`instant`) and on the wall clock (`now`), and record a jump beyond the configured
+    /// threshold.
+    fn check_clock(&mut self, instant: Instant, now: SystemTime) {
+        let threshold = match self.clock_jump_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+        let (anchor_instant, anchor_time) = match self.clock_anchor {
+            Some(anchor) if instant >= anchor.0 => anchor,
+            _ => return,
+        };
+
+        let monotonic = instant.duration_since(anchor_instant);
+        self.clock_jump = match now.duration_since(anchor_time) {
+            Ok(wall) if wall > monotonic + threshold => Some(ClockJump::Forward(wall - monotonic)),
+            Ok(wall) if wall + threshold < monotonic => Some(ClockJump::Backward(monotonic - wall)),
+            Ok(_) => None,
+            Err(err) => Some(ClockJump::Backward(monotonic + err.duration())),
+        };
+
+        if let Some(jump) = self.clock_jump {
+            warn!(
+                "lock '{}' wall clock jumped since the acquisition ({:?})",
+                self.partition_key_value, jump
+            );
+        }
+    }
+
+    /// Apply the maximum lease policy to the requested `lease`.
+    ///
+    /// A lease longer than `max_lease` is either clamped to it or, in strict mode, rejected
+    /// with `DynaErrorKind::InvalidLease`.
+    fn bound_lease(&self, lease: Duration) -> Result<Duration, DynaError> {
+        match self.max_lease {
+            Some(max_lease) if lease > max_lease => {
+                if self.max_lease_strict {
+                    return Err(DynaError::new(
+                        DynaErrorKind::InvalidLease,
+                        Some(&format!(
+                            "requested {}s, maximum {}s",
+                            lease.as_secs(),
+                            max_lease.as_secs()
+                        )),
+                    ));
+                }
+
+                warn!(
+                    "lock '{}' lease ({}s) clamped to the maximum lease ({}s)",
+                    self.partition_key_value,
+                    lease.as_secs(),
+                    max_lease.as_secs()
+                );
+                Ok(max_lease)
+            }
+            _ => Ok(lease),
+        }
+    }
+
+    /// Extend the acquire update expression with the renew count, if a field is configured.
+    fn add_renew_count_attribute(
+        &self,
+        renew_count: u32,
+        update_expression: &mut String,
+        attribute_names: &mut HashMap<String, String>,
+        attribute_values: &mut HashMap<String, AttributeValue>,
+    ) {
+        if let Some(ref renew_count_field_name) = self.renew_count_field_name {
+            update_expression.push_str(", ");
+            update_expression.push_str(expressions::RENEW_COUNT_UPDATE);
+            attribute_names.insert(
+                String::from("#renew_count_field"),
+                renew_count_field_name.clone(),
+            );
+            attribute_values.insert(
+                String::from(":renew_count"),
+                AttributeValue {
+                    n: Some(renew_count.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    /// Extend the acquire update expression to increment the generation, if configured.
+    fn add_generation_attribute(
+        &self,
+        update_expression: &mut String,
+        attribute_names: &mut HashMap<String, String>,
+        attribute_values: &mut HashMap<String, AttributeValue>,
+    ) {
+        if let Some(ref generation_field_name) = self.generation_field_name {
+            update_expression.push_str(", ");
+            update_expression.push_str(expressions::GENERATION_UPDATE);
+            attribute_names.insert(
+                String::from("#generation_field"),
+                generation_field_name.clone(),
+            );
+            attribute_values.insert(
+                String::from(":zero"),
+                AttributeValue {
+                    n: Some(String::from("0")),
+                    ..Default::default()
+                },
+            );
+            attribute_values.insert(
+                String::from(":one"),
+                AttributeValue {
+                    n: Some(String::from("1")),
+                    ..Default::default()
+                },
+            );
+        }
+    }
+
+    /// Return the metadata blob to store for `metadata`, encoded with the configured codec.
+    fn encode_metadata(&self, metadata: &[u8]) -> Result<Vec<u8>, DynaError> {
+        match self.metadata_codec {
+            Some(ref codec) => codec::encode_metadata(&**codec, metadata),
+            None => Ok(metadata.to_vec()),
+        }
+    }
+
+    /// Return the metadata stored in `blob`, decoded with the configured codec.
+    ///
+    /// Blobs that can't be decoded are returned as-is, so that a foreign or corrupted
+    /// blob doesn't prevent refreshing the lock.
+    fn decode_metadata(&self, blob: Vec<u8>) -> Vec<u8> {
+        let codec = match self.metadata_codec {
+            Some(ref codec) => codec,
+            None => return blob,
+        };
+
+        match codec::decode_metadata(&**codec, &blob) {
+            Ok(metadata) => metadata,
+            Err(err) => {
+                warn!(
+                    "lock '{}' metadata could not be decoded, keeping it as-is: {}",
+                    self.partition_key_value, err
+                );
+                blob
+            }
+        }
+    }
+
+    /// Return the generation stored on `item`, if a generation field is configured.
+    fn stored_generation(&self, item: &HashMap<String, AttributeValue>) -> Option<u64> {
+        item.get(self.generation_field_name.as_ref()?)
+            .and_then(|attr| attr.n.as_ref())
+            .and_then(|n| n.parse::<u64>().ok())
+    }
+
+    /// Extend the acquire update expression with the configured ISO-8601 attributes.
+    #[cfg(feature = "iso8601")]
+    fn add_iso8601_attributes(
+        &self,
+        acquired_at: SystemTime,
+        lease: Duration,
+        update_expression: &mut String,
+        attribute_names: &mut HashMap<String, String>,
+        attribute_values: &mut HashMap<String, AttributeValue>,
+    ) {
+        let fields = [
+            (
+                self.acquired_at_iso_field_name.as_ref(),
+                expressions::ACQUIRED_AT_ISO_UPDATE,
+                "#acquired_at_iso_field",
+                ":acquired_at_iso",
+                acquired_at,
+            ),
+            (
+                self.expires_at_iso_field_name.as_ref(),
+                expressions::EXPIRES_AT_ISO_UPDATE,
+                "#expires_at_iso_field",
+                ":expires_at_iso",
+                acquired_at + lease,
+            ),
+        ];
+
+        for &(field_name, action, name, value, time) in fields.iter() {
+            if let Some(field_name) = field_name {
+                update_expression.push_str(", ");
+                update_expression.push_str(action);
+                attribute_names.insert(String::from(name), field_name.clone());
+                attribute_values.insert(
+                    String::from(value),
+                    AttributeValue {
+                        s: Some(iso8601(time)),
+                        ..Default::default()
+                    },
+                );
+            }
+        }
+    }
+
+    /// Extend an acquire `condition` with the optional liveness and drain conditions.
+    fn extend_acquire_condition(
+        &self,
+        condition: String,
+        attribute_names: &mut HashMap<String, String>,
+        attribute_values: &mut HashMap<String, AttributeValue>,
+    ) -> String {
+        let mut condition = condition;
+
+        // A lock item without its liveness attribute is free to take
+        if let Some(ref liveness_field) = self.require_attribute {
+            condition = format!("{} OR {}", condition, expressions::LIVENESS_CONDITION);
+            attribute_names.insert(String::from("#liveness_field"), liveness_field.clone());
+        }
+
+        // Refuse to acquire the lock while it is being drained
+        if let Some(ref drain_field) = self.drain_field_name {
+            condition = format!("({}) AND ({})", condition, expressions::DRAIN_CONDITION);
+            attribute_names.insert(String::from("#drain_field"), drain_field.clone());
+            attribute_values.insert(
+                String::from(":drain_false"),
+                AttributeValue {
+                    bool: Some(false),
+                    ..Default::default()
+                },
+            );
+        }
+
+        // Leave lock items written by a newer schema version alone
+        if let Some(ref schema_version_field) = self.schema_version_field_name {
+            condition = format!(
+                "({}) AND ({})",
+                condition,
+                expressions::SCHEMA_VERSION_CONDITION
+            );
+            attribute_names.insert(
+                String::from("#schema_version_field"),
+                schema_version_field.clone(),
+            );
+            attribute_values.insert(
+                String::from(":schema_version"),
+                AttributeValue {
+                    n: Some(SCHEMA_VERSION.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        condition
+    }
+
+    /// Return the schema version stored on `item` if it is newer than `SCHEMA_VERSION`.
+    fn newer_schema_version(&self, item: &HashMap<String, AttributeValue>) -> Option<u64> {
+        let version = item
+            .get(self.schema_version_field_name.as_ref()?)
+            .and_then(|attr| attr.n.as_ref())
+            .and_then(|n| n.parse::<u64>().ok())?;
+
+        if version > SCHEMA_VERSION {
+            Some(version)
+        } else {
+            None
+        }
+    }
+
+    /// Return a `LockView` of the lock item `item` of `key`.
+    fn lock_view(&self, key: String, item: &HashMap<String, AttributeValue>) -> LockView {
+        let number = |field: &str| {
+            item.get(field)
+                .and_then(|attr| attr.n.as_ref())
+                .and_then(|n| n.parse::<u64>().ok())
+        };
+
+        LockView {
+            key: key,
+            token: item
+                .get(&self.token_field_name)
+                .and_then(|attr| attr.s.clone()),
+            lease: number(&self.duration_field_name).map(Duration::from_secs),
+            expires_at: number(&self.expires_at_field_name)
+                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs)),
+            metadata: item
+                .get(&self.metadata_field_name)
+                .and_then(|attr| attr.b.clone())
+                .map(|blob| self.decode_metadata(blob)),
+            reason: item
+                .get(&self.reason_field_name)
+                .and_then(|attr| attr.s.clone()),
+            host_identity: match self.host_identity_field_name {
+                Some(ref field) => item.get(field).and_then(|attr| attr.s.clone()),
+                None => None,
+            },
+        }
+    }
+
+    /// Leave the lease duration action and its placeholders out of the `update` expression
+    /// unless the driver writes the lease duration.
+    fn omit_duration(
+        &self,
+        update: String,
+        attribute_names: &mut HashMap<String, String>,
+        attribute_values: &mut HashMap<String, AttributeValue>,
+    ) -> String {
+        if self.write_duration {
+            return update;
+        }
+
+        match remove_set_action(&update, expressions::DURATION_UPDATE) {
+            Some(update) => {
+                attribute_names.remove("#duration_field");
+                attribute_values.remove(":lease");
+                update
+            }
+            None => update,
+        }
+    }
+
+    /// Generate a new random fence token with the configured generator, if any.
+    fn generate_token(&self) -> Result<String, DynaError> {
+        match self.token_generator {
+            Some(ref generator) => generator.generate(),
+            None => Ok(self.token_format.generate()),
+        }
+    }
+
+    /// Return the lease left at `now` according to the expiry attribute of `item`, if any.
+    fn stored_remaining(
+        &self,
+        item: &HashMap<String, AttributeValue>,
+        now: SystemTime,
+    ) -> Result<Option<Duration>, DynaError> {
+        match self.stored_expires_at(item) {
+            Some(expires_at) => {
+                let now_secs = now.duration_since(UNIX_EPOCH)?.as_secs();
+                Ok(Some(Duration::from_secs(
+                    expires_at.saturating_sub(now_secs),
+                )))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Return the absolute lease expiry stored on `item`, in seconds since the UNIX epoch.
+    fn stored_expires_at(&self, item: &HashMap<String, AttributeValue>) -> Option<u64> {
+        item.get(&self.expires_at_field_name)
+            .and_then(|attr| attr.n.as_ref())
+            .and_then(|n| n.parse::<u64>().ok())
+    }
+
+    /// Return the readers map of `item` when it holds readers whose leases all expired
+    /// by `now`, in which case an exclusive acquisition may clear them out.
+    fn expired_readers(
+        &self,
+        item: &HashMap<String, AttributeValue>,
+        now: SystemTime,
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynaError> {
+        let readers = match item
+            .get(&self.readers_field_name)
+            .and_then(|attr| attr.m.as_ref())
+        {
+            Some(readers) if !readers.is_empty() => readers,
+            _ => return Ok(None),
+        };
+
+        let now_secs = now.duration_since(UNIX_EPOCH)?.as_secs();
+        let expired = readers.values().all(|expires_at| {
+            expires_at
+                .n
+                .as_ref()
+                .and_then(|n| n.parse::<u64>().ok())
+                .map_or(false, |expires_at| expires_at <= now_secs)
+        });
+
+        Ok(if expired { Some(readers.clone()) } else { None })
+    }
+
+    /// Return true unless a required liveness attribute is missing from `item`.
+    fn is_live(&self, item: &HashMap<String, AttributeValue>) -> bool {
+        match self.require_attribute {
+            Some(ref liveness_field) => item.contains_key(liveness_field),
+            None => true,
+        }
+    }
+
+    /// Classify a failed acquire condition given the current lock item and the `epoch`
+    /// presented by the acquisition, if any.
+    fn contention_kind(
+        &self,
+        item: Option<&HashMap<String, AttributeValue>>,
+        epoch: Option<u64>,
+    ) -> DynaErrorKind {
+        if let Some(version) = item.and_then(|item| self.newer_schema_version(item)) {
+            warn!(
+                "lock '{}' has an incompatible schema version ({})",
+                self.partition_key_value, version
+            );
+            return DynaErrorKind::IncompatibleSchema;
+        }
+
+        if let (Some(item), Some(drain_field)) = (item, self.drain_field_name.as_ref()) {
+            if item.get(drain_field).and_then(|attr| attr.bool) == Some(true) {
+                warn!("lock '{}' is being drained", self.partition_key_value);
+                return DynaErrorKind::Draining;
+            }
+        }
+
+        if let Some(stored_epoch) = item.and_then(|item| self.newer_epoch(item, epoch)) {
+            warn!(
+                "lock '{}' has moved on to epoch ({})",
+                self.partition_key_value, stored_epoch
+            );
+            return DynaErrorKind::StaleEpoch;
+        }
+
+        DynaErrorKind::LockAlreadyAcquired
+    }
+
+    /// Return the epoch stored on `item` if it is newer than the presented `epoch`.
+    fn newer_epoch(
+        &self,
+        item: &HashMap<String, AttributeValue>,
+        epoch: Option<u64>,
+    ) -> Option<u64> {
+        let epoch = epoch?;
+        let stored_epoch = item
+            .get(self.epoch_field_name.as_ref()?)
+            .and_then(|attr| attr.n.as_ref())
+            .and_then(|n| n.parse::<u64>().ok())?;
+
+        if stored_epoch > epoch {
+            Some(stored_epoch)
+        } else {
+            None
+        }
+    }
+
+    /// Read the lock item of the configured partition key value, if it exists.
+    fn get_lock_item(
+        &mut self,
+        consistent_read: Option<bool>,
+        timeout: Duration,
+    ) -> Result<Option<HashMap<String, AttributeValue>>, DynaError> {
+        self.throttle()?;
+
+        // Prepare get method input
+        let get_input = GetItemInput {
+            consistent_read: consistent_read,
+            table_name: self.table_name.clone(),
+            key: self.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .client
+            .get_item(&get_input)
+            .with_timeout(timeout)
+            .sync()
+            .map_err(DynaError::from);
+        let output = self.record(result)?;
+
+        Ok(output.item)
+    }
+
+    /// Consult the circuit breaker and take a token from the rate limiter (if configured)
+    /// before issuing a DynamoDB call.
+    ///
+    /// An open circuit fails with `DynaErrorKind::CircuitOpen`. Depending on the configured
+    /// `RateLimitMode` this either sleeps until a token is available or fails with
+    /// `DynaErrorKind::RateLimited`.
+    fn throttle(&self) -> Result<(), DynaError> {
+        if let Some(ref circuit) = self.circuit_breaker {
+            if let Err(wait) = circuit.borrow_mut().check(Instant::now()) {
+                warn!("circuit is open, rejecting operation ({:?} left)", wait);
+                return Err(DynaError::new(DynaErrorKind::CircuitOpen, None));
+            }
+        }
+
+        let mode = self.rate_limit_mode;
+
+        if let Some(ref bucket) = self.rate_limiter {
+            let mut now = Instant::now();
+
+            loop {
+                // Bind the outcome first, so that the bucket isn't borrowed while sleeping
+                let taken = bucket.borrow_mut().try_take(now);
+                match taken {
+                    Ok(()) => break,
+                    Err(wait) => match mode {
+                        RateLimitMode::Block => {
+                            debug!("rate limit reached, sleeping for {:?}", wait);
+                            self.sleeper.sleep(wait);
+                            // A sleeper may return early, the wait is over regardless
+                            now = cmp::max(Instant::now(), now + wait);
+                        }
+                        RateLimitMode::Fail => {
+                            warn!("rate limit reached, rejecting operation");
+                            return Err(DynaError::new(DynaErrorKind::RateLimited, None));
+                        }
+                    },
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Feed the outcome of a DynamoDB call to the circuit breaker (if configured) and
+    /// return it.
+    ///
+    /// Only provider errors and throttling count as failures, any other outcome
+    /// (including lock contention) shows that DynamoDB is serving requests.
+    fn record<T>(&self, result: Result<T, DynaError>) -> Result<T, DynaError> {
+        if let Some(ref circuit) = self.circuit_breaker {
+            let mut circuit = circuit.borrow_mut();
+            match result {
+                Err(ref err)
+                    if err.kind() == DynaErrorKind::ProviderError
+                        || err.kind() == DynaErrorKind::Throttled =>
+                {
+                    circuit.record_failure(Instant::now())
+                }
+                _ => circuit.record_success(Instant::now()),
+            }
+        }
+
+        result
+    }
+}
+
+impl DynamoDbDriver<CredentialsProvider, RequestDispatcher> {
+    /// Initialize a new DynamoDbDriver structure with a default client for the
+    /// region named by `region`.
+    ///
+    /// The region is parsed with `parse_region`, an unknown region name results
+    /// in a `DynaErrorKind::InvalidConfiguration` error.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// use dynalock::dynamodb::{DynamoDbDriver, DynamoDbDriverInput};
+    ///
+    /// let input = DynamoDbDriverInput {
+    ///     table_name: String::from("locks_table"),
+    ///     partition_key_field_name: String::from("lock_id"),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let driver = DynamoDbDriver::new_in_region("custom:local:http://localhost:8000", &input)
+    ///     .unwrap();
+    /// ```
+    pub fn new_in_region(region: &str, input: &DynamoDbDriverInput) -> Result<Self, DynaError> {
+        let region = parse_region(region)?;
+
+        Ok(DynamoDbDriver::new(DynamoDbClient::simple(region), input))
+    }
+}
+
+/// Parse a region name (e.g., "us-east-1") into a `rusoto_core::Region`.
+///
+/// Custom endpoints, such as a DynamoDB Local instance, can be described with the
+/// `"custom:<name>:<endpoint>"` syntax (e.g., "custom:local:http://localhost:8000").
+pub fn parse_region(region: &str) -> Result<Region, DynaError> {
+    let mut parts = region.splitn(3, ':');
+
+    if parts.next() != Some("custom") {
+        return Ok(Region::from_str(region)?);
+    }
+
+    match (parts.next(), parts.next()) {
+        (Some(name), Some(endpoint)) if !name.is_empty() && !endpoint.is_empty() => {
+            Ok(Region::Custom {
+                name: name.to_string(),
+                endpoint: endpoint.to_string(),
+            })
+        }
+        _ => Err(DynaError::new(
+            DynaErrorKind::InvalidConfiguration,
+            Some("custom regions must be in the form 'custom:<name>:<endpoint>'"),
+        )),
+    }
+}
+
+/// Return the identity of this process as "<hostname>:<pid>".
+///
+/// The hostname is read from the kernel on Linux and from the `HOSTNAME` environment
+/// variable elsewhere, falling back to "unknown". The process ID is only available on
+/// Linux and is omitted elsewhere.
+pub fn default_host_identity() -> String {
+    let mut hostname = String::new();
+    let read = File::open("/proc/sys/kernel/hostname")
+        .and_then(|mut file| file.read_to_string(&mut hostname))
+        .is_ok();
+    if !read {
+        hostname = env::var("HOSTNAME").unwrap_or_default();
+    }
+
+    let mut hostname = hostname.trim().to_string();
+    if hostname.is_empty() {
+        hostname = String::from("unknown");
+    }
+
+    match fs::read_link("/proc/self") {
+        Ok(pid) => format!("{}:{}", hostname, pid.display()),
+        Err(_) => hostname,
+    }
+}
+
+/// Check that `token` is structured like a fence token rendered in `format`.
+///
+/// Only the structure is checked, i.e., the length, the position of the hyphens, the
+/// lowercase hexadecimal digits and the URN prefix. This lets resources reject
+/// malformed tokens before comparing them, but says nothing about whether the token was
+/// ever written to a lock item.
+pub fn validate_token_format(token: &str, format: TokenFormat) -> bool {
+    let is_hex = |byte: u8| byte.is_ascii_digit() || (b'a' <= byte && byte <= b'f');
+
+    let hyphenated = match format {
+        TokenFormat::Hyphenated => token,
+        TokenFormat::Urn if token.starts_with("urn:uuid:") => &token[9..],
+        TokenFormat::Urn => return false,
+        TokenFormat::Simple => return token.len() == 32 && token.bytes().all(is_hex),
+    };
+
+    hyphenated.len() == 36
+        && hyphenated.bytes().enumerate().all(|(i, byte)| match i {
+            8 | 13 | 18 | 23 => byte == b'-',
+            _ => is_hex(byte),
+        })
+}
+
+/// Remove the `action` of the `SET` clause of the update `expression`, along with its
+/// separating comma, or return `None` when the action isn't part of a list of actions.
+fn remove_set_action(expression: &str, action: &str) -> Option<String> {
+    let leading = format!("{}, ", action);
+    let trailing = format!(", {}", action);
+
+    if expression.contains(&leading) {
+        Some(expression.replacen(&leading, "", 1))
+    } else if expression.contains(&trailing) {
+        Some(expression.replacen(&trailing, "", 1))
+    } else {
+        None
+    }
+}
+
+/// The version of the lock item schema written by this crate, see
+/// `DynamoDbDriverInput::schema_version_field_name`.
+pub const SCHEMA_VERSION: u64 = 1;
+
+/// The maximum number of significant digits of a DynamoDB number.
+pub const MAX_NUMBER_PRECISION: usize = 38;
+
+/// The maximum number of integer digits of a DynamoDB number, whose magnitude must be
+/// below 10^126.
+pub const MAX_NUMBER_DIGITS: usize = 126;
+
+/// Check that `number` is a plain decimal integer DynamoDB can store as-is.
+///
+/// DynamoDB numbers hold up to `MAX_NUMBER_PRECISION` significant digits and must be
+/// below 10^126 in magnitude. Exponents are rejected as well, since they would mean the
+/// value was produced by an unexpected formatting path. `InvalidConfiguration` is
+/// returned for any other number.
+pub fn validate_number(number: &str) -> Result<(), DynaError> {
+    let digits = if number.starts_with('-') {
+        &number[1..]
+    } else {
+        number
+    };
+
+    let error = |reason: &str| {
+        Err(DynaError::new(
+            DynaErrorKind::InvalidConfiguration,
+            Some(&format!("number '{}' {}", number, reason)),
+        ))
+    };
+
+    if digits.is_empty() || !digits.bytes().all(|byte| byte.is_ascii_digit()) {
+        return error("is not a plain decimal integer");
+    }
+
+    // Leading zeros are not significant, trailing zeros of an integer only add magnitude
+    let magnitude = digits.len() - digits.bytes().take_while(|byte| *byte == b'0').count();
+    if digits.trim_matches('0').len() > MAX_NUMBER_PRECISION {
+        return error("exceeds the precision of DynamoDB numbers");
+    }
+    if magnitude > MAX_NUMBER_DIGITS {
+        return error("exceeds the magnitude of DynamoDB numbers");
+    }
+
+    Ok(())
+}
+
+/// Return a number attribute holding `value`, checked with `validate_number`.
+fn number_attribute<T: ToString>(value: T) -> Result<AttributeValue, DynaError> {
+    let number = value.to_string();
+    validate_number(&number)?;
+
+    Ok(AttributeValue {
+        n: Some(number),
+        ..Default::default()
+    })
+}
+
+/// Return the number of seconds since the UNIX epoch at `length` after `now_secs`.
+///
+/// `InvalidConfiguration` is returned when the result can't be represented, e.g., for a
+/// lease or a TTL of `u64::MAX` seconds.
+fn seconds_after(now_secs: u64, length: Duration) -> Result<u64, DynaError> {
+    now_secs.checked_add(length.as_secs()).ok_or_else(|| {
+        DynaError::new(
+            DynaErrorKind::InvalidConfiguration,
+            Some("lease or TTL end overflows the UNIX time in seconds"),
+        )
+    })
+}
+
+/// Format `time` as an ISO-8601 UTC timestamp with a one second resolution.
+#[cfg(feature = "iso8601")]
+fn iso8601(time: SystemTime) -> String {
+    DateTime::<Utc>::from(time)
+        .format("%Y-%m-%dT%H:%M:%SZ")
+        .to_string()
+}
+
+/// The maximum number of keys DynamoDB accepts in a single `BatchGetItem` call.
+pub const MAX_BATCH_GET_ITEMS: usize = 100;
+
+/// The number of times `DynamoDbDriver::peek_many` requests unprocessed keys again.
+pub const MAX_UNPROCESSED_RETRIES: u32 = 5;
+
+/// The delay in milliseconds before requesting unprocessed keys again the first time,
+/// doubled after every retry.
+const UNPROCESSED_RETRY_DELAY_MILLIS: u64 = 50;
+
+/// The maximum size in bytes of the metadata blob written on the lock item.
+///
+/// DynamoDB items are limited to 400KB, the cap leaves ample room for the key and
+/// the lock attributes. With a metadata codec configured, the cap applies to the
+/// encoded blob.
+pub const MAX_METADATA_SIZE: usize = 64 * 1024;
+
+/// The item collection size, in GB, above which `acquire_lock` logs a warning.
+///
+/// Item collections of tables with local secondary indexes are limited to 10 GB, writes
+/// beyond it fail with an `ItemCollectionSizeLimitExceeded` error.
+pub const ITEM_COLLECTION_WARN_GB: f64 = 8.0;
+
+/// The number of seconds in 24 hours.
+pub const DAY_SECONDS: u64 = 86400;
+
+/// The modes a lock can be held in, to use the lock as a readers-writer lock.
+///
+/// Shared holders are recorded in a map attribute on the lock item, from their reader
+/// token to the Unix time their lease expires at, and do not write a fence token, so
+/// any number of them can hold the lock while no exclusive holder does. An exclusive
+/// lock can only be acquired when the readers map is empty, or when every reader in it
+/// was seen expired by the last `refresh_lock`, in which case the readers are cleared
+/// out. A crashed reader thus blocks exclusive holders for no longer than its lease.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LockMode {
+    /// Hold the lock together with other shared holders.
+    Shared,
+    /// Hold the lock alone, the default mode of the Dynalock algorithm.
+    Exclusive,
+}
+
+impl Default for LockMode {
+    fn default() -> Self {
+        LockMode::Exclusive
+    }
+}
+
+/// The renderings of the UUID fence tokens.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TokenFormat {
+    /// The 36 characters hyphenated form (e.g., "67e55044-10b1-426f-9247-bb680e5fe0c8").
+    Hyphenated,
+    /// The 32 characters form without hyphens (e.g., "67e5504410b1426f9247bb680e5fe0c8").
+    Simple,
+    /// The URN form (e.g., "urn:uuid:67e55044-10b1-426f-9247-bb680e5fe0c8").
+    Urn,
+}
+
+impl TokenFormat {
+    /// Generate a new random fence token in this format.
+    pub fn generate(&self) -> String {
+        self.render(Uuid::new_v4())
+    }
+
+    /// Derive a fence token in this format from `name`, the same name always results in
+    /// the same (UUID v5) token.
+    pub fn derive(&self, name: &str) -> String {
+        self.render(Uuid::new_v5(&NAMESPACE_OID, name))
+    }
+
+    fn render(&self, uuid: Uuid) -> String {
+        match *self {
+            TokenFormat::Hyphenated => uuid.hyphenated().to_string(),
+            TokenFormat::Simple => uuid.simple().to_string(),
+            TokenFormat::Urn => uuid.urn().to_string(),
+        }
+    }
+}
+
+impl Default for TokenFormat {
+    fn default() -> Self {
+        TokenFormat::Hyphenated
+    }
+}
+
+/// A wall-clock jump relative to the monotonic clock, see `clock_jump_threshold`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ClockJump {
+    /// The wall clock fell behind the monotonic clock by the given duration.
+    Backward(Duration),
+    /// The wall clock ran ahead of the monotonic clock by the given duration.
+    Forward(Duration),
+}
+
+/// The fence token written by `DistLock::acquire_with_token`, required by
+/// `DistLock::release_with`.
+///
+/// The token is neither `Clone` nor `Copy`, so each acquisition can be released at most
+/// once through `release_with`.
+#[derive(Debug, Eq, PartialEq)]
+pub struct LockToken(String);
+
+impl LockToken {
+    /// Return the fence token as a string slice.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// The state of a lock item as read from the lock table, e.g., by
+/// `DynamoDbDriver::peek_many`.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct LockView {
+    /// The partition key value of the lock item.
+    pub key: String,
+    /// The fence token of the holder, if the lock item carries one.
+    pub token: Option<String>,
+    /// The lease duration written by the holder, if any.
+    pub lease: Option<Duration>,
+    /// The absolute expiry of the lease, if any.
+    pub expires_at: Option<SystemTime>,
+    /// The (decoded) metadata blob written by the holder, if any.
+    pub metadata: Option<Vec<u8>>,
+    /// The reason given by the holder, if any.
+    pub reason: Option<String>,
+    /// The host identity of the holder, if recorded.
+    pub host_identity: Option<String>,
+}
+
+impl LockView {
+    /// Return the lease left at `now` according to the absolute expiry, if any.
+    pub fn remaining_at(&self, now: SystemTime) -> Option<Duration> {
+        self.expires_at
+            .map(|expires_at| expires_at.duration_since(now).unwrap_or_default())
+    }
+}
+
+/// The strategies to compute the TTL written on the lock item, relative to the time
+/// of acquisition.
+///
+/// DynamoDB deletes lock items once their TTL passes (if TTL is enabled on the table),
+/// so the TTL must never be shorter than the lease.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum TtlMode {
+    /// A fixed duration from now, regardless of the lease (e.g., to keep an audit record
+    /// long after the lease ended).
+    RelativeToNow(Duration),
+    /// Exactly the lease duration, so the lock item is collected as soon as it expires.
+    EqualToLease,
+    /// The lease duration plus an extra grace period.
+    LeasePlus(Duration),
+}
+
+impl TtlMode {
+    /// Compute the TTL for a `lease` duration.
+    ///
+    /// Fails with a `DynaErrorKind::InvalidConfiguration` error when the TTL would be
+    /// shorter than the lease.
+    pub fn ttl(&self, lease: Duration) -> Result<Duration, DynaError> {
+        match *self {
+            TtlMode::RelativeToNow(ttl) if ttl < lease => Err(DynaError::new(
+                DynaErrorKind::InvalidConfiguration,
+                Some("TTL is shorter than the lock lease"),
+            )),
+            TtlMode::RelativeToNow(ttl) => Ok(ttl),
+            TtlMode::EqualToLease => Ok(lease),
+            TtlMode::LeasePlus(extra) => lease.checked_add(extra).ok_or_else(|| {
+                DynaError::new(
+                    DynaErrorKind::InvalidConfiguration,
+                    Some("TTL overflows the maximum duration"),
+                )
+            }),
+        }
+    }
+}
+
+impl Default for TtlMode {
+    fn default() -> Self {
+        TtlMode::RelativeToNow(Duration::from_secs(DAY_SECONDS * 7))
+    }
+}
+
+/// A structure that describes the inputs to `DynamoDbDriver::new`.
+///
+/// This structure's `Default` trait implementation provides sane default
+/// values. Only the `table_name` and the `partition_key_field_name` fields are
+/// required.
+#[derive(Debug, Clone)]
+pub struct DynamoDbDriverInput {
+    /// The DynamoDB lock table name to be used.
+    pub table_name: String,
+    /// The partition key field name.
+    pub partition_key_field_name: String,
+    /// The partition key value (default: "singleton"). This field should be provided
+    /// to use the lock driver on multiple shared resources, each represented by a
+    /// partition key value.
+    pub partition_key_value: String,
+    /// The fence token field name (default: "rvn").
+    pub token_field_name: String,
+    /// The lease duration field name (default: "duration").
+    pub duration_field_name: String,
+    /// Whether the lease duration is written on the lock item (default: true). Lock
+    /// items only carry the absolute lease expiry when disabled, which suits tables where
+    /// the lease is only enforced by the holders through `remaining`.
+    pub write_duration: bool,
+    /// The TTL field name (default: "ttl").
+    pub ttl_field_name: String,
+    /// How the TTL written on the lock item is computed (default: 7 days from now).
+    pub ttl_mode: TtlMode,
+    /// The absolute lease expiry field name (default: "lease_expires_at"). The field holds
+    /// the wall-clock time, in seconds since the UNIX epoch, at which the lease ends.
+    pub expires_at_field_name: String,
+    /// The field name of a human-readable ISO-8601 copy of the acquisition time
+    /// (default: None). It is only meant for browsing the table, the numeric fields remain
+    /// authoritative.
+    #[cfg(feature = "iso8601")]
+    pub acquired_at_iso_field_name: Option<String>,
+    /// The field name of a human-readable ISO-8601 copy of the lease expiry time
+    /// (default: None). It is only meant for browsing the table, the numeric fields remain
+    /// authoritative.
+    #[cfg(feature = "iso8601")]
+    pub expires_at_iso_field_name: Option<String>,
+    /// The maximum number of DynamoDB calls per second issued by the driver (default: None).
+    /// When set, a token bucket is consulted before every call to protect low-capacity tables.
+    pub max_ops_per_sec: Option<u32>,
+    /// What to do when `max_ops_per_sec` is exceeded (default: `RateLimitMode::Block`).
+    pub rate_limit_mode: RateLimitMode,
+    /// The circuit breaker thresholds (default: None). When set, repeated provider errors
+    /// open the circuit and operations fail fast with `DynaErrorKind::CircuitOpen` until
+    /// the cooldown elapses.
+    pub circuit: Option<CircuitConfig>,
+    /// The drain flag field name (default: None). When set, `acquire_lock` refuses to
+    /// acquire a lock item whose boolean drain attribute is `true`, which lets operators
+    /// cordon a resource while existing work finishes.
+    pub drain_field_name: Option<String>,
+    /// A liveness attribute name the lock depends on (default: None). When set, a lock
+    /// item missing this attribute (e.g., a session attribute written by a heartbeat and
+    /// removed by TTL) is treated as free even if a fence token lingers on it.
+    pub require_attribute: Option<String>,
+    /// The metadata blob field name (default: "metadata").
+    pub metadata_field_name: String,
+    /// The acquisition reason field name (default: "reason").
+    pub reason_field_name: String,
+    /// The host identity field name (default: None). When set, `acquire_lock` writes the
+    /// identity returned by `host_identity` on the lock item and `refresh_lock` reads it
+    /// back, so that operators investigating a split brain can see which hosts believed
+    /// they held the lock.
+    pub host_identity_field_name: Option<String>,
+    /// The source of the host identity, called once by `DynamoDbDriver::new` when a
+    /// `host_identity_field_name` is set (default: `default_host_identity`), e.g., a
+    /// function returning the pod name where the hostname is meaningless.
+    pub host_identity: fn() -> String,
+    /// The waiters queue field name used by `acquire_fifo` (default: "waiters").
+    pub waiters_field_name: String,
+    /// How the UUID fence tokens are rendered (default: `TokenFormat::Hyphenated`).
+    pub token_format: TokenFormat,
+    /// The shared lock readers map field name (default: "readers").
+    pub readers_field_name: String,
+    /// The lock intents map field name, used by waiters polling with an `intent_ttl`
+    /// (default: "intents").
+    pub intents_field_name: String,
+    /// The renew count field name (default: None). When set, `acquire_lock` writes the
+    /// number of in-place renewals of the current lease to the lock item.
+    pub renew_count_field_name: Option<String>,
+    /// The epoch field name (default: None). When set, an exclusive `acquire_lock`
+    /// presenting an `epoch` only succeeds if the stored epoch is not newer, and writes
+    /// the presented epoch on success.
+    pub epoch_field_name: Option<String>,
+    /// The generation field name (default: None). When set, the driver runs in generation
+    /// mode for clock-less environments: every exclusive `acquire_lock` increments the
+    /// stored generation, and a holder stays valid until a higher generation is stored.
+    ///
+    /// In this mode `remaining` always returns `None` and `has_lease` returns `false`,
+    /// since no clock is trusted, and validity must be checked with `is_current_holder`,
+    /// which compares the stored generation with ours. Helpers built on `remaining` follow
+    /// suit: `spawn_refresher` reports the lease valid as long as renewals succeed, and
+    /// `LockGuard::remaining` always returns `None`. TTL and expiry attributes are still
+    /// written for cleanup.
+    pub generation_field_name: Option<String>,
+    /// The schema version field name (default: None). When set, exclusive acquisitions
+    /// write `SCHEMA_VERSION` on the lock item, and lock items written by a newer schema
+    /// version are neither acquired nor refreshed: `DynaErrorKind::IncompatibleSchema` is
+    /// returned instead, so that older processors of a mixed-version fleet fail safe.
+    pub schema_version_field_name: Option<String>,
+    /// The longest lease the driver grants (default: None). Longer leases requested
+    /// through `DistLock::new` are clamped to it with a warning, so that a misconfigured
+    /// caller can't hold a lock for a day.
+    pub max_lease: Option<Duration>,
+    /// Whether a lease longer than `max_lease` is rejected with
+    /// `DynaErrorKind::InvalidLease` instead of being clamped (default: false).
+    pub max_lease_strict: bool,
+    /// How far the wall clock may drift from the monotonic clock between an acquisition
+    /// and a refresh before `refresh_lock` logs a warning and records a `ClockJump`
+    /// (default: None, i.e., not checked).
+    ///
+    /// The TTL and expiry attributes are computed from the wall clock while `remaining`
+    /// relies on the monotonic clock, so a wall clock jump (e.g., a VM resumed from a
+    /// snapshot) makes them disagree.
+    pub clock_jump_threshold: Option<Duration>,
+}
+
+impl Default for DynamoDbDriverInput {
+    fn default() -> Self {
+        DynamoDbDriverInput {
+            table_name: String::new(),
+            partition_key_field_name: String::new(),
+            partition_key_value: String::from("singleton"),
+            token_field_name: String::from("rvn"),
+            duration_field_name: String::from("duration"),
+            write_duration: true,
+            ttl_field_name: String::from("ttl"),
+            ttl_mode: TtlMode::default(),
+            expires_at_field_name: String::from("lease_expires_at"),
+            #[cfg(feature = "iso8601")]
+            acquired_at_iso_field_name: None,
+            #[cfg(feature = "iso8601")]
+            expires_at_iso_field_name: None,
+            max_ops_per_sec: None,
+            rate_limit_mode: RateLimitMode::Block,
+            circuit: None,
+            drain_field_name: None,
+            require_attribute: None,
+            metadata_field_name: String::from("metadata"),
+            reason_field_name: String::from("reason"),
+            host_identity_field_name: None,
+            host_identity: default_host_identity,
+            waiters_field_name: String::from("waiters"),
+            token_format: TokenFormat::Hyphenated,
+            readers_field_name: String::from("readers"),
+            intents_field_name: String::from("intents"),
+            renew_count_field_name: None,
+            epoch_field_name: None,
+            generation_field_name: None,
+            schema_version_field_name: None,
+            max_lease: None,
+            max_lease_strict: false,
+            clock_jump_threshold: None,
+        }
+    }
+}
+
+/// A struct to hold input variables for the `Locking` trait methods inputs.
+///
+/// The optional field `consistent_read` is not required to be set for the `refresh_lock`
+/// method, this field only exists for convenience.
+#[derive(Debug, Clone)]
+pub struct DynamoDbLockInput {
+    /// After how much time we timeout from a lock acquisition or refresh request to DynamoDB.
+    pub timeout: Duration,
+    /// Whether to carry out a strongly consistent read on the table within a refresh request.
+    pub consistent_read: Option<bool>,
+    /// Whether to follow a successful `acquire_lock` with a strongly consistent read that
+    /// verifies the stored fence token is the one we just wrote (default: false).
+    ///
+    /// The conditional `UpdateItem` call is already strongly consistent, so this is a
+    /// belt-and-suspenders check for environments that require an explicit read-your-write
+    /// confirmation. It costs an extra read per acquisition.
+    pub verify_after_acquire: bool,
+    /// An opaque blob written as a binary attribute on the lock item by `acquire_lock`
+    /// (default: None), e.g., a serialized leader-election payload. Once encoded with the
+    /// driver's metadata codec, if any, it must not exceed `MAX_METADATA_SIZE` bytes. When
+    /// set, the acquire update expression is extended with an extra `SET` action, so custom
+    /// acquire update expressions must end with a `SET` clause.
+    pub metadata: Option<Vec<u8>>,
+    /// A human-readable reason written as a string attribute on the lock item by
+    /// `acquire_lock` (default: None), e.g., "nightly-reindex job run 4821". It is meant
+    /// for operators reading the table and the logs. Like `metadata`, it is written with
+    /// an extra `SET` action.
+    pub reason: Option<String>,
+    /// Whether an eventually consistent `refresh_lock` reads the lock item twice and only
+    /// adopts the fence token if both reads agree (default: false).
+    ///
+    /// This reduces the chance of adopting a stale fence token right after another
+    /// processor acquired the lock, but doesn't eliminate it. Strongly consistent
+    /// refreshes are not affected.
+    pub refresh_confirm: bool,
+    /// Whether a contended `acquire_lock` reads the lock item back to log and report which
+    /// branch of the acquire condition failed (default: false). It costs an extra strongly
+    /// consistent read per contended acquisition.
+    pub diagnose_contention: bool,
+    /// Whether to acquire or release the lock in shared or exclusive mode
+    /// (default: `LockMode::Exclusive`).
+    pub mode: LockMode,
+    /// The monotonic time an acquired lease starts at (default: None, i.e., the time the
+    /// acquisition succeeded). Deterministic simulations pass their virtual time here, it
+    /// is returned as is by `acquire_lock`.
+    pub instant: Option<Instant>,
+    /// The wall-clock time the TTL and lease expiry attributes are computed from
+    /// (default: None, i.e., `SystemTime::now()`). Deterministic simulations pass their
+    /// virtual time here.
+    pub system_time: Option<SystemTime>,
+    /// The generation number an exclusive `acquire_lock` presents (default: None). When an
+    /// `epoch_field_name` is configured, a lock item carrying a newer epoch is refused with
+    /// `DynaErrorKind::StaleEpoch`, which keeps a deposed leader from acquiring again.
+    pub epoch: Option<u64>,
+    /// A key identifying one logical exclusive acquisition across retries (default: None).
+    ///
+    /// When set, the new fence token is derived from the key, the table and the resource
+    /// instead of being random, and the acquire condition also matches a stored token equal
+    /// to it. A retry whose previous attempt took effect but whose response was lost then
+    /// succeeds on its own write instead of contending with it. Use a fresh key (e.g., a
+    /// random UUID) for every logical acquisition, reusing a key keeps the fence token
+    /// from changing.
+    pub idempotency_key: Option<String>,
+    /// The lease a holder needs left for `refresh_lock` to succeed (default: None).
+    ///
+    /// When set and the refreshed lock item still carries our fence token, the lease left
+    /// according to the stored expiry attribute is compared against it, and
+    /// `DynaErrorKind::LeaseShortened` is returned when less is left. This lets holders
+    /// notice a lease shortened by another party on their next heartbeat.
+    pub min_remaining: Option<Duration>,
+    /// The TTL of the lock intent `refresh_lock` registers while another processor holds
+    /// the lock (default: None, i.e., no intent is registered).
+    ///
+    /// Waiters polling with an intent TTL count each other's intents and the retrying
+    /// helpers spread their polls accordingly, see the `intents` module. The TTL should
+    /// cover a few poll intervals, polls renew the intent once half of it went by.
+    pub intent_ttl: Option<Duration>,
+}
+
+impl DynamoDbLockInput {
+    /// Return the input with `timeout` replaced, e.g., to give a single call a longer
+    /// timeout with `base.clone().with_timeout(Duration::from_secs(2))`.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Return the input with `consistent_read` set to `consistent_read`.
+    pub fn with_consistent_read(mut self, consistent_read: bool) -> Self {
+        self.consistent_read = Some(consistent_read);
+        self
+    }
+
+    /// Return the overriding monotonic time, or the current time of `clock`.
+    fn instant_or_now(&self, clock: &Clock) -> Instant {
+        self.instant.unwrap_or_else(|| clock.now())
+    }
+
+    /// Return the overriding wall-clock time, or the current time.
+    fn system_time_or_now(&self) -> SystemTime {
+        self.system_time.unwrap_or_else(SystemTime::now)
+    }
+}
+
+impl Default for DynamoDbLockInput {
+    fn default() -> Self {
+        DynamoDbLockInput {
+            timeout: Duration::from_secs(10),
+            consistent_read: Some(false),
+            verify_after_acquire: false,
+            metadata: None,
+            reason: None,
+            refresh_confirm: false,
+            diagnose_contention: false,
+            mode: LockMode::Exclusive,
+            instant: None,
+            system_time: None,
+            epoch: None,
+            idempotency_key: None,
+            min_remaining: None,
+            intent_ttl: None,
+        }
+    }
+}
+
+/// The DynamoDB expressions sent by the driver.
+///
+/// Placeholders starting with `#` are attribute names and placeholders starting with
+/// `:` are attribute values, both are filled in by the driver for every request.
+pub mod expressions {
+    /// The default update expression of `acquire_lock`.
+    pub const ACQUIRE_UPDATE: &'static str = "SET #token_field = :new_token, \
+                                              #duration_field = :lease, \
+                                              #ttl_field = :ttl, \
+                                              #expires_at_field = :expires_at";
+    /// The action of the acquire and extend update expressions writing the lease duration.
+    pub const DURATION_UPDATE: &'static str = "#duration_field = :lease";
+    /// The default condition expression of `acquire_lock`.
+    pub const ACQUIRE_CONDITION: &'static str =
+        "attribute_not_exists(#token_field) OR #token_field = :cond_current_token";
+    /// The condition added to `acquire_lock` when a liveness attribute is required.
+    pub const LIVENESS_CONDITION: &'static str = "attribute_not_exists(#liveness_field)";
+    /// The condition added to `acquire_lock` when a drain flag is configured.
+    pub const DRAIN_CONDITION: &'static str =
+        "attribute_not_exists(#drain_field) OR #drain_field = :drain_false";
+    /// The action added to the acquire update expression when metadata is written.
+    pub const METADATA_UPDATE: &'static str = "#metadata_field = :metadata";
+    /// The action added to the acquire update expression when a reason is written.
+    pub const REASON_UPDATE: &'static str = "#reason_field = :reason";
+    /// The action added to the acquire update expression when a host identity is written.
+    pub const HOST_IDENTITY_UPDATE: &'static str = "#host_identity_field = :host_identity";
+    /// The action added to the acquire update expression to write the ISO-8601
+    /// acquisition time.
+    pub const ACQUIRED_AT_ISO_UPDATE: &'static str = "#acquired_at_iso_field = :acquired_at_iso";
+    /// The action added to the acquire update expression to write the ISO-8601 lease
+    /// expiry time.
+    pub const EXPIRES_AT_ISO_UPDATE: &'static str = "#expires_at_iso_field = :expires_at_iso";
+    /// The condition added to `acquire_lock` when a schema version field is configured.
+    pub const SCHEMA_VERSION_CONDITION: &'static str =
+        "attribute_not_exists(#schema_version_field) OR #schema_version_field <= :schema_version";
+    /// The action added to the acquire update expression to write the schema version.
+    pub const SCHEMA_VERSION_UPDATE: &'static str = "#schema_version_field = :schema_version";
+    /// The condition added to `acquire_lock` when an epoch is presented.
+    pub const EPOCH_CONDITION: &'static str =
+        "attribute_not_exists(#epoch_field) OR #epoch_field <= :epoch";
+    /// The action added to the acquire update expression when an epoch is presented.
+    pub const EPOCH_UPDATE: &'static str = "#epoch_field = :epoch";
+    /// The action added to the acquire update expression to write the renew count.
+    pub const RENEW_COUNT_UPDATE: &'static str = "#renew_count_field = :renew_count";
+    /// The action added to the acquire update expression to increment the generation.
+    pub const GENERATION_UPDATE: &'static str =
+        "#generation_field = if_not_exists(#generation_field, :zero) + :one";
+    /// The update expression appending a waiter to the queue of `acquire_fifo`.
+    pub const FIFO_ENQUEUE_UPDATE: &'static str =
+        "SET #waiters_field = list_append(if_not_exists(#waiters_field, :empty_list), :waiter)";
+    /// The condition added to `acquire_lock` in exclusive mode.
+    pub const EXCLUSIVE_CONDITION: &'static str =
+        "attribute_not_exists(#readers_field) OR size(#readers_field) = :no_readers";
+    /// The alternative to `EXCLUSIVE_CONDITION` when every reader was seen expired.
+    pub const STALE_READERS_CONDITION: &'static str = "#readers_field = :stale_readers";
+    /// The action added to the acquire update expression to clear out expired readers.
+    pub const READERS_CLEAR_UPDATE: &'static str = "#readers_field = :empty_readers";
+    /// The condition added to `acquire_lock` to match our own write when retried with an
+    /// idempotency key.
+    pub const IDEMPOTENT_CONDITION: &'static str = "#token_field = :new_token";
+    /// The update expression creating the readers map ahead of a first shared acquisition.
+    pub const READERS_CREATE_UPDATE: &'static str =
+        "SET #readers_field = if_not_exists(#readers_field, :empty_readers), \
+         #ttl_field = if_not_exists(#ttl_field, :ttl)";
+    /// The update expression of `acquire_lock` in shared mode and of `downgrade`.
+    pub const SHARED_ACQUIRE_UPDATE: &'static str =
+        "SET #readers_field.#reader = :reader_expires_at, #ttl_field = :ttl REMOVE #token_field";
+    /// The condition added to `acquire_lock` in shared mode and to `downgrade`, and the
+    /// condition of `release_lock` in shared mode.
+    pub const SHARED_CONDITION: &'static str = "attribute_exists(#readers_field)";
+    /// The update expression of `release_lock` in shared mode.
+    pub const SHARED_RELEASE_UPDATE: &'static str = "REMOVE #readers_field.#reader";
+    /// The update expression creating the intents map ahead of a first intent.
+    pub const INTENTS_CREATE_UPDATE: &'static str =
+        "SET #intents_field = if_not_exists(#intents_field, :empty_intents)";
+    /// The update expression registering or renewing a lock intent.
+    pub const INTENT_UPDATE: &'static str = "SET #intents_field.#intent = :intent_expires_at";
+    /// The update expression withdrawing a lock intent.
+    pub const INTENT_WITHDRAW_UPDATE: &'static str = "REMOVE #intents_field.#intent";
+    /// The condition expression of registering and withdrawing lock intents.
+    pub const INTENTS_CONDITION: &'static str = "attribute_exists(#intents_field)";
+    /// The update expression of `set_draining`.
+    pub const DRAIN_UPDATE: &'static str = "SET #drain_field = :drain";
+    /// The default update expression of `release_lock`.
+    pub const RELEASE_UPDATE: &'static str = "REMOVE #token_field";
+    /// The condition expression of `downgrade`.
+    pub const DOWNGRADE_CONDITION: &'static str = "#token_field = :cond_current_token";
+    /// The update expression of `refresh_and_extend`.
+    pub const EXTEND_UPDATE: &'static str = "SET #duration_field = :lease, \
+                                             #ttl_field = :ttl, \
+                                             #expires_at_field = :expires_at";
+    /// The condition expression of `refresh_and_extend`.
+    pub const EXTEND_CONDITION: &'static str = "#token_field = :cond_current_token";
+    /// The default condition expression of `release_lock`.
+    pub const RELEASE_CONDITION: &'static str =
+        "attribute_exists(#token_field) AND #token_field = :cond_current_token";
+    /// The scan filter expression of `sweep_expired`.
+    pub const SWEEP_FILTER: &'static str =
+        "attribute_exists(#token_field) AND #expires_at_field < :threshold";
+    /// The update expression of `sweep_expired`.
+    pub const SWEEP_UPDATE: &'static str = "REMOVE #token_field";
+    /// The condition expression of `sweep_expired`.
+    pub const SWEEP_CONDITION: &'static str =
+        "#token_field = :cond_current_token AND #expires_at_field < :threshold";
+    /// The scan filter expression of `find_orphaned`.
+    pub const ORPHANED_FILTER: &'static str =
+        "#expires_at_field < :now AND attribute_exists(#token_field)";
+    /// The scan filter expression of `migrate_token_field`.
+    pub const MIGRATE_FILTER: &'static str = "attribute_exists(#old_token_field)";
+    /// The update expression of `migrate_token_field`.
+    pub const MIGRATE_UPDATE: &'static str =
+        "SET #new_token_field = :token REMOVE #old_token_field";
+    /// The condition expression of `migrate_token_field`.
+    pub const MIGRATE_CONDITION: &'static str =
+        "#old_token_field = :token AND attribute_not_exists(#new_token_field)";
+
+    /// Placeholders that must appear in the acquire update or condition expressions.
+    pub const ACQUIRE_PLACEHOLDERS: &'static [&'static str] = &[
+        "#token_field",
+        "#duration_field",
+        "#ttl_field",
+        "#expires_at_field",
+        ":new_token",
+        ":lease",
+        ":ttl",
+        ":expires_at",
+        ":cond_current_token",
+    ];
+    /// Placeholders that must appear in the release update or condition expressions.
+    pub const RELEASE_PLACEHOLDERS: &'static [&'static str] =
+        &["#token_field", ":cond_current_token"];
+}
+
+/// The set of base expressions used by `acquire_lock` and `release_lock`.
+///
+/// The `Default` implementation uses the constants in the `expressions` module. Custom
+/// expressions can be installed with `DynamoDbDriver::with_expressions` to adapt the
+/// driver to unusual table policies. Optional conditions (e.g., the drain flag) are
+/// still appended by the driver to the acquire condition.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExpressionSet {
+    /// The update expression of `acquire_lock`.
+    pub acquire_update: String,
+    /// The condition expression of `acquire_lock`.
+    pub acquire_condition: String,
+    /// The update expression of `release_lock`.
+    pub release_update: String,
+    /// The condition expression of `release_lock`.
+    pub release_condition: String,
+}
+
+impl ExpressionSet {
+    /// Check that every placeholder the driver fills in is used by the expressions.
+    ///
+    /// DynamoDB rejects requests with unused attribute names or values, so a set missing
+    /// any of the placeholders listed in the `expressions` module is invalid.
+    pub fn validate(&self) -> Result<(), DynaError> {
+        let acquire = format!("{} {}", self.acquire_update, self.acquire_condition);
+        let release = format!("{} {}", self.release_update, self.release_condition);
+
+        let missing = expressions::ACQUIRE_PLACEHOLDERS
+            .iter()
+            .filter(|placeholder| !acquire.contains(*placeholder))
+            .chain(
+                expressions::RELEASE_PLACEHOLDERS
+                    .iter()
+                    .filter(|placeholder| !release.contains(*placeholder)),
+            )
+            .map(|placeholder| placeholder.to_string())
+            .collect::<Vec<String>>();
+
+        if !missing.is_empty() {
+            let msg = format!(
+                "expressions are missing placeholders: {}",
+                missing.join(", ")
+            );
+            return Err(DynaError::new(
+                DynaErrorKind::InvalidConfiguration,
+                Some(&msg),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for ExpressionSet {
+    fn default() -> Self {
+        ExpressionSet {
+            acquire_update: String::from(expressions::ACQUIRE_UPDATE),
+            acquire_condition: String::from(expressions::ACQUIRE_CONDITION),
+            release_update: String::from(expressions::RELEASE_UPDATE),
+            release_condition: String::from(expressions::RELEASE_CONDITION),
+        }
+    }
+}
+
+impl<P, D> Locking for DistLock<DynamoDbDriver<P, D>>
+where
+    P: ProvideAwsCredentials + 'static,
+    D: DispatchSignedRequest + 'static,
+{
+    type AcquireLockInputType = DynamoDbLockInput;
+    type RefreshLockInputType = DynamoDbLockInput;
+    type ReleaseLockInputType = DynamoDbLockInput;
+
+    fn acquire_lock(&mut self, input: &Self::AcquireLockInputType) -> Result<Instant, DynaError> {
+        let metadata_blob = match input.metadata {
+            Some(ref metadata) => Some(self.driver.encode_metadata(metadata)?),
+            None => None,
+        };
+        if metadata_blob.as_ref().map_or(0, |blob| blob.len()) > MAX_METADATA_SIZE {
+            return Err(DynaError::new(
+                DynaErrorKind::InvalidInput,
+                Some("lock metadata exceeds the maximum size"),
+            ));
+        }
+
+        // Enforce the lease policy, then reject TTL settings that would let DynamoDB
+        // collect a live lock
+        self.duration = self.driver.bound_lease(self.duration)?;
+        let ttl = self.driver.ttl_mode.ttl(self.duration)?;
+
+        if input.mode == LockMode::Shared {
+            return self.acquire_shared(input, ttl);
+        }
+
+        self.driver.throttle()?;
+
+        let new_token = match input.idempotency_key {
+            Some(ref key) => self.driver.token_format.derive(&format!(
+                "{}/{}/{}",
+                self.driver.table_name, self.driver.partition_key_value, key
+            )),
+            None => self.driver.generate_token()?,
+        };
+
+        // Use new token as current token if this is our first run
+        if self.driver.current_token.is_empty() {
+            self.driver.current_token = new_token.clone();
+        }
+
+        // Conditioning on a fence token we wrote renews our lease in place
+        let renewing = self.driver.owns_token;
+        let renew_count = if renewing {
+            self.driver.renew_count.saturating_add(1)
+        } else {
+            0
+        };
+
+        // Get time since EPOCH in seconds and add to it the TTL value and the lease
+        let now = input.system_time_or_now();
+        let now_secs = now.duration_since(UNIX_EPOCH)?.as_secs();
+        let ttl_secs = seconds_after(now_secs, ttl)?;
+        let expires_at_secs = seconds_after(now_secs, self.duration)?;
+
+        // Prepare the condition expression and its attributes
+        let mut condition_expression = self.driver.expressions.acquire_condition.clone();
+        if input.idempotency_key.is_some() {
+            // A retried acquisition may find the fence token its lost attempt wrote
+            condition_expression = format!(
+                "({}) OR {}",
+                condition_expression,
+                expressions::IDEMPOTENT_CONDITION
+            );
+        }
+        let mut attribute_names = hashmap! {
+            String::from("#token_field") => self.driver.token_field_name.clone(),
+            String::from("#duration_field") => self.driver.duration_field_name.clone(),
+            String::from("#ttl_field") => self.driver.ttl_field_name.clone(),
+            String::from("#expires_at_field") => self.driver.expires_at_field_name.clone(),
+        };
+        let mut attribute_values = hashmap! {
+            String::from(":new_token") => AttributeValue { s: Some(new_token.clone()), ..Default::default() },
+            String::from(":lease") => number_attribute(self.duration.as_secs())?,
+            String::from(":ttl") => number_attribute(ttl_secs)?,
+            String::from(":expires_at") => number_attribute(expires_at_secs)?,
+            String::from(":cond_current_token") => AttributeValue { s: Some(self.driver.current_token.clone()), ..Default::default() }
+        };
+
+        condition_expression = self.driver.extend_acquire_condition(
+            condition_expression,
+            &mut attribute_names,
+            &mut attribute_values,
+        );
+
+        // An exclusive lock can't be taken while readers hold it, unless all of them
+        // were seen expired and none renewed since
+        let exclusive_condition = match self.driver.stale_readers {
+            Some(ref stale_readers) => {
+                attribute_values.insert(
+                    String::from(":stale_readers"),
+                    AttributeValue {
+                        m: Some(stale_readers.clone()),
+                        ..Default::default()
+                    },
+                );
+                format!(
+                    "{} OR {}",
+                    expressions::EXCLUSIVE_CONDITION,
+                    expressions::STALE_READERS_CONDITION
+                )
+            }
+            None => String::from(expressions::EXCLUSIVE_CONDITION),
+        };
+        condition_expression = format!("({}) AND ({})", condition_expression, exclusive_condition);
+        attribute_names.insert(
+            String::from("#readers_field"),
+            self.driver.readers_field_name.clone(),
+        );
+        attribute_values.insert(String::from(":no_readers"), number_attribute(0)?);
+
+        // Store the metadata blob along with the lease
+        let mut update_expression = self.driver.omit_duration(
+            self.driver.expressions.acquire_update.clone(),
+            &mut attribute_names,
+            &mut attribute_values,
+        );
+        if let Some(metadata_blob) = metadata_blob {
+            update_expression = format!("{}, {}", update_expression, expressions::METADATA_UPDATE);
+            attribute_names.insert(
+                String::from("#metadata_field"),
+                self.driver.metadata_field_name.clone(),
+            );
+            attribute_values.insert(
+                String::from(":metadata"),
+                AttributeValue {
+                    b: Some(metadata_blob),
+                    ..Default::default()
+                },
+            );
+        }
+
+        // Store the reason for operators
+        if let Some(ref reason) = input.reason {
+            update_expression = format!("{}, {}", update_expression, expressions::REASON_UPDATE);
+            attribute_names.insert(
+                String::from("#reason_field"),
+                self.driver.reason_field_name.clone(),
+            );
+            attribute_values.insert(
+                String::from(":reason"),
+                AttributeValue {
+                    s: Some(reason.clone()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        // Store the identity of this host for split-brain investigations
+        if let (Some(field), Some(identity)) = (
+            self.driver.host_identity_field_name.as_ref(),
+            self.driver.host_identity.as_ref(),
+        ) {
+            update_expression = format!(
+                "{}, {}",
+                update_expression,
+                expressions::HOST_IDENTITY_UPDATE
+            );
+            attribute_names.insert(String::from("#host_identity_field"), field.clone());
+            attribute_values.insert(
+                String::from(":host_identity"),
+                AttributeValue {
+                    s: Some(identity.clone()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        // Refuse to acquire the lock on behalf of a deposed generation
+        if let (Some(epoch_field), Some(epoch)) =
+            (self.driver.epoch_field_name.clone(), input.epoch)
+        {
+            condition_expression = format!(
+                "({}) AND ({})",
+                condition_expression,
+                expressions::EPOCH_CONDITION
+            );
+            update_expression = format!("{}, {}", update_expression, expressions::EPOCH_UPDATE);
+            attribute_names.insert(String::from("#epoch_field"), epoch_field);
+            attribute_values.insert(
+                String::from(":epoch"),
+                AttributeValue {
+                    n: Some(epoch.to_string()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        // Clear out the expired readers
+        if self.driver.stale_readers.is_some() {
+            update_expression = format!(
+                "{}, {}",
+                update_expression,
+                expressions::READERS_CLEAR_UPDATE
+            );
+            attribute_values.insert(
+                String::from(":empty_readers"),
+                AttributeValue {
+                    m: Some(HashMap::new()),
+                    ..Default::default()
+                },
+            );
+        }
+
+        // Record the renew count for monitoring
+        self.driver.add_renew_count_attribute(
+            renew_count,
+            &mut update_expression,
+            &mut attribute_names,
+            &mut attribute_values,
+        );
+
+        // Move on to the next generation
+        self.driver.add_generation_attribute(
+            &mut update_expression,
+            &mut attribute_names,
+            &mut attribute_values,
+        );
+
+        // Tag the lock item with the schema it was written with, the condition already
+        // holds the attribute name and value
+        if self.driver.schema_version_field_name.is_some() {
+            update_expression = format!(
+                "{}, {}",
+                update_expression,
+                expressions::SCHEMA_VERSION_UPDATE
+            );
+        }
+
+        // Write human-readable copies of the lease timestamps
+        #[cfg(feature = "iso8601")]
+        self.driver.add_iso8601_attributes(
+            now,
+            self.duration,
+            &mut update_expression,
+            &mut attribute_names,
+            &mut attribute_values,
+        );
+
+        // Prepare update method input, the previous fence token tells takeovers apart
+        let update_input = UpdateItemInput {
+            table_name: self.driver.table_name.clone(),
+            update_expression: Some(update_expression),
+            condition_expression: Some(condition_expression),
+            expression_attribute_names: Some(attribute_names),
+            expression_attribute_values: Some(attribute_values),
+            key: self.driver.key(),
+            return_values: Some(String::from("ALL_OLD")),
+            return_item_collection_metrics: Some(String::from("SIZE")),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .driver
+            .client
+            .update_item(&update_input)
+            .with_timeout(input.timeout)
+            .sync()
+            .map_err(DynaError::from);
+
+        let output = match self.driver.record(result) {
+            Ok(output) => output,
+            Err(err) => {
+                let err = self.driver.explain_acquire_failure(
+                    err,
+                    input.diagnose_contention,
+                    input.epoch,
+                    input.timeout,
+                );
+                if err.kind() == DynaErrorKind::LockAlreadyAcquired {
+                    self.driver.contention.record_contended();
+                }
+
+                return Err(err);
+            }
+        };
+
+        ////////// After this point the lock clock starts //////////
+        let start = input.instant_or_now(&*self.driver.clock);
+
+        // Optionally confirm our write with a strongly consistent read
+        if input.verify_after_acquire {
+            let item = self.driver.get_lock_item(Some(true), input.timeout)?;
+            let stored_token = item
+                .as_ref()
+                .and_then(|item| item.get(&self.driver.token_field_name))
+                .and_then(|attr| attr.s.as_ref());
+
+            if stored_token != Some(&new_token) {
+                warn!(
+                    "lock '{}' write verification failed, expected token ({}) found ({:?})",
+                    self.driver.partition_key_value, new_token, stored_token
+                );
+                return Err(DynaError::new(DynaErrorKind::WriteVerificationFailed, None));
+            }
+        }
+
+        // Keep an eye on the item collection growing towards its size limit
+        self.driver.item_collection_size = output
+            .item_collection_metrics
+            .and_then(|metrics| metrics.size_estimate_range_gb)
+            .and_then(|range| {
+                if range.len() == 2 {
+                    Some((range[0], range[1]))
+                } else {
+                    None
+                }
+            });
+        if let Some((_, upper)) = self.driver.item_collection_size {
+            if upper >= ITEM_COLLECTION_WARN_GB {
+                warn!(
+                    "lock '{}' item collection is up to {}GB, close to the 10GB limit",
+                    self.driver.partition_key_value, upper
+                );
+            }
+        }
+
+        // The generation we wrote follows the one we replaced
+        if self.driver.generation_field_name.is_some() {
+            let previous = output
+                .attributes
+                .as_ref()
+                .and_then(|item| self.driver.stored_generation(item))
+                .unwrap_or(0);
+            self.driver.generation = Some(previous + 1);
+        }
+
+        // A fence token we didn't write was left behind by a crashed or expired holder
+        let previous_token = output
+            .attributes
+            .and_then(|mut item| item.remove(&self.driver.token_field_name))
+            .and_then(|attr| attr.s);
+        self.driver.taken_over_token = match previous_token {
+            Some(ref token)
+                if *token != new_token && (!renewing || *token != self.driver.current_token) =>
+            {
+                warn!(
+                    "lock '{}' taken over from token ({})",
+                    self.driver.partition_key_value, token
+                );
+                previous_token.clone()
+            }
+            _ => None,
+        };
+
+        // Lock acquired successfully, record the new fence token
+        info!(
+            "lock '{}' acquired successfully, current token ({}) new token ({}) lease ({}s) \
+             reason ({})",
+            self.driver.partition_key_value,
+            self.driver.current_token,
+            new_token,
+            self.duration.as_secs(),
+            input
+                .reason
+                .as_ref()
+                .map_or("none", |reason| reason.as_str())
+        );
+        self.driver.current_token = new_token.clone();
+        self.driver.owns_token = true;
+        self.driver.observed_metadata = input.metadata.clone();
+        self.driver.observed_reason = input.reason.clone();
+        self.driver.observed_host_identity = self.driver.host_identity.clone();
+        self.driver.clock_anchor = Some((start, now));
+        self.driver.renew_count = renew_count;
+        self.driver.expected_expires_at = Some(expires_at_secs);
+        self.driver.stale_readers = None;
+        self.driver.contention.record_acquired(start, renewing);
+
+        // Our intent is fulfilled, left behind it would slow the other waiters down
+        if self.driver.intent.is_some() {
+            if let Err(err) = self.withdraw_intent(input) {
+                warn!(
+                    "lock '{}' failed to withdraw its intent: {}",
+                    self.driver.partition_key_value, err
+                );
+            }
+        }
+
+        Ok(start)
+    }
+
+    fn refresh_lock(&mut self, input: &Self::RefreshLockInputType) -> Result<(), DynaError> {
+        self.refresh_and_view(input).map(|_| ())
+    }
+
+    fn release_lock(&mut self, input: &Self::ReleaseLockInputType) -> Result<(), DynaError> {
+        if input.mode == LockMode::Shared {
+            return self.release_shared(input);
+        }
+
+        self.driver.throttle()?;
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.driver.table_name.clone(),
+            update_expression: Some(self.driver.expressions.release_update.clone()),
+            condition_expression: Some(self.driver.expressions.release_condition.clone()),
+            expression_attribute_names: Some(hashmap! {
+                String::from("#token_field") => self.driver.token_field_name.clone(),
+            }),
+            expression_attribute_values: Some(hashmap! {
+                String::from(":cond_current_token") => AttributeValue { s: Some(self.driver.current_token.clone()), ..Default::default() }
+            }),
+            key: hashmap! {
+                self.driver.partition_key_field_name.clone() => AttributeValue {
+                    s: Some(self.driver.partition_key_value.clone()),
+                    ..Default::default()
+                },
+            },
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .driver
+            .client
+            .update_item(&update_input)
+            .with_timeout(input.timeout)
+            .sync()
+            .map_err(DynaError::from);
+        self.driver.record(result)?;
+
+        // Lock released successfully, clear the fence token
+        info!(
+            "lock '{}' successfully released for token ({})",
+            self.driver.partition_key_value, self.driver.current_token
+        );
+        self.driver.current_token.clear();
+        self.driver.owns_token = false;
+        self.driver.observed_metadata = None;
+        self.driver.observed_reason = None;
+        self.driver.observed_host_identity = None;
+        self.driver.renew_count = 0;
+        self.driver.expected_expires_at = None;
+        self.driver
+            .contention
+            .record_released(input.instant_or_now(&*self.driver.clock));
+
+        Ok(())
+    }
+
+    fn remaining(&self, instant: Instant) -> Option<Duration> {
+        // No clock is trusted in generation mode
+        if self.driver.generation_field_name.is_some() {
+            return None;
+        }
+
+        self.duration
+            .checked_sub(self.driver.clock.elapsed(instant))
+    }
+
+    fn observed_token(&self) -> Option<String> {
+        if self.driver.current_token.is_empty() {
+            None
+        } else {
+            Some(self.driver.current_token.clone())
+        }
+    }
+
+    fn has_lease(&self) -> bool {
+        self.driver.generation_field_name.is_none()
+    }
+
+    fn observed_contenders(&self) -> u32 {
+        self.driver.observed_intents
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "dynamodb"
+    }
+}
+
+impl<P, D> DistLock<DynamoDbDriver<P, D>>
+where
+    P: ProvideAwsCredentials + 'static,
+    D: DispatchSignedRequest + 'static,
+{
+    /// Derive the current `LockState` from the fence token we know of and the lease
+    /// validity.
+    ///
+    /// The `acquired` parameter is the `Instant` returned by the last successful
+    /// `acquire_lock` call, if any. A lock we wrote the fence token for is reported
+    /// as `Expired` when no `acquired` instant is given, since its lease can't be
+    /// verified.
+    pub fn state(&self, acquired: Option<Instant>) -> LockState {
+        if self.driver.current_token.is_empty() {
+            return LockState::Unlocked;
+        }
+
+        if !self.driver.owns_token {
+            return LockState::Observed;
+        }
+
+        match acquired.and_then(|instant| self.remaining(instant)) {
+            Some(_) => LockState::Held,
+            None => LockState::Expired,
+        }
+    }
+
+    /// Return a `LockSnapshot` of the lock, computing the lease left from the `acquired`
+    /// instant returned by the last successful `acquire_lock` call, if any. No I/O is made.
+    pub fn snapshot(&self, acquired: Option<Instant>) -> LockSnapshot {
+        let token = if self.driver.current_token.is_empty() {
+            None
+        } else {
+            Some(self.driver.current_token.clone())
+        };
+        let remaining = if self.driver.owns_token {
+            acquired.and_then(|instant| self.remaining(instant))
+        } else {
+            None
+        };
+
+        LockSnapshot {
+            provider: self.provider_name(),
+            resource: self.driver.partition_key_value.clone(),
+            token: token,
+            owned: self.driver.owns_token,
+            lease_millis: as_millis(self.duration),
+            remaining_millis: remaining.map(as_millis),
+            renew_count: self.driver.renew_count,
+        }
+    }
+
+    /// Return whether `other` guards the same resource, i.e., targets the same lock item.
+    ///
+    /// Two locks on the same lock item contend with each other even within a process, which
+    /// shows up as a process stealing its own lock. The table name, the partition key field
+    /// name and the partition key value are compared; the clients' regions are not.
+    pub fn same_resource<P2, D2>(&self, other: &DistLock<DynamoDbDriver<P2, D2>>) -> bool
+    where
+        P2: ProvideAwsCredentials,
+        D2: DispatchSignedRequest,
+    {
+        self.driver.table_name == other.driver.table_name
+            && self.driver.partition_key_field_name == other.driver.partition_key_field_name
+            && self.driver.partition_key_value == other.driver.partition_key_value
+    }
+
+    /// Acquire the lock like `acquire_lock` and return the fence token written along with
+    /// the `Instant` the lease starts at.
+    ///
+    /// The `LockToken` must be handed back to `release_with` to release the lock, which
+    /// makes the pairing of an acquisition with its release explicit. Only exclusive
+    /// acquisitions write a fence token, `DynaErrorKind::InvalidInput` is returned for
+    /// `LockMode::Shared`.
+    pub fn acquire_with_token(
+        &mut self,
+        input: &DynamoDbLockInput,
+    ) -> Result<(Instant, LockToken), DynaError> {
+        if input.mode == LockMode::Shared {
+            return Err(DynaError::new(
+                DynaErrorKind::InvalidInput,
+                Some("shared acquisitions don't write a fence token"),
+            ));
+        }
+
+        let instant = self.acquire_lock(input)?;
+
+        Ok((instant, LockToken(self.driver.current_token.clone())))
+    }
+
+    /// Release the lock acquired by the `acquire_with_token` call that returned `token`.
+    ///
+    /// `DynaErrorKind::TokenMismatch` is returned without releasing anything when the
+    /// fence token we know of is no longer `token` or we don't hold the lock anymore, e.g.,
+    /// because a `refresh_lock` call in between observed another processor's fence token.
+    pub fn release_with(
+        &mut self,
+        token: LockToken,
+        input: &DynamoDbLockInput,
+    ) -> Result<(), DynaError> {
+        if !self.driver.owns_token || self.driver.current_token != token.0 {
+            return Err(DynaError::new(
+                DynaErrorKind::TokenMismatch,
+                Some(&format!(
+                    "expected token ({}), found ({})",
+                    token.0, self.driver.current_token
+                )),
+            ));
+        }
+
+        self.release_lock(input)
+    }
+
+    /// Check with a strongly consistent read whether we still hold the lock.
+    ///
+    /// Unlike `refresh_lock` this does not update the fence token we know of, it only
+    /// answers whether the stored fence token is the one we wrote. `Ok(false)` is
+    /// returned when we never acquired the lock, the lock item is absent or free, or
+    /// another processor wrote its own fence token. The lease validity is not checked,
+    /// but in generation mode a stored generation other than ours results in `Ok(false)`.
+    pub fn is_current_holder(&mut self, input: &DynamoDbLockInput) -> Result<bool, DynaError> {
+        if !self.driver.owns_token || self.driver.current_token.is_empty() {
+            return Ok(false);
+        }
+
+        let item = match self.driver.get_lock_item(Some(true), input.timeout)? {
+            Some(item) => item,
+            None => return Ok(false),
+        };
+
+        if !self.driver.is_live(&item) {
+            return Ok(false);
+        }
+
+        // A higher generation means another processor acquired the lock since
+        if self.driver.generation_field_name.is_some()
+            && self.driver.stored_generation(&item) != self.driver.generation
+        {
+            return Ok(false);
+        }
+
+        let stored_token = item
+            .get(&self.driver.token_field_name)
+            .and_then(|attr| attr.s.as_ref());
+
+        Ok(stored_token == Some(&self.driver.current_token))
+    }
+
+    /// Refresh the lock like `refresh_lock` and return a `LockView` of the lock item it
+    /// observed, or `None` when there is no lock item.
+    ///
+    /// This is the recommended way to refresh, as the token, lease, expiry, metadata and
+    /// holder identity observed are returned instead of having to be read back from the
+    /// driver, e.g., to wait out the lease of the holder before trying to acquire. A lock
+    /// item missing its liveness attribute is considered free and reported as `None`.
+    /// When the two reads of `refresh_confirm` disagree the fence token we know of is
+    /// kept, and the view of the second read is returned.
+    pub fn refresh_and_view(
+        &mut self,
+        input: &DynamoDbLockInput,
+    ) -> Result<Option<LockView>, DynaError> {
+        self.driver.check_clock(
+            input.instant_or_now(&*self.driver.clock),
+            input.system_time_or_now(),
+        );
+        self.driver.external_extension = None;
+        self.driver.stale_readers = None;
+        self.driver.observed_intents = 0;
+
+        // Read the lock item with the requested consistency
+        let item = self
+            .driver
+            .get_lock_item(input.consistent_read, input.timeout)?;
+
+        // Read the lock item again and keep our fence token unless both reads agree
+        if input.refresh_confirm && input.consistent_read != Some(true) {
+            let confirmation = self
+                .driver
+                .get_lock_item(input.consistent_read, input.timeout)?;
+
+            let token_field = &self.driver.token_field_name;
+            let first = item.as_ref().and_then(|item| item.get(token_field));
+            let second = confirmation.as_ref().and_then(|item| item.get(token_field));
+
+            if first != second {
+                warn!(
+                    "lock '{}' refresh reads disagree, keeping token ({})",
+                    self.driver.partition_key_value, self.driver.current_token
+                );
+                let key = self.driver.partition_key_value.clone();
+                return Ok(confirmation.map(|item| self.driver.lock_view(key, &item)));
+            }
+        }
+
+        // A lock item without its liveness attribute is considered free
+        if item.is_some() && !self.driver.is_live(item.as_ref().unwrap()) {
+            info!(
+                "lock '{}' is missing its liveness attribute, treating it as free",
+                self.driver.partition_key_value
+            );
+            self.driver.current_token.clear();
+            self.driver.owns_token = false;
+            self.driver.observed_metadata = None;
+            self.driver.observed_reason = None;
+            self.driver.observed_host_identity = None;
+            self.driver.contention.record_lost();
+
+            return Ok(None);
+        }
+
+        // A lock item written by a newer schema version can't be interpreted
+        if let Some(version) = item
+            .as_ref()
+            .and_then(|item| self.driver.newer_schema_version(item))
+        {
+            let msg = format!("supported ({}) found ({})", SCHEMA_VERSION, version);
+            warn!(
+                "lock '{}' has an incompatible schema version, {}",
+                self.driver.partition_key_value, msg
+            );
+            return Err(DynaError::new(
+                DynaErrorKind::IncompatibleSchema,
+                Some(&msg),
+            ));
+        }
+
+        // A lock item was found
+        if item.is_some() {
+            self.driver.observed_metadata = item
+                .as_ref()
+                .unwrap()
+                .get(&self.driver.metadata_field_name)
+                .and_then(|attr| attr.b.clone())
+                .map(|blob| self.driver.decode_metadata(blob));
+            self.driver.observed_reason = item
+                .as_ref()
+                .unwrap()
+                .get(&self.driver.reason_field_name)
+                .and_then(|attr| attr.s.clone());
+            self.driver.observed_host_identity = match self.driver.host_identity_field_name {
+                Some(ref field) => item
+                    .as_ref()
+                    .unwrap()
+                    .get(field)
+                    .and_then(|attr| attr.s.clone()),
+                None => None,
+            };
+
+            // Remember the readers an exclusive acquisition may clear out
+            self.driver.stale_readers = self
+                .driver
+                .expired_readers(item.as_ref().unwrap(), input.system_time_or_now())?;
+
+            // Count the other waiters for the retrying helpers to back off accordingly
+            let now_secs = input
+                .system_time_or_now()
+                .duration_since(UNIX_EPOCH)?
+                .as_secs();
+            self.driver.observed_intents =
+                self.driver.live_intents(item.as_ref().unwrap(), now_secs);
+
+            let attr = item.as_ref().unwrap().get(&self.driver.token_field_name);
+
+            if attr.is_some() {
+                let token = attr.unwrap().s.as_ref().unwrap().clone();
+
+                // Somebody else wrote the lock item since we last touched it
+                if token != self.driver.current_token {
+                    self.driver.owns_token = false;
+                    self.driver.expected_expires_at = None;
+                    self.driver.contention.record_lost();
+                }
+
+                self.driver.current_token = token;
+                info!(
+                    "lock '{}' refreshed successful, found new token ({})",
+                    self.driver.partition_key_value, self.driver.current_token
+                );
+            }
+
+            // Let the holder know another writer of our fence token extended the lease
+            if let (true, Some(expected)) =
+                (self.driver.owns_token, self.driver.expected_expires_at)
+            {
+                match self.driver.stored_expires_at(item.as_ref().unwrap()) {
+                    Some(stored) if stored > expected => {
+                        let extension = Duration::from_secs(stored - expected);
+                        warn!(
+                            "lock '{}' lease was extended by another writer of token ({}) by {}s",
+                            self.driver.partition_key_value,
+                            self.driver.current_token,
+                            extension.as_secs()
+                        );
+                        self.driver.external_extension = Some(extension);
+                    }
+                    _ => {}
+                }
+            }
+
+            // Let the holder know the stored lease no longer leaves the time it needs
+            if let (true, Some(min_remaining)) = (self.driver.owns_token, input.min_remaining) {
+                let now = input.system_time_or_now();
+                let item = item.as_ref().unwrap();
+
+                if let Some(left) = self.driver.stored_remaining(item, now)? {
+                    if left < min_remaining {
+                        let msg = format!(
+                            "{}s left, {}s required",
+                            left.as_secs(),
+                            min_remaining.as_secs()
+                        );
+                        warn!(
+                            "lock '{}' lease shortened, {}",
+                            self.driver.partition_key_value, msg
+                        );
+                        return Err(DynaError::new(DynaErrorKind::LeaseShortened, Some(&msg)));
+                    }
+                }
+            }
+        }
+
+        // Let the other waiters know we wait for the lock too
+        if let (Some(ttl), Some(item)) = (input.intent_ttl, item.as_ref()) {
+            if !self.driver.owns_token && item.contains_key(&self.driver.token_field_name) {
+                let has_intents = item.contains_key(&self.driver.intents_field_name);
+                self.register_intent(has_intents, ttl, input)?;
+            }
+        }
+
+        let key = self.driver.partition_key_value.clone();
+        Ok(item.map(|item| self.driver.lock_view(key, &item)))
+    }
+
+    /// Extend the lease of a lock we believe we hold in a single round trip.
+    ///
+    /// Unlike renewing with `acquire_lock`, the stored fence token is kept and only the
+    /// lease, TTL and expiry attributes are bumped, on the condition that the stored fence
+    /// token is still the one we wrote. On success the `Instant` the extended lease starts
+    /// at is returned. When the condition fails the lock item is read like `refresh_lock`
+    /// does, so that the fence token of the new holder is known, and
+    /// `DynaErrorKind::LockAlreadyAcquired` is returned.
+    ///
+    /// Pull-based workers extending the lease per processed item should use `touch`.
+    pub fn refresh_and_extend(&mut self, input: &DynamoDbLockInput) -> Result<Instant, DynaError> {
+        if !self.driver.owns_token || self.driver.current_token.is_empty() {
+            return Err(DynaError::new(
+                DynaErrorKind::LockAlreadyAcquired,
+                Some("lock is not held"),
+            ));
+        }
+
+        self.duration = self.driver.bound_lease(self.duration)?;
+        let ttl = self.driver.ttl_mode.ttl(self.duration)?;
+
+        self.driver.throttle()?;
+
+        let renew_count = self.driver.renew_count.saturating_add(1);
+
+        // Get time since EPOCH in seconds and add to it the TTL value and the lease
+        let now = input.system_time_or_now();
+        let now_secs = now.duration_since(UNIX_EPOCH)?.as_secs();
+        let ttl_secs = seconds_after(now_secs, ttl)?;
+        let expires_at_secs = seconds_after(now_secs, self.duration)?;
+
+        let mut attribute_names = hashmap! {
+            String::from("#token_field") => self.driver.token_field_name.clone(),
+            String::from("#duration_field") => self.driver.duration_field_name.clone(),
+            String::from("#ttl_field") => self.driver.ttl_field_name.clone(),
+            String::from("#expires_at_field") => self.driver.expires_at_field_name.clone(),
+        };
+        let mut attribute_values = hashmap! {
+            String::from(":lease") => number_attribute(self.duration.as_secs())?,
+            String::from(":ttl") => number_attribute(ttl_secs)?,
+            String::from(":expires_at") => number_attribute(expires_at_secs)?,
+            String::from(":cond_current_token") => AttributeValue { s: Some(self.driver.current_token.clone()), ..Default::default() }
+        };
+        let mut update_expression = self.driver.omit_duration(
+            String::from(expressions::EXTEND_UPDATE),
+            &mut attribute_names,
+            &mut attribute_values,
+        );
+
+        self.driver.add_renew_count_attribute(
+            renew_count,
+            &mut update_expression,
+            &mut attribute_names,
+            &mut attribute_values,
+        );
+
+        // Write human-readable copies of the lease timestamps
+        #[cfg(feature = "iso8601")]
+        self.driver.add_iso8601_attributes(
+            now,
+            self.duration,
+            &mut update_expression,
+            &mut attribute_names,
+            &mut attribute_values,
+        );
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.driver.table_name.clone(),
+            update_expression: Some(update_expression),
+            condition_expression: Some(String::from(expressions::EXTEND_CONDITION)),
+            expression_attribute_names: Some(attribute_names),
+            expression_attribute_values: Some(attribute_values),
+            key: self.driver.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .driver
+            .client
+            .update_item(&update_input)
+            .with_timeout(input.timeout)
+            .sync()
+            .map_err(DynaError::from);
+
+        match self.driver.record(result) {
+            Ok(_) => {}
+            Err(ref err) if err.kind() == DynaErrorKind::LockAlreadyAcquired => {
+                info!(
+                    "lock '{}' is no longer held with token ({}), reading the lock item",
+                    self.driver.partition_key_value, self.driver.current_token
+                );
+                self.refresh_lock(input)?;
+                self.driver.owns_token = false;
+                self.driver.renew_count = 0;
+
+                return Err(DynaError::new(
+                    DynaErrorKind::LockAlreadyAcquired,
+                    Some("lock was lost before it could be extended"),
+                ));
+            }
+            Err(err) => return Err(err),
+        }
+
+        ////////// After this point the lock clock starts //////////
+        let start = input.instant_or_now(&*self.driver.clock);
+
+        info!(
+            "lock '{}' extended for token ({}) lease ({}s)",
+            self.driver.partition_key_value,
+            self.driver.current_token,
+            self.duration.as_secs()
+        );
+        self.driver.renew_count = renew_count;
+        self.driver.expected_expires_at = Some(expires_at_secs);
+
+        Ok(start)
+    }
+
+    /// Extend the lease after a unit of work was processed and return the `Instant` the
+    /// extended lease starts at, like a visibility timeout extension.
+    ///
+    /// This is `refresh_and_extend` for pull-based workers, meant to be called after each
+    /// processed item rather than on a timer: the lease lasts as long as the work makes
+    /// progress. The flip side is that a worker stalled on a single item stops touching
+    /// the lock and loses it once the lease runs out, even though it's still alive, so
+    /// the lease must cover the slowest item. `DynaErrorKind::LockAlreadyAcquired` is
+    /// returned once the lock was lost, after which the worker must stop processing.
+    pub fn touch(&mut self, input: &DynamoDbLockInput) -> Result<Instant, DynaError> {
+        self.refresh_and_extend(input)
+    }
+}
+
+/// Release every lock in `locks`, carrying on past failures.
+///
+/// A release error does not stop the remaining locks from being released, so a single
+/// failure can't leave the later locks held. The result of every release is returned
+/// together with the index of its lock in `locks`, so that the failures can be logged
+/// or retried individually.
+pub fn release_all<P, D>(
+    locks: &mut [DistLock<DynamoDbDriver<P, D>>],
+    input: &DynamoDbLockInput,
+) -> Vec<(usize, Result<(), DynaError>)>
+where
+    P: ProvideAwsCredentials + 'static,
+    D: DispatchSignedRequest + 'static,
+{
+    locks
+        .iter_mut()
+        .enumerate()
+        .map(|(index, lock)| (index, lock.release_lock(input)))
+        .collect()
+}
+
+impl From<SystemTimeError> for DynaError {
+    fn from(err: SystemTimeError) -> DynaError {
+        error!("{}", err);
+        DynaError::new(DynaErrorKind::UnhandledError, Some(&err.to_string())).with_cause(err)
+    }
+}
+
+impl From<ParseRegionError> for DynaError {
+    fn from(err: ParseRegionError) -> DynaError {
+        error!("{}", err);
+        DynaError::new(DynaErrorKind::InvalidConfiguration, Some(&err.to_string())).with_cause(err)
+    }
+}
+
+impl From<DescribeTableError> for DynaError {
+    fn from(err: DescribeTableError) -> DynaError {
+        match err {
+            DescribeTableError::ResourceNotFound(_) => {
+                error!("{}", err);
+                DynaError::new(
+                    DynaErrorKind::InvalidConfiguration,
+                    Some("lock table not found"),
+                )
+                .with_cause(err)
+            }
+            _ => {
+                error!("{}", err);
+                DynaError::new(DynaErrorKind::ProviderError, Some(&err.to_string())).with_cause(err)
+            }
+        }
+    }
+}
+
+impl From<DescribeGlobalTableError> for DynaError {
+    fn from(err: DescribeGlobalTableError) -> DynaError {
+        error!("{}", err);
+        DynaError::new(DynaErrorKind::ProviderError, Some(&err.to_string())).with_cause(err)
+    }
+}
+
+impl From<DescribeTimeToLiveError> for DynaError {
+    fn from(err: DescribeTimeToLiveError) -> DynaError {
+        error!("{}", err);
+        DynaError::new(DynaErrorKind::ProviderError, Some(&err.to_string())).with_cause(err)
+    }
+}
+
+impl From<GetItemError> for DynaError {
+    fn from(err: GetItemError) -> DynaError {
+        match err {
+            GetItemError::ProvisionedThroughputExceeded(_) => {
+                warn!("{}", err);
+                DynaError::new(DynaErrorKind::Throttled, Some(&err.to_string())).with_cause(err)
+            }
+            _ => {
+                error!("{}", err);
+                DynaError::new(DynaErrorKind::ProviderError, Some(&err.to_string())).with_cause(err)
+            }
+        }
+    }
+}
+
+impl From<BatchGetItemError> for DynaError {
+    fn from(err: BatchGetItemError) -> DynaError {
+        match err {
+            BatchGetItemError::ProvisionedThroughputExceeded(_) => {
+                warn!("{}", err);
+                DynaError::new(DynaErrorKind::Throttled, Some(&err.to_string())).with_cause(err)
+            }
+            _ => {
+                error!("{}", err);
+                DynaError::new(DynaErrorKind::ProviderError, Some(&err.to_string())).with_cause(err)
+            }
+        }
+    }
+}
+
+impl From<ScanError> for DynaError {
+    fn from(err: ScanError) -> DynaError {
+        match err {
+            ScanError::ProvisionedThroughputExceeded(_) => {
+                warn!("{}", err);
+                DynaError::new(DynaErrorKind::Throttled, Some(&err.to_string())).with_cause(err)
+            }
+            _ => {
+                error!("{}", err);
+                DynaError::new(DynaErrorKind::ProviderError, Some(&err.to_string())).with_cause(err)
+            }
+        }
+    }
+}
+
+impl From<UpdateItemError> for DynaError {
+    fn from(err: UpdateItemError) -> DynaError {
+        match err {
+            UpdateItemError::ConditionalCheckFailed(_) => {
+                warn!("{}", err);
+                DynaError::new(DynaErrorKind::LockAlreadyAcquired, None).with_cause(err)
+            }
+            UpdateItemError::ProvisionedThroughputExceeded(_) => {
+                warn!("{}", err);
+                DynaError::new(DynaErrorKind::Throttled, Some(&err.to_string())).with_cause(err)
+            }
+            _ => {
+                error!("{}", err);
+                DynaError::new(DynaErrorKind::ProviderError, Some(&err.to_string())).with_cause(err)
+            }
+        }
+    }
+}
+
+impl DynaError {
+    /// Return the DynamoDB `UpdateItem` error this error was converted from, if any.
+    pub fn as_dynamodb_error(&self) -> Option<&UpdateItemError> {
+        self.downcast_cause::<UpdateItemError>()
+    }
+
+    /// Return the DynamoDB `GetItem` error this error was converted from, if any.
+    pub fn as_dynamodb_get_error(&self) -> Option<&GetItemError> {
+        self.downcast_cause::<GetItemError>()
     }
 }
@@ -0,0 +1,110 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Acquisition of any free lock out of a pool of candidate keys.
+//!
+//! A pool of identical workers can each grab whichever slot is free by trying the
+//! candidate partition key values in turn. The driver is switched over to the key it
+//! locked, so that later refreshes and releases target that key.
+
+use std::time::Instant;
+
+use rusoto_core::{DispatchSignedRequest, ProvideAwsCredentials};
+
+use super::{DynamoDbDriver, DynamoDbLockInput};
+use {DistLock, DynaError, DynaErrorKind, Locking};
+
+impl<P, D> DynamoDbDriver<P, D>
+where
+    P: ProvideAwsCredentials,
+    D: DispatchSignedRequest,
+{
+    /// Point the driver at the lock item of `key`, forgetting what it knew of the
+    /// previous one.
+    fn switch_key(&mut self, key: &str) {
+        self.partition_key_value = String::from(key);
+        self.current_token.clear();
+        self.owns_token = false;
+        self.observed_metadata = None;
+        self.observed_reason = None;
+        self.observed_host_identity = None;
+        self.waiter_entry = None;
+        self.reader_token = None;
+        self.stale_readers = None;
+        self.intent = None;
+        self.observed_intents = 0;
+        self.renew_count = 0;
+        self.generation = None;
+        self.taken_over_token = None;
+        self.item_collection_size = None;
+        self.clock_anchor = None;
+        self.clock_jump = None;
+        self.expected_expires_at = None;
+        self.external_extension = None;
+    }
+}
+
+impl<P, D> DistLock<DynamoDbDriver<P, D>>
+where
+    P: ProvideAwsCredentials + 'static,
+    D: DispatchSignedRequest + 'static,
+{
+    /// Try to acquire the lock of each key in `keys`, in order, and return the first key
+    /// locked along with the `Instant` its lease starts at.
+    ///
+    /// Every key gets a single `acquire_lock` attempt. A key held by another processor,
+    /// or being drained, is skipped, while any other error is returned right away. On
+    /// success the driver keeps targeting the locked key. When every key is held `None`
+    /// is returned and the driver is left targeting the key it targeted before.
+    ///
+    /// Only free keys can be locked: a slot left behind by a crashed holder is skipped
+    /// until its lock item is removed by the TTL or taken over through `acquire_lock`.
+    /// `DynaErrorKind::InvalidInput` is returned when the driver already holds a lock.
+    pub fn acquire_any(
+        &mut self,
+        keys: &[String],
+        input: &DynamoDbLockInput,
+    ) -> Result<Option<(String, Instant)>, DynaError> {
+        if self.driver.owns_token {
+            return Err(DynaError::new(
+                DynaErrorKind::InvalidInput,
+                Some("a lock is already held, release it first"),
+            ));
+        }
+
+        let previous_key = self.driver.partition_key_value.clone();
+
+        for key in keys {
+            self.driver.switch_key(key);
+
+            match self.acquire_lock(input) {
+                Ok(instant) => return Ok(Some((key.clone(), instant))),
+                Err(ref err)
+                    if err.kind() == DynaErrorKind::LockAlreadyAcquired
+                        || err.kind() == DynaErrorKind::Draining =>
+                {
+                    debug!("lock '{}' is held, trying the next key", key);
+                }
+                Err(err) => {
+                    self.driver.switch_key(&previous_key);
+                    return Err(err);
+                }
+            }
+        }
+
+        self.driver.switch_key(&previous_key);
+
+        Ok(None)
+    }
+}
@@ -0,0 +1,141 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Recording of the requests the DynamoDB driver sends, for tests.
+//!
+//! A `RecordingDispatcher` wraps another request dispatcher, e.g., a scripted
+//! `rusoto_mock::MockRequestDispatcher`, and captures every request before passing it
+//! on. Tests can then assert on the exact sequence of operations and on the
+//! expressions, attribute names and values generated for each of them through the
+//! dispatcher's `RequestLog`. This module is available with the `test-util` feature.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::DispatchSignedRequest;
+use serde_json::{self, Value};
+
+/// A request captured by a `RecordingDispatcher`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedRequest {
+    /// The DynamoDB operation, e.g., "UpdateItem".
+    pub operation: String,
+    /// The JSON body of the request.
+    pub body: Vec<u8>,
+}
+
+impl RecordedRequest {
+    /// Decode the JSON body of the request, e.g., into the fields of an `UpdateItemInput`
+    /// such as `"ConditionExpression"` or `"ExpressionAttributeNames"`.
+    pub fn input(&self) -> Value {
+        serde_json::from_slice(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+/// A shared log of the requests captured by a `RecordingDispatcher`.
+///
+/// Clones share the same log, so the log can be kept to inspect the requests once the
+/// dispatcher was handed over to a DynamoDB client.
+#[derive(Debug, Default, Clone)]
+pub struct RequestLog {
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+impl RequestLog {
+    /// Return the requests captured so far, in order.
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// Return the names of the operations captured so far, in order.
+    pub fn operations(&self) -> Vec<String> {
+        self.requests()
+            .into_iter()
+            .map(|request| request.operation)
+            .collect()
+    }
+
+    /// Return the decoded inputs of the `UpdateItem` requests captured so far, in order.
+    pub fn update_item_inputs(&self) -> Vec<Value> {
+        self.inputs_of("UpdateItem")
+    }
+
+    /// Return the decoded inputs of the `GetItem` requests captured so far, in order.
+    pub fn get_item_inputs(&self) -> Vec<Value> {
+        self.inputs_of("GetItem")
+    }
+
+    /// Return the decoded inputs of the `operation` requests captured so far, in order.
+    fn inputs_of(&self, operation: &str) -> Vec<Value> {
+        self.requests()
+            .iter()
+            .filter(|request| request.operation == operation)
+            .map(|request| request.input())
+            .collect()
+    }
+
+    /// Append `request` to the log.
+    fn record(&self, request: &SignedRequest) {
+        // The target is the API version and the operation, e.g., "DynamoDB_20120810.GetItem"
+        let target = request
+            .headers
+            .get("x-amz-target")
+            .and_then(|values| values.first())
+            .map(|value| String::from_utf8_lossy(value).into_owned())
+            .unwrap_or_default();
+
+        self.requests.lock().unwrap().push(RecordedRequest {
+            operation: target.rsplit('.').next().unwrap_or_default().to_string(),
+            body: request.payload.clone().unwrap_or_default(),
+        });
+    }
+}
+
+/// A request dispatcher capturing every request before passing it on to the wrapped
+/// dispatcher.
+pub struct RecordingDispatcher<D> {
+    inner: D,
+    log: RequestLog,
+}
+
+impl<D> RecordingDispatcher<D>
+where
+    D: DispatchSignedRequest,
+{
+    /// Wrap the `inner` dispatcher with an empty request log.
+    pub fn new(inner: D) -> Self {
+        RecordingDispatcher {
+            inner: inner,
+            log: RequestLog::default(),
+        }
+    }
+
+    /// Return a handle on the log of the captured requests.
+    pub fn log(&self) -> RequestLog {
+        self.log.clone()
+    }
+}
+
+impl<D> DispatchSignedRequest for RecordingDispatcher<D>
+where
+    D: DispatchSignedRequest,
+{
+    type Future = D::Future;
+
+    fn dispatch(&self, request: SignedRequest, timeout: Option<Duration>) -> Self::Future {
+        self.log.record(&request);
+        self.inner.dispatch(request, timeout)
+    }
+}
@@ -0,0 +1,316 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Shared (reader) holders of DynamoDB locks.
+//!
+//! A shared acquisition sets the holder's reader token in the readers map of the lock
+//! item, mapped to the Unix time its lease expires at, on the same condition as an
+//! exclusive acquisition: no fence token is stored or the stored one is the (expired)
+//! fence token we know of. The expired fence token is removed along the way, which lets
+//! readers take over from a dead exclusive holder the same way exclusive holders do.
+//! Renewing in shared mode pushes the expiry of the reader entry forward, and an
+//! exclusive acquisition clears out readers whose entries all expired, which lets
+//! writers take over from dead readers in turn.
+//!
+//! Entries can only be set in an existing map, so the first shared acquisition of a
+//! holder first creates an empty readers map unless there is one.
+//!
+//! An exclusive holder can downgrade to a shared holder in a single conditional
+//! update, without a release gap another writer could slip through. Upgrading a
+//! shared holder is intentionally not offered: two readers upgrading at the same
+//! time would each wait for the other to leave the readers set and deadlock, so a
+//! reader has to release and acquire in exclusive mode instead.
+
+use std::collections::HashMap;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use rusoto_core::{DispatchSignedRequest, ProvideAwsCredentials};
+use rusoto_dynamodb::{AttributeValue, DynamoDb, UpdateItemInput};
+
+use super::{expressions, number_attribute, seconds_after, DynamoDbDriver, DynamoDbLockInput};
+use {DistLock, DynaError, DynaErrorKind};
+
+impl<P, D> DistLock<DynamoDbDriver<P, D>>
+where
+    P: ProvideAwsCredentials + 'static,
+    D: DispatchSignedRequest + 'static,
+{
+    /// Acquire or renew the lock in shared mode, writing a TTL of `ttl` from now.
+    pub(super) fn acquire_shared(
+        &mut self,
+        input: &DynamoDbLockInput,
+        ttl: Duration,
+    ) -> Result<Instant, DynaError> {
+        let now = input.system_time_or_now();
+        let now_secs = now.duration_since(UNIX_EPOCH)?.as_secs();
+        let ttl_secs = seconds_after(now_secs, ttl)?;
+        let expires_at_secs = seconds_after(now_secs, self.duration)?;
+
+        let reader_token = match self.driver.reader_token {
+            Some(ref reader_token) => reader_token.clone(),
+            None => {
+                self.create_readers(input, ttl_secs)?;
+                self.driver.generate_token()?
+            }
+        };
+
+        self.driver.throttle()?;
+
+        // Without a known fence token any value that can't match a stored one will do
+        let cond_token = if self.driver.current_token.is_empty() {
+            reader_token.clone()
+        } else {
+            self.driver.current_token.clone()
+        };
+
+        // Prepare the condition expression and its attributes
+        let mut attribute_names = hashmap! {
+            String::from("#token_field") => self.driver.token_field_name.clone(),
+            String::from("#readers_field") => self.driver.readers_field_name.clone(),
+            String::from("#reader") => reader_token.clone(),
+            String::from("#ttl_field") => self.driver.ttl_field_name.clone(),
+        };
+        let mut attribute_values = hashmap! {
+            String::from(":reader_expires_at") => number_attribute(expires_at_secs)?,
+            String::from(":ttl") => number_attribute(ttl_secs)?,
+            String::from(":cond_current_token") => AttributeValue { s: Some(cond_token), ..Default::default() },
+        };
+        let condition_expression = self.driver.extend_acquire_condition(
+            String::from(expressions::ACQUIRE_CONDITION),
+            &mut attribute_names,
+            &mut attribute_values,
+        );
+        let condition_expression = format!(
+            "({}) AND {}",
+            condition_expression,
+            expressions::SHARED_CONDITION
+        );
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.driver.table_name.clone(),
+            update_expression: Some(String::from(expressions::SHARED_ACQUIRE_UPDATE)),
+            condition_expression: Some(condition_expression),
+            expression_attribute_names: Some(attribute_names),
+            expression_attribute_values: Some(attribute_values),
+            key: self.driver.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .driver
+            .client
+            .update_item(&update_input)
+            .with_timeout(input.timeout)
+            .sync()
+            .map_err(DynaError::from);
+
+        if let Err(err) = self.driver.record(result) {
+            let err = self.driver.explain_acquire_failure(
+                err,
+                input.diagnose_contention,
+                None,
+                input.timeout,
+            );
+
+            // A renewal failing its condition means our reader entry is gone
+            if err.kind() == DynaErrorKind::LockAlreadyAcquired {
+                self.driver.reader_token = None;
+            }
+
+            return Err(err);
+        }
+
+        ////////// After this point the lock clock starts //////////
+        let start = input.instant_or_now(&*self.driver.clock);
+
+        // Shared lock acquired successfully, no exclusive holder is left
+        info!(
+            "lock '{}' acquired in shared mode, reader token ({}) lease ({}s)",
+            self.driver.partition_key_value,
+            reader_token,
+            self.duration.as_secs()
+        );
+        self.driver.reader_token = Some(reader_token);
+        self.driver.current_token.clear();
+        self.driver.owns_token = false;
+
+        Ok(start)
+    }
+
+    /// Atomically turn the exclusive lock we hold into a shared lock.
+    ///
+    /// Once the readers map is known to exist, a single conditional update, on the stored
+    /// fence token being the one we wrote, removes the fence token and sets our reader
+    /// token in the readers map. On success the `Instant` the shared lease starts at is
+    /// returned and the lock must from then on be renewed and released in
+    /// `LockMode::Shared` mode. When we don't hold the lock
+    /// `DynaErrorKind::LockAlreadyAcquired` is returned.
+    pub fn downgrade(&mut self, input: &DynamoDbLockInput) -> Result<Instant, DynaError> {
+        if !self.driver.owns_token || self.driver.current_token.is_empty() {
+            return Err(DynaError::new(
+                DynaErrorKind::LockAlreadyAcquired,
+                Some("lock is not held"),
+            ));
+        }
+
+        self.duration = self.driver.bound_lease(self.duration)?;
+        let ttl = self.driver.ttl_mode.ttl(self.duration)?;
+
+        let now = input.system_time_or_now();
+        let now_secs = now.duration_since(UNIX_EPOCH)?.as_secs();
+        let ttl_secs = seconds_after(now_secs, ttl)?;
+        let expires_at_secs = seconds_after(now_secs, self.duration)?;
+
+        self.create_readers(input, ttl_secs)?;
+        self.driver.throttle()?;
+
+        let reader_token = self.driver.generate_token()?;
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.driver.table_name.clone(),
+            update_expression: Some(String::from(expressions::SHARED_ACQUIRE_UPDATE)),
+            condition_expression: Some(format!(
+                "{} AND {}",
+                expressions::DOWNGRADE_CONDITION,
+                expressions::SHARED_CONDITION
+            )),
+            expression_attribute_names: Some(hashmap! {
+                String::from("#token_field") => self.driver.token_field_name.clone(),
+                String::from("#readers_field") => self.driver.readers_field_name.clone(),
+                String::from("#reader") => reader_token.clone(),
+                String::from("#ttl_field") => self.driver.ttl_field_name.clone(),
+            }),
+            expression_attribute_values: Some(hashmap! {
+                String::from(":reader_expires_at") => number_attribute(expires_at_secs)?,
+                String::from(":ttl") => number_attribute(ttl_secs)?,
+                String::from(":cond_current_token") => AttributeValue { s: Some(self.driver.current_token.clone()), ..Default::default() },
+            }),
+            key: self.driver.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .driver
+            .client
+            .update_item(&update_input)
+            .with_timeout(input.timeout)
+            .sync()
+            .map_err(DynaError::from);
+        self.driver.record(result)?;
+
+        ////////// After this point the lock clock starts //////////
+        let start = input.instant_or_now(&*self.driver.clock);
+
+        info!(
+            "lock '{}' downgraded from token ({}) to reader token ({})",
+            self.driver.partition_key_value, self.driver.current_token, reader_token
+        );
+        self.driver.reader_token = Some(reader_token);
+        self.driver.current_token.clear();
+        self.driver.owns_token = false;
+        self.driver.renew_count = 0;
+
+        Ok(start)
+    }
+
+    /// Release the lock in shared mode by removing our reader token from the readers map.
+    ///
+    /// A readers map that no longer exists has no entry of ours left to remove, which
+    /// counts as released.
+    pub(super) fn release_shared(&mut self, input: &DynamoDbLockInput) -> Result<(), DynaError> {
+        let reader_token = match self.driver.reader_token {
+            Some(ref reader_token) => reader_token.clone(),
+            None => return Ok(()),
+        };
+
+        self.driver.throttle()?;
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.driver.table_name.clone(),
+            update_expression: Some(String::from(expressions::SHARED_RELEASE_UPDATE)),
+            condition_expression: Some(String::from(expressions::SHARED_CONDITION)),
+            expression_attribute_names: Some(hashmap! {
+                String::from("#readers_field") => self.driver.readers_field_name.clone(),
+                String::from("#reader") => reader_token.clone(),
+            }),
+            key: self.driver.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .driver
+            .client
+            .update_item(&update_input)
+            .with_timeout(input.timeout)
+            .sync()
+            .map_err(DynaError::from);
+        match self.driver.record(result) {
+            Ok(_) => {}
+            Err(ref err) if err.kind() == DynaErrorKind::LockAlreadyAcquired => {}
+            Err(err) => return Err(err),
+        }
+
+        info!(
+            "lock '{}' released in shared mode for reader token ({})",
+            self.driver.partition_key_value, reader_token
+        );
+        self.driver.reader_token = None;
+
+        Ok(())
+    }
+
+    /// Create an empty readers map on the lock item unless it has one, along with a TTL
+    /// of `ttl_secs` unless it has one, so that a reader entry can be set in it.
+    fn create_readers(
+        &mut self,
+        input: &DynamoDbLockInput,
+        ttl_secs: u64,
+    ) -> Result<(), DynaError> {
+        self.driver.throttle()?;
+
+        // Prepare update method input
+        let update_input = UpdateItemInput {
+            table_name: self.driver.table_name.clone(),
+            update_expression: Some(String::from(expressions::READERS_CREATE_UPDATE)),
+            expression_attribute_names: Some(hashmap! {
+                String::from("#readers_field") => self.driver.readers_field_name.clone(),
+                String::from("#ttl_field") => self.driver.ttl_field_name.clone(),
+            }),
+            expression_attribute_values: Some(hashmap! {
+                String::from(":empty_readers") => AttributeValue { m: Some(HashMap::new()), ..Default::default() },
+                String::from(":ttl") => number_attribute(ttl_secs)?,
+            }),
+            key: self.driver.key(),
+            ..Default::default()
+        };
+
+        // Make a sync call with timeout
+        let result = self
+            .driver
+            .client
+            .update_item(&update_input)
+            .with_timeout(input.timeout)
+            .sync()
+            .map_err(DynaError::from);
+        self.driver.record(result)?;
+
+        Ok(())
+    }
+}
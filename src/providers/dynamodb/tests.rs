@@ -17,11 +17,46 @@
 extern crate rusoto_mock;
 
 use std::default::Default;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
 
+use super::fifo::fifo_position;
+use super::token::{RandomBytesTokenGenerator, TokenGenerator, MIN_TOKEN_BYTES};
 use super::*;
+use clock::{Clock, MockClock};
+use sleep::MockSleeper;
 
 use self::rusoto_mock::*;
-use rusoto_core::Region;
+use rusoto_core::signature::SignedRequest;
+use rusoto_core::{DispatchSignedRequest, Region};
+
+/// A request dispatcher answering each request with the next of `dispatchers`, the last
+/// one answering every request past the end of the sequence.
+struct SequenceDispatcher {
+    dispatchers: Vec<MockRequestDispatcher>,
+    next: AtomicUsize,
+}
+
+impl SequenceDispatcher {
+    fn new(dispatchers: Vec<MockRequestDispatcher>) -> Self {
+        SequenceDispatcher {
+            dispatchers: dispatchers,
+            next: AtomicUsize::new(0),
+        }
+    }
+}
+
+impl DispatchSignedRequest for SequenceDispatcher {
+    type Future = <MockRequestDispatcher as DispatchSignedRequest>::Future;
+
+    fn dispatch(&self, request: SignedRequest, timeout: Option<Duration>) -> Self::Future {
+        let next = self.next.fetch_add(1, Ordering::SeqCst);
+        let last = self.dispatchers.len() - 1;
+
+        self.dispatchers[cmp::min(next, last)].dispatch(request, timeout)
+    }
+}
 
 #[test]
 fn driver_input_default_is_sane() {
@@ -32,8 +67,31 @@ fn driver_input_default_is_sane() {
     assert_eq!(input.partition_key_value, String::from("singleton"));
     assert_eq!(input.token_field_name, String::from("rvn"));
     assert_eq!(input.duration_field_name, String::from("duration"));
+    assert!(input.write_duration);
     assert_eq!(input.ttl_field_name, String::from("ttl"));
-    assert_eq!(input.ttl_value, DAY_SECONDS * 7);
+    assert_eq!(
+        input.ttl_mode,
+        TtlMode::RelativeToNow(Duration::from_secs(DAY_SECONDS * 7))
+    );
+    assert_eq!(
+        input.expires_at_field_name,
+        String::from("lease_expires_at")
+    );
+    assert_eq!(input.max_ops_per_sec, None);
+    assert_eq!(input.rate_limit_mode, RateLimitMode::Block);
+    assert_eq!(input.circuit, None);
+    assert_eq!(input.drain_field_name, None);
+    assert_eq!(input.require_attribute, None);
+    assert_eq!(input.reason_field_name, String::from("reason"));
+    assert_eq!(input.host_identity_field_name, None);
+    assert_eq!(input.token_format, TokenFormat::Hyphenated);
+    assert_eq!(input.renew_count_field_name, None);
+    assert_eq!(input.epoch_field_name, None);
+    assert_eq!(input.generation_field_name, None);
+    assert_eq!(input.schema_version_field_name, None);
+    assert_eq!(input.max_lease, None);
+    assert!(!input.max_lease_strict);
+    assert_eq!(input.clock_jump_threshold, None);
 }
 
 #[test]
@@ -42,6 +100,75 @@ fn lock_input_default_is_sane() {
 
     assert_eq!(input.timeout, Duration::from_secs(10));
     assert_eq!(input.consistent_read, Some(false));
+    assert!(!input.verify_after_acquire);
+    assert!(!input.refresh_confirm);
+    assert!(!input.diagnose_contention);
+    assert_eq!(input.mode, LockMode::Exclusive);
+    assert_eq!(input.instant, None);
+    assert_eq!(input.system_time, None);
+    assert_eq!(input.epoch, None);
+    assert_eq!(input.idempotency_key, None);
+    assert_eq!(input.min_remaining, None);
+}
+
+#[test]
+fn lock_input_overrides_success() {
+    let base = DynamoDbLockInput::default();
+
+    let input = base
+        .clone()
+        .with_timeout(Duration::from_secs(2))
+        .with_consistent_read(true);
+    assert_eq!(input.timeout, Duration::from_secs(2));
+    assert_eq!(input.consistent_read, Some(true));
+    assert_eq!(base.timeout, Duration::from_secs(10));
+    assert_eq!(base.consistent_read, Some(false));
+}
+
+#[test]
+fn parse_region_success() {
+    assert_eq!(parse_region("us-east-1").unwrap(), Region::UsEast1);
+    assert_eq!(
+        parse_region("custom:local:http://localhost:8000").unwrap(),
+        Region::Custom {
+            name: String::from("local"),
+            endpoint: String::from("http://localhost:8000"),
+        }
+    );
+}
+
+#[test]
+fn parse_region_fail() {
+    assert_eq!(
+        parse_region("mars-north-1").err().unwrap().kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+    assert_eq!(
+        parse_region("custom:local").err().unwrap().kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+    assert_eq!(
+        parse_region("custom::http://localhost:8000")
+            .err()
+            .unwrap()
+            .kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+}
+
+#[test]
+fn new_in_region_unknown_region_fail() {
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let result = DynamoDbDriver::new_in_region("mars-north-1", &input);
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
 }
 
 #[test]
@@ -94,6 +221,52 @@ fn second_to_acquire_the_lock_fail() {
     );
 }
 
+#[test]
+fn acquire_lock_error_keeps_dynamodb_error_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_condition_fail.json",
+    );
+    let mock = MockRequestDispatcher::with_status(400).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let err = lock
+        .acquire_lock(&DynamoDbLockInput::default())
+        .err()
+        .unwrap();
+    match err.as_dynamodb_error() {
+        Some(&UpdateItemError::ConditionalCheckFailed(_)) => {}
+        other => panic!("unexpected DynamoDB error: {:?}", other),
+    }
+    assert!(err.as_dynamodb_get_error().is_none());
+}
+
+#[test]
+fn get_item_error_keeps_dynamodb_error_success() {
+    let err = DynaError::from(GetItemError::ResourceNotFound(String::from(
+        "lock table not found",
+    )));
+
+    assert_eq!(err.kind(), DynaErrorKind::ProviderError);
+    assert_eq!(
+        err.as_dynamodb_get_error(),
+        Some(&GetItemError::ResourceNotFound(String::from(
+            "lock table not found"
+        )))
+    );
+    assert!(err.as_dynamodb_error().is_none());
+}
+
 #[test]
 fn refresh_lock_updates_current_token_success() {
     let body =
@@ -197,3 +370,3992 @@ fn remaining_time_is_calculated_correctly_success() {
     assert_eq!(remaining.as_secs(), 9);
     assert!(remaining.subsec_nanos() > 999900000);
 }
+
+#[test]
+fn remaining_with_mock_clock_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let clock = MockClock::new();
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input).with_clock(clock.clone());
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let instant = lock.acquire_lock(&DynamoDbLockInput::default()).unwrap();
+    assert_eq!(instant, clock.now());
+
+    clock.advance(Duration::from_secs(1));
+    assert_eq!(lock.remaining(instant), Some(Duration::from_secs(9)));
+
+    // The lease ends exactly at the boundary
+    clock.advance(Duration::from_secs(9));
+    assert_eq!(lock.remaining(instant), Some(Duration::from_secs(0)));
+    clock.advance(Duration::new(0, 1));
+    assert_eq!(lock.remaining(instant), None);
+}
+
+#[test]
+fn rate_limited_refresh_fails_fast_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_empty_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        max_ops_per_sec: Some(1),
+        rate_limit_mode: RateLimitMode::Fail,
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+
+    let result = lock.refresh_lock(&DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::RateLimited);
+}
+
+#[test]
+fn circuit_opens_after_provider_errors_fail() {
+    let mock = MockRequestDispatcher::with_status(500);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        circuit: Some(CircuitConfig {
+            failure_threshold: 2,
+            window: Duration::from_secs(60),
+            cooldown: Duration::from_secs(60),
+        }),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    for _ in 0..2 {
+        let result = lock.refresh_lock(&DynamoDbLockInput::default());
+        assert_eq!(result.err().unwrap().kind(), DynaErrorKind::ProviderError);
+    }
+
+    let result = lock.acquire_lock(&DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::CircuitOpen);
+}
+
+#[test]
+fn circuit_ignores_contention_success() {
+    let mock = MockRequestDispatcher::with_status(400)
+        .with_body(r#"{"__type": "ConditionalCheckFailedException"}"#);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        circuit: Some(CircuitConfig {
+            failure_threshold: 1,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    for _ in 0..2 {
+        let result = lock.acquire_lock(&DynamoDbLockInput::default());
+        assert_eq!(
+            result.err().unwrap().kind(),
+            DynaErrorKind::LockAlreadyAcquired
+        );
+    }
+}
+
+#[test]
+fn rate_limited_refresh_sleeps_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_empty_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        max_ops_per_sec: Some(1),
+        ..Default::default()
+    };
+
+    let sleeper = MockSleeper::default();
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input).with_sleeper(sleeper.clone());
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    assert!(sleeper.sleeps().is_empty());
+
+    // The bucket is empty, the second refresh waits for the next token
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    let sleeps = sleeper.sleeps();
+    assert_eq!(sleeps.len(), 1);
+    assert!(sleeps[0] > Duration::from_millis(900) && sleeps[0] <= Duration::from_secs(1));
+}
+
+#[test]
+fn ping_success() {
+    let body =
+        MockResponseReader::read_response("test_resources/dynamodb", "describe_table_success.json");
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    assert!(driver.ping(&DynamoDbLockInput::default()).is_ok());
+}
+
+#[test]
+fn ping_table_not_found_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "describe_table_not_found_fail.json",
+    );
+    let mock = MockRequestDispatcher::with_status(400).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let result = driver.ping(&DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+}
+
+#[test]
+fn ping_unreachable_fail() {
+    let mock = MockRequestDispatcher::with_status(500);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let result = driver.ping(&DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::ProviderError);
+}
+
+#[test]
+fn ping_rate_limited_fail() {
+    let body =
+        MockResponseReader::read_response("test_resources/dynamodb", "describe_table_success.json");
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        max_ops_per_sec: Some(1),
+        rate_limit_mode: RateLimitMode::Fail,
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    assert!(driver.ping(&DynamoDbLockInput::default()).is_ok());
+
+    let result = driver.check_ttl_enabled(&DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::RateLimited);
+}
+
+#[test]
+fn ping_opens_circuit_fail() {
+    let mock = MockRequestDispatcher::with_status(500);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        circuit: Some(CircuitConfig {
+            failure_threshold: 2,
+            window: Duration::from_secs(60),
+            cooldown: Duration::from_secs(60),
+        }),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    for _ in 0..2 {
+        let result = driver.ping(&DynamoDbLockInput::default());
+        assert_eq!(result.err().unwrap().kind(), DynaErrorKind::ProviderError);
+    }
+
+    let result = driver.check_single_region(&DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::CircuitOpen);
+}
+
+#[test]
+fn check_single_region_not_global_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "describe_global_table_not_found_fail.json",
+    );
+    let mock = MockRequestDispatcher::with_status(400).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    assert!(driver
+        .check_single_region(&DynamoDbLockInput::default())
+        .is_ok());
+}
+
+#[test]
+fn check_single_region_replicated_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "describe_global_table_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let err = driver
+        .check_single_region(&DynamoDbLockInput::default())
+        .unwrap_err();
+    assert_eq!(err.kind(), DynaErrorKind::CrossRegionReplication);
+    assert_eq!(
+        err.to_string(),
+        "lock table is replicated across regions: \
+         table 'test_lock_table' is replicated to us-east-1, eu-west-1"
+    );
+}
+
+#[test]
+fn check_ttl_enabled_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "describe_ttl_enabled_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    assert_eq!(
+        driver.check_ttl_enabled(&DynamoDbLockInput::default()),
+        Ok(true)
+    );
+}
+
+#[test]
+fn check_ttl_disabled_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "describe_ttl_disabled_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    assert_eq!(
+        driver.check_ttl_enabled(&DynamoDbLockInput::default()),
+        Ok(false)
+    );
+}
+
+#[test]
+fn check_ttl_enabled_on_other_attribute_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "describe_ttl_other_attribute_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let result = driver.check_ttl_enabled(&DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+}
+
+#[test]
+fn verify_after_acquire_missing_token_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let lock_input = DynamoDbLockInput {
+        verify_after_acquire: true,
+        ..Default::default()
+    };
+
+    let result = lock.acquire_lock(&lock_input);
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::WriteVerificationFailed
+    );
+}
+
+#[test]
+fn lock_state_follows_the_algorithm_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    assert_eq!(lock.state(None), LockState::Unlocked);
+
+    let instant = lock.acquire_lock(&DynamoDbLockInput::default()).unwrap();
+    assert_eq!(lock.state(Some(instant)), LockState::Held);
+    assert_eq!(
+        lock.state(Some(instant - Duration::from_secs(11))),
+        LockState::Expired
+    );
+    assert_eq!(lock.state(None), LockState::Expired);
+
+    lock.release_lock(&DynamoDbLockInput::default()).unwrap();
+    assert_eq!(lock.state(Some(instant)), LockState::Unlocked);
+}
+
+#[test]
+fn snapshot_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        partition_key_value: String::from("resource"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let snapshot = lock.snapshot(None);
+    assert_eq!(snapshot.provider, "dynamodb");
+    assert_eq!(snapshot.resource, "resource");
+    assert_eq!(snapshot.token, None);
+    assert!(!snapshot.owned);
+    assert_eq!(snapshot.lease_millis, 10000);
+    assert_eq!(snapshot.remaining_millis, None);
+
+    let instant = lock.acquire_lock(&DynamoDbLockInput::default()).unwrap();
+    let snapshot = lock.snapshot(Some(instant));
+    assert_eq!(snapshot.token, Some(lock.driver.current_token.clone()));
+    assert!(snapshot.owned);
+    assert!(snapshot.remaining_millis.unwrap() > 9000);
+    assert_eq!(snapshot.renew_count, 0);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn snapshot_serializes_success() {
+    extern crate serde_json;
+
+    let snapshot = LockSnapshot {
+        provider: "dynamodb",
+        resource: String::from("resource"),
+        token: None,
+        owned: false,
+        lease_millis: 10000,
+        remaining_millis: None,
+        renew_count: 0,
+    };
+
+    assert_eq!(
+        serde_json::to_string(&snapshot).unwrap(),
+        "{\"provider\":\"dynamodb\",\"resource\":\"resource\",\"token\":null,\
+         \"owned\":false,\"lease_millis\":10000,\"remaining_millis\":null,\"renew_count\":0}"
+    );
+}
+
+#[test]
+fn lock_state_observed_after_refresh_success() {
+    let body =
+        MockResponseReader::read_response("test_resources/dynamodb", "get_lock_item_success.json");
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.current_token = String::from("our RVN token");
+    lock.driver.owns_token = true;
+
+    lock.refresh_lock(&DynamoDbLockInput::default()).unwrap();
+    assert_eq!(lock.state(Some(Instant::now())), LockState::Observed);
+}
+
+#[test]
+fn refresh_and_view_returns_observed_lock_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_expiry_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let view = lock
+        .refresh_and_view(&DynamoDbLockInput::default())
+        .unwrap()
+        .unwrap();
+    assert_eq!(view.key, "singleton");
+    assert_eq!(view.token, Some(String::from("test RVN token")));
+    let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    assert_eq!(view.remaining_at(now), Some(Duration::from_secs(5)));
+
+    // The driver was refreshed as well
+    assert_eq!(lock.driver.current_token, String::from("test RVN token"));
+}
+
+#[test]
+fn refresh_and_view_without_lock_item_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_empty_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let result = lock.refresh_and_view(&DynamoDbLockInput::default());
+    assert_eq!(result, Ok(None));
+}
+
+#[test]
+fn refresh_lock_detects_shortened_lease_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_expiry_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.current_token = String::from("test RVN token");
+    lock.driver.owns_token = true;
+
+    // The stored lease expires 5 seconds from now
+    let mut lock_input = DynamoDbLockInput {
+        system_time: Some(UNIX_EPOCH + Duration::from_secs(1_000_000)),
+        min_remaining: Some(Duration::from_secs(3)),
+        ..Default::default()
+    };
+    assert!(lock.refresh_lock(&lock_input).is_ok());
+
+    lock_input.min_remaining = Some(Duration::from_secs(8));
+    let err = lock.refresh_lock(&lock_input).unwrap_err();
+    assert_eq!(err.kind(), DynaErrorKind::LeaseShortened);
+    assert_eq!(
+        err.to_string(),
+        "lease was shortened below the required time: 5s left, 8s required"
+    );
+}
+
+#[test]
+fn set_draining_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        drain_field_name: Some(String::from("drain")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let mut driver = DynamoDbDriver::new(client, &input);
+
+    let result = driver.set_draining(true, &DynamoDbLockInput::default());
+    assert!(result.is_ok());
+}
+
+#[test]
+fn set_draining_without_drain_field_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let mut driver = DynamoDbDriver::new(client, &input);
+
+    let result = driver.set_draining(true, &DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+}
+
+#[test]
+fn contention_kind_detects_draining_success() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        drain_field_name: Some(String::from("drain")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let mut item = hashmap! {
+        String::from("rvn") => AttributeValue { s: Some(String::from("test RVN token")), ..Default::default() },
+    };
+    assert_eq!(
+        driver.contention_kind(Some(&item), None),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+
+    item.insert(
+        String::from("drain"),
+        AttributeValue {
+            bool: Some(true),
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        driver.contention_kind(Some(&item), None),
+        DynaErrorKind::Draining
+    );
+    assert_eq!(
+        driver.contention_kind(None, None),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+}
+
+#[test]
+fn drain_aware_acquire_contended_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_condition_fail.json",
+    );
+    let mock = MockRequestDispatcher::with_status(400).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        drain_field_name: Some(String::from("drain")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let result = lock.acquire_lock(&DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+}
+
+#[test]
+fn lock_manager_caches_locks_per_key_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let mut manager = LockManager::new(client, input, Duration::from_secs(10));
+    assert!(manager.is_empty());
+
+    manager
+        .lock_for("resource-a")
+        .acquire_lock(&DynamoDbLockInput::default())
+        .unwrap();
+    let token = manager.lock_for("resource-a").driver.current_token.clone();
+    assert!(!token.is_empty());
+    assert_eq!(manager.len(), 1);
+
+    let lock = manager.lock_for("resource-b");
+    assert_eq!(lock.driver.partition_key_value, String::from("resource-b"));
+    assert!(lock.driver.current_token.is_empty());
+    assert_eq!(lock.duration(), Duration::from_secs(10));
+    assert_eq!(manager.len(), 2);
+
+    let lock = manager.remove("resource-a").unwrap();
+    assert!(Arc::ptr_eq(
+        &lock.driver.client,
+        &manager.lock_for("resource-b").driver.client
+    ));
+    assert_eq!(lock.driver.current_token, token);
+    assert_eq!(manager.len(), 1);
+}
+
+#[test]
+fn lock_manager_resolves_per_resource_configuration_success() {
+    fn resolve(key: &str) -> (Duration, DynamoDbLockInput) {
+        if key.starts_with("migration-") {
+            let input = DynamoDbLockInput::default().with_timeout(Duration::from_secs(30));
+            (Duration::from_secs(300), input)
+        } else {
+            (Duration::from_secs(5), DynamoDbLockInput::default())
+        }
+    }
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let mock = MockRequestDispatcher::with_status(200);
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let mut manager =
+        LockManager::new(client, input, Duration::from_secs(10)).with_resolver(resolve);
+
+    {
+        let (lock, lock_input) = manager.lock_with_input_for("migration-1");
+        assert_eq!(lock.duration(), Duration::from_secs(300));
+        assert_eq!(lock_input.timeout, Duration::from_secs(30));
+    }
+
+    let (lock, lock_input) = manager.lock_with_input_for("queue-1");
+    assert_eq!(lock.duration(), Duration::from_secs(5));
+    assert_eq!(lock_input.timeout, Duration::from_secs(10));
+}
+
+#[test]
+fn refresh_lock_without_liveness_attribute_treats_lock_as_free_success() {
+    let body =
+        MockResponseReader::read_response("test_resources/dynamodb", "get_lock_item_success.json");
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        require_attribute: Some(String::from("session")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.current_token = String::from("stale RVN token");
+
+    let result = lock.refresh_lock(&DynamoDbLockInput::default());
+    assert!(result.is_ok());
+    assert!(lock.driver.current_token.is_empty());
+    assert_eq!(lock.state(None), LockState::Unlocked);
+}
+
+#[test]
+fn refresh_lock_with_liveness_attribute_updates_current_token_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_live_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        require_attribute: Some(String::from("session")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let result = lock.refresh_lock(&DynamoDbLockInput::default());
+    assert!(result.is_ok());
+    assert_eq!(lock.driver.current_token, String::from("test RVN token"));
+}
+
+#[test]
+fn default_expressions_are_valid_success() {
+    let expressions = ExpressionSet::default();
+
+    assert_eq!(expressions.acquire_update, expressions::ACQUIRE_UPDATE);
+    assert_eq!(
+        expressions.release_condition,
+        expressions::RELEASE_CONDITION
+    );
+    assert!(expressions.validate().is_ok());
+}
+
+#[test]
+fn custom_expressions_are_installed_success() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let expressions = ExpressionSet {
+        acquire_condition: format!(
+            "({}) AND attribute_exists(lock_id)",
+            expressions::ACQUIRE_CONDITION
+        ),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input)
+        .with_expressions(expressions.clone())
+        .ok()
+        .unwrap();
+
+    assert_eq!(*driver.expressions(), expressions);
+}
+
+#[test]
+fn custom_expressions_missing_placeholders_fail() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let expressions = ExpressionSet {
+        acquire_condition: String::from("attribute_not_exists(#token_field)"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let result = DynamoDbDriver::new(client, &input).with_expressions(expressions);
+
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+}
+
+#[test]
+fn release_all_reports_partial_failure_success() {
+    let success_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let fail_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_condition_fail.json",
+    );
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let mut locks = vec![
+        (200, &success_body),
+        (400, &fail_body),
+        (200, &success_body),
+    ]
+    .into_iter()
+    .map(|(status, body)| {
+        let mock = MockRequestDispatcher::with_status(status).with_body(body);
+        let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+        let driver = DynamoDbDriver::new(client, &input);
+        let mut lock = DistLock::new(driver, Duration::from_secs(10));
+        lock.driver.current_token = String::from("test RVN token");
+        lock
+    })
+    .collect::<Vec<_>>();
+
+    let results = release_all(&mut locks, &DynamoDbLockInput::default());
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].0, 0);
+    assert!(results[0].1.is_ok());
+    assert_eq!(results[1].0, 1);
+    assert_eq!(
+        results[1].1.as_ref().err().unwrap().kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+    assert_eq!(results[2].0, 2);
+    assert!(results[2].1.is_ok());
+
+    assert!(locks[0].driver.current_token.is_empty());
+    assert_eq!(
+        locks[1].driver.current_token,
+        String::from("test RVN token")
+    );
+    assert!(locks[2].driver.current_token.is_empty());
+}
+
+#[test]
+fn acquire_lock_with_metadata_records_metadata_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    assert_eq!(lock.driver.observed_metadata(), None);
+
+    let lock_input = DynamoDbLockInput {
+        metadata: Some(b"leader-1".to_vec()),
+        ..Default::default()
+    };
+    assert!(lock.acquire_lock(&lock_input).is_ok());
+    assert_eq!(lock.driver.observed_metadata(), Some(&b"leader-1"[..]));
+}
+
+#[test]
+fn default_host_identity_success() {
+    let identity = default_host_identity();
+
+    assert!(!identity.is_empty());
+    assert!(!identity.starts_with(':'));
+}
+
+#[test]
+fn acquire_lock_writes_host_identity_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains(expressions::HOST_IDENTITY_UPDATE));
+            assert!(payload.contains("\"S\":\"worker-7:4242\""));
+        });
+
+    fn worker_identity() -> String {
+        String::from("worker-7:4242")
+    }
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        host_identity_field_name: Some(String::from("host")),
+        host_identity: worker_identity,
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    assert_eq!(lock.driver.observed_host_identity(), None);
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.observed_host_identity(), Some("worker-7:4242"));
+}
+
+#[test]
+fn refresh_lock_reads_host_identity_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_host_identity_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        host_identity_field_name: Some(String::from("host")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.observed_host_identity(), Some("worker-7:4242"));
+}
+
+#[test]
+fn acquire_lock_with_reason_records_reason_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains("#reason_field = :reason"));
+            assert!(payload.contains("\"S\":\"nightly-reindex job run 4821\""));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    assert_eq!(lock.driver.observed_reason(), None);
+
+    let lock_input = DynamoDbLockInput {
+        reason: Some(String::from("nightly-reindex job run 4821")),
+        ..Default::default()
+    };
+    assert!(lock.acquire_lock(&lock_input).is_ok());
+    assert_eq!(
+        lock.driver.observed_reason(),
+        Some("nightly-reindex job run 4821")
+    );
+}
+
+#[test]
+fn refresh_lock_reads_reason_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_reason_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(
+        lock.driver.observed_reason(),
+        Some("nightly-reindex job run 4821")
+    );
+}
+
+#[test]
+fn acquire_lock_counts_renewals_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            if payload.contains("UpdateExpression\":\"SET") {
+                assert!(payload.contains("#renew_count_field = :renew_count"));
+                assert!(payload.contains("\"renewals\""));
+            }
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        renew_count_field_name: Some(String::from("renewals")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    for renewals in 0..3 {
+        assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+        assert_eq!(lock.driver.renew_count(), renewals);
+    }
+
+    assert!(lock.release_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.renew_count(), 0);
+}
+
+#[test]
+fn acquire_lock_with_virtual_time_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains("\"N\":\"1000010\""));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let virtual_instant = Instant::now() + Duration::from_secs(3600);
+    let lock_input = DynamoDbLockInput {
+        instant: Some(virtual_instant),
+        system_time: Some(UNIX_EPOCH + Duration::from_secs(1_000_000)),
+        ..Default::default()
+    };
+    assert_eq!(lock.acquire_lock(&lock_input).unwrap(), virtual_instant);
+}
+
+#[test]
+fn refresh_lock_detects_clock_jumps_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        clock_jump_threshold: Some(Duration::from_secs(5)),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let instant = Instant::now();
+    let time = UNIX_EPOCH + Duration::from_secs(1_000_000);
+    let clocks = |elapsed: u64, wall_elapsed: u64| DynamoDbLockInput {
+        instant: Some(instant + Duration::from_secs(elapsed)),
+        system_time: Some(time + Duration::from_secs(wall_elapsed)),
+        ..Default::default()
+    };
+    lock.acquire_lock(&clocks(0, 0)).unwrap();
+
+    lock.refresh_lock(&clocks(10, 12)).unwrap();
+    assert_eq!(lock.driver.last_clock_jump(), None);
+
+    lock.refresh_lock(&clocks(20, 120)).unwrap();
+    assert_eq!(
+        lock.driver.last_clock_jump(),
+        Some(ClockJump::Forward(Duration::from_secs(100)))
+    );
+
+    let backward = DynamoDbLockInput {
+        system_time: Some(time - Duration::from_secs(10)),
+        ..clocks(30, 0)
+    };
+    lock.refresh_lock(&backward).unwrap();
+    assert_eq!(
+        lock.driver.last_clock_jump(),
+        Some(ClockJump::Backward(Duration::from_secs(40)))
+    );
+}
+
+#[test]
+fn acquire_lock_reports_takeover_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_takeover_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains("\"ReturnValues\":\"ALL_OLD\""));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    assert_eq!(lock.driver.taken_over_token(), None);
+
+    // The fence token was observed through a refresh, not written by us
+    lock.driver.current_token = String::from("previous RVN token");
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.taken_over_token(), Some("previous RVN token"));
+}
+
+#[test]
+fn acquire_lock_records_item_collection_size_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_collection_metrics_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains("\"ReturnItemCollectionMetrics\":\"SIZE\""));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    assert_eq!(lock.driver.last_item_collection_size(), None);
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.last_item_collection_size(), Some((8.5, 9.0)));
+}
+
+#[test]
+fn acquire_lock_renewal_is_not_takeover_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_takeover_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.current_token = String::from("previous RVN token");
+    lock.driver.owns_token = true;
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.taken_over_token(), None);
+}
+
+#[test]
+fn acquire_lock_clamps_lease_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains("\":lease\":{\"N\":\"60\"}"));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        max_lease: Some(Duration::from_secs(60)),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(86400));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.duration(), Duration::from_secs(60));
+}
+
+#[test]
+fn acquire_lock_strict_max_lease_fail() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let mock = MockRequestDispatcher::with_status(200).with_request_checker(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        max_lease: Some(Duration::from_secs(60)),
+        max_lease_strict: true,
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(86400));
+
+    let result = lock.acquire_lock(&DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::InvalidLease);
+    assert_eq!(lock.duration(), Duration::from_secs(86400));
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn acquire_lock_with_oversized_metadata_fail() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let lock_input = DynamoDbLockInput {
+        metadata: Some(vec![0; MAX_METADATA_SIZE + 1]),
+        ..Default::default()
+    };
+    let result = lock.acquire_lock(&lock_input);
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::InvalidInput);
+    assert!(lock.driver.current_token.is_empty());
+}
+
+#[test]
+fn refresh_lock_reads_metadata_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_metadata_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.observed_metadata(), Some(&b"leader-1"[..]));
+}
+
+#[test]
+fn raw_codec_round_trip_success() {
+    let blob = codec::encode_metadata(&codec::RawCodec, b"leader-1").unwrap();
+    assert_eq!(blob, b"\x00leader-1".to_vec());
+
+    let metadata = codec::decode_metadata(&codec::RawCodec, &blob).unwrap();
+    assert_eq!(metadata, b"leader-1".to_vec());
+}
+
+#[cfg(feature = "gzip")]
+#[test]
+fn gzip_codec_round_trip_success() {
+    let codec = codec::GzipCodec::default();
+    let metadata = vec![b'x'; 4096];
+
+    let blob = codec::encode_metadata(&codec, &metadata).unwrap();
+    assert_eq!(blob[0], codec::GZIP_CODEC_ID);
+    assert!(blob.len() < metadata.len());
+    assert_eq!(codec::decode_metadata(&codec, &blob).unwrap(), metadata);
+
+    // Blobs written without compression stay readable
+    let raw = codec::encode_metadata(&codec::RawCodec, &metadata).unwrap();
+    assert_eq!(codec::decode_metadata(&codec, &raw).unwrap(), metadata);
+}
+
+#[test]
+fn decode_metadata_unknown_codec_fail() {
+    let result = codec::decode_metadata(&codec::RawCodec, b"\x7fleader-1");
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::InvalidMetadata);
+
+    let result = codec::decode_metadata(&codec::RawCodec, b"");
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::InvalidMetadata);
+}
+
+#[test]
+fn acquire_lock_encodes_metadata_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains("\"B\":\"AGxlYWRlci0x\""));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input).with_metadata_codec(codec::RawCodec);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let lock_input = DynamoDbLockInput {
+        metadata: Some(b"leader-1".to_vec()),
+        ..Default::default()
+    };
+    assert!(lock.acquire_lock(&lock_input).is_ok());
+    assert_eq!(lock.driver.observed_metadata(), Some(&b"leader-1"[..]));
+}
+
+#[test]
+fn refresh_lock_decodes_metadata_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_encoded_metadata_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input).with_metadata_codec(codec::RawCodec);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.observed_metadata(), Some(&b"leader-1"[..]));
+}
+
+#[test]
+fn refresh_lock_keeps_undecodable_metadata_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_metadata_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input).with_metadata_codec(codec::RawCodec);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    // The blob was written without a codec
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.observed_metadata(), Some(&b"leader-1"[..]));
+}
+
+#[test]
+fn acquire_any_locks_first_free_key_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains("\"S\":\"slot-a\""));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let keys = vec![String::from("slot-a"), String::from("slot-b")];
+    let (key, _) = lock
+        .acquire_any(&keys, &DynamoDbLockInput::default())
+        .unwrap()
+        .unwrap();
+    assert_eq!(key, String::from("slot-a"));
+    assert_eq!(lock.driver.partition_key_value(), "slot-a");
+    assert!(lock.driver.owns_token);
+}
+
+#[test]
+fn acquire_any_all_keys_held_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_condition_fail.json",
+    );
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let mock = MockRequestDispatcher::with_status(400)
+        .with_body(&body)
+        .with_request_checker(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let keys = vec![String::from("slot-a"), String::from("slot-b")];
+    let result = lock.acquire_any(&keys, &DynamoDbLockInput::default());
+    assert!(result.unwrap().is_none());
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert_eq!(lock.driver.partition_key_value(), "singleton");
+}
+
+#[test]
+fn acquire_any_forgets_stale_readers_of_previous_key_success() {
+    let get_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_stale_reader_success.json",
+    );
+    let update_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = SequenceDispatcher::new(vec![
+        MockRequestDispatcher::with_status(200).with_body(&get_body),
+        MockRequestDispatcher::with_status(200)
+            .with_body(&update_body)
+            .with_request_checker(|request| {
+                let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+                assert!(payload.contains("\"S\":\"slot-a\""));
+                assert!(!payload.contains(expressions::STALE_READERS_CONDITION));
+                assert!(!payload.contains("dead reader token"));
+            }),
+    ]);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    // The stale reader was seen on the item of the default key, not on slot-a
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    assert!(lock.driver.stale_readers.is_some());
+    lock.driver.clock_jump = Some(ClockJump::Forward(Duration::from_secs(60)));
+
+    let keys = vec![String::from("slot-a")];
+    let (key, _) = lock
+        .acquire_any(&keys, &DynamoDbLockInput::default())
+        .unwrap()
+        .unwrap();
+    assert_eq!(key, String::from("slot-a"));
+    assert!(lock.driver.stale_readers.is_none());
+    assert_eq!(lock.driver.last_clock_jump(), None);
+}
+
+#[test]
+fn acquire_any_while_holding_fail() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.current_token = String::from("test RVN token");
+    lock.driver.owns_token = true;
+
+    let keys = vec![String::from("slot-a")];
+    let result = lock.acquire_any(&keys, &DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::InvalidInput);
+    assert_eq!(lock.driver.current_token, String::from("test RVN token"));
+}
+
+#[test]
+fn recording_dispatcher_records_acquire_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+    let dispatcher = recording::RecordingDispatcher::new(mock);
+    let log = dispatcher.log();
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        drain_field_name: Some(String::from("drain")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(dispatcher, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(
+        log.operations(),
+        vec![String::from("UpdateItem"), String::from("GetItem")]
+    );
+
+    let update = &log.update_item_inputs()[0];
+    assert_eq!(update["TableName"], "test_lock_table");
+    assert_eq!(update["ReturnValues"], "ALL_OLD");
+    assert_eq!(update["ExpressionAttributeNames"]["#drain_field"], "drain");
+    assert!(update["ConditionExpression"]
+        .as_str()
+        .unwrap()
+        .contains(expressions::DRAIN_CONDITION));
+
+    let get = &log.get_item_inputs()[0];
+    assert_eq!(get["Key"]["lock_id"]["S"], "singleton");
+}
+
+#[test]
+fn sweep_expired_clears_expired_locks_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "scan_expired_lock_items_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let result = driver.sweep_expired(Duration::from_secs(3600), &DynamoDbLockInput::default());
+    assert_eq!(result, Ok(2));
+}
+
+#[test]
+fn sweep_expired_scan_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "scan_resource_not_found_fail.json",
+    );
+    let mock = MockRequestDispatcher::with_status(400).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let result = driver.sweep_expired(Duration::from_secs(3600), &DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::ProviderError);
+}
+
+#[test]
+fn acquire_lock_throttled_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "provisioned_throughput_exceeded_fail.json",
+    );
+    let mock = MockRequestDispatcher::with_status(400).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let err = lock
+        .acquire_lock(&DynamoDbLockInput::default())
+        .unwrap_err();
+    assert_eq!(err.kind(), DynaErrorKind::Throttled);
+    match err.as_dynamodb_error() {
+        Some(&UpdateItemError::ProvisionedThroughputExceeded(_)) => {}
+        other => panic!("unexpected cause {:?}", other),
+    }
+}
+
+#[test]
+fn refresh_lock_throttled_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "provisioned_throughput_exceeded_fail.json",
+    );
+    let mock = MockRequestDispatcher::with_status(400).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let err = lock
+        .refresh_lock(&DynamoDbLockInput::default())
+        .unwrap_err();
+    assert_eq!(err.kind(), DynaErrorKind::Throttled);
+    assert!(err.as_dynamodb_get_error().is_some());
+}
+
+#[test]
+fn find_orphaned_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "scan_expired_lock_items_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+    let dispatcher = recording::RecordingDispatcher::new(mock);
+    let log = dispatcher.log();
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(dispatcher, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let lock_input = DynamoDbLockInput {
+        system_time: Some(UNIX_EPOCH + Duration::from_secs(1530000100)),
+        ..Default::default()
+    };
+    let orphaned = driver.find_orphaned(&lock_input).unwrap();
+    assert_eq!(orphaned.len(), 2);
+    assert_eq!(orphaned[0].key, "resource-a");
+    assert_eq!(orphaned[0].token, Some(String::from("test RVN token a")));
+    assert_eq!(
+        orphaned[1].expires_at,
+        Some(UNIX_EPOCH + Duration::from_secs(1530000010))
+    );
+
+    // A single filtered scan was sent, and nothing was written
+    assert_eq!(log.operations(), vec!["Scan"]);
+    let scan = log.requests()[0].input();
+    assert_eq!(
+        scan["FilterExpression"],
+        "#expires_at_field < :now AND attribute_exists(#token_field)"
+    );
+    assert_eq!(scan["ExpressionAttributeValues"][":now"]["N"], "1530000100");
+}
+
+#[test]
+fn find_orphaned_scan_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "scan_resource_not_found_fail.json",
+    );
+    let mock = MockRequestDispatcher::with_status(400).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let result = driver.find_orphaned(&DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::ProviderError);
+}
+
+#[test]
+fn find_orphaned_throttles_each_page_fail() {
+    let page_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "scan_expired_lock_items_page_success.json",
+    );
+    let last_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "scan_expired_lock_items_success.json",
+    );
+    let mock = SequenceDispatcher::new(vec![
+        MockRequestDispatcher::with_status(200).with_body(&page_body),
+        MockRequestDispatcher::with_status(200).with_body(&last_body),
+    ]);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        max_ops_per_sec: Some(1),
+        rate_limit_mode: RateLimitMode::Fail,
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    // The second page needs a token of its own
+    let result = driver.find_orphaned(&DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::RateLimited);
+}
+
+#[test]
+fn migrate_token_field_moves_tokens_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "scan_expired_lock_items_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+    let dispatcher = recording::RecordingDispatcher::new(mock);
+    let log = dispatcher.log();
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(dispatcher, MockCredentialsProvider, Region::UsEast1);
+    let mut driver = DynamoDbDriver::new(client, &input);
+
+    let result = driver.migrate_token_field("rvn", "fence_token", &DynamoDbLockInput::default());
+    assert_eq!(result, Ok(2));
+
+    let updates = log.update_item_inputs();
+    assert_eq!(updates.len(), 2);
+    assert_eq!(
+        updates[0]["ExpressionAttributeNames"]["#old_token_field"],
+        "rvn"
+    );
+    assert_eq!(
+        updates[0]["ExpressionAttributeNames"]["#new_token_field"],
+        "fence_token"
+    );
+    assert_eq!(
+        updates[0]["ExpressionAttributeValues"][":token"]["S"],
+        "test RVN token a"
+    );
+    assert_eq!(updates[1]["Key"]["lock_id"]["S"], "resource-b");
+}
+
+#[test]
+fn migrate_token_field_same_field_fail() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let mut driver = DynamoDbDriver::new(client, &input);
+
+    let result = driver.migrate_token_field("rvn", "rvn", &DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::InvalidInput);
+}
+
+#[test]
+fn validate_number_boundaries_success() {
+    assert_eq!(validate_number("0"), Ok(()));
+    assert_eq!(validate_number("-42"), Ok(()));
+    assert_eq!(validate_number(&u64::max_value().to_string()), Ok(()));
+    assert_eq!(validate_number(&"9".repeat(MAX_NUMBER_PRECISION)), Ok(()));
+    assert_eq!(
+        validate_number(&format!("1{}", "0".repeat(MAX_NUMBER_DIGITS - 1))),
+        Ok(())
+    );
+    assert_eq!(
+        validate_number(&format!("000{}", "9".repeat(MAX_NUMBER_PRECISION))),
+        Ok(())
+    );
+}
+
+#[test]
+fn validate_number_boundaries_fail() {
+    let invalid = vec![
+        String::new(),
+        String::from("-"),
+        String::from("1e10"),
+        String::from("1.5"),
+        String::from("+1"),
+        "9".repeat(MAX_NUMBER_PRECISION + 1),
+        format!("1{}", "0".repeat(MAX_NUMBER_DIGITS)),
+    ];
+
+    for number in invalid {
+        assert_eq!(
+            validate_number(&number).err().unwrap().kind(),
+            DynaErrorKind::InvalidConfiguration,
+            "{}",
+            number
+        );
+    }
+}
+
+#[test]
+fn acquire_lock_with_overflowing_lease_fail() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ttl_mode: TtlMode::EqualToLease,
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(u64::max_value()));
+
+    let result = lock.acquire_lock(&DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+    assert!(!lock.driver.owns_token);
+}
+
+#[test]
+fn ttl_mode_computes_ttl_success() {
+    let lease = Duration::from_secs(10);
+
+    assert_eq!(
+        TtlMode::RelativeToNow(Duration::from_secs(60)).ttl(lease),
+        Ok(Duration::from_secs(60))
+    );
+    assert_eq!(TtlMode::EqualToLease.ttl(lease), Ok(lease));
+    assert_eq!(
+        TtlMode::LeasePlus(Duration::from_secs(u64::max_value()))
+            .ttl(lease)
+            .err()
+            .unwrap()
+            .kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+    assert_eq!(
+        TtlMode::LeasePlus(Duration::from_secs(5)).ttl(lease),
+        Ok(Duration::from_secs(15))
+    );
+}
+
+#[test]
+fn acquire_lock_with_ttl_shorter_than_lease_fail() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ttl_mode: TtlMode::RelativeToNow(Duration::from_secs(5)),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let result = lock.acquire_lock(&DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+}
+
+#[test]
+fn same_resource_compares_lock_items_success() {
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+    let other_key = DynamoDbDriverInput {
+        partition_key_value: String::from("other"),
+        ..input.clone()
+    };
+    let other_table = DynamoDbDriverInput {
+        table_name: String::from("other_lock_table"),
+        ..input.clone()
+    };
+
+    let new_lock = |input: &DynamoDbDriverInput| {
+        let mock = MockRequestDispatcher::with_status(200);
+        let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+        DistLock::new(DynamoDbDriver::new(client, input), Duration::from_secs(10))
+    };
+
+    let lock = new_lock(&input);
+    assert!(lock.same_resource(&new_lock(&input)));
+    assert!(!lock.same_resource(&new_lock(&other_key)));
+    assert!(!lock.same_resource(&new_lock(&other_table)));
+}
+
+#[test]
+fn release_with_acquired_token_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let (_, token) = lock
+        .acquire_with_token(&DynamoDbLockInput::default())
+        .unwrap();
+    assert_eq!(token.as_str(), lock.driver.current_token.as_str());
+    assert_eq!(
+        lock.release_with(token, &DynamoDbLockInput::default()),
+        Ok(())
+    );
+}
+
+#[test]
+fn current_token_is_the_written_token_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+    let dispatcher = recording::RecordingDispatcher::new(mock);
+    let log = dispatcher.log();
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(dispatcher, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    assert_eq!(lock.driver.current_token(), "");
+
+    lock.acquire_lock(&DynamoDbLockInput::default()).unwrap();
+    let first = lock.driver.current_token().to_string();
+    lock.acquire_lock(&DynamoDbLockInput::default()).unwrap();
+    let second = lock.driver.current_token().to_string();
+
+    // Every acquisition hands back the token it committed, not the previous one
+    let updates = log.update_item_inputs();
+    assert_eq!(
+        updates[0]["ExpressionAttributeValues"][":new_token"]["S"],
+        first.as_str()
+    );
+    assert_eq!(
+        updates[1]["ExpressionAttributeValues"][":new_token"]["S"],
+        second.as_str()
+    );
+    assert!(first != second);
+}
+
+#[test]
+fn release_with_stale_token_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let (_, token) = lock
+        .acquire_with_token(&DynamoDbLockInput::default())
+        .unwrap();
+
+    // A stray refresh observed another processor's fence token
+    lock.driver.current_token = String::from("test RVN token");
+    lock.driver.owns_token = false;
+
+    let result = lock.release_with(token, &DynamoDbLockInput::default());
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::TokenMismatch);
+    assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn acquire_with_token_shared_fail() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let lock_input = DynamoDbLockInput {
+        mode: LockMode::Shared,
+        ..Default::default()
+    };
+    let result = lock.acquire_with_token(&lock_input);
+    assert_eq!(result.err().unwrap().kind(), DynaErrorKind::InvalidInput);
+}
+
+#[test]
+fn is_current_holder_matching_token_success() {
+    let body =
+        MockResponseReader::read_response("test_resources/dynamodb", "get_lock_item_success.json");
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.current_token = String::from("test RVN token");
+
+    // We only hold the lock after acquiring it ourselves
+    assert_eq!(
+        lock.is_current_holder(&DynamoDbLockInput::default()),
+        Ok(false)
+    );
+
+    lock.driver.owns_token = true;
+    assert_eq!(
+        lock.is_current_holder(&DynamoDbLockInput::default()),
+        Ok(true)
+    );
+    assert_eq!(lock.driver.current_token, String::from("test RVN token"));
+}
+
+#[test]
+fn is_current_holder_different_token_success() {
+    let body =
+        MockResponseReader::read_response("test_resources/dynamodb", "get_lock_item_success.json");
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.current_token = String::from("our RVN token");
+    lock.driver.owns_token = true;
+
+    assert_eq!(
+        lock.is_current_holder(&DynamoDbLockInput::default()),
+        Ok(false)
+    );
+    assert_eq!(lock.driver.current_token, String::from("our RVN token"));
+}
+
+#[test]
+fn acquire_lock_increments_generation_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_generation_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains(expressions::GENERATION_UPDATE));
+            assert!(payload.contains("\"#generation_field\":\"generation\""));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        generation_field_name: Some(String::from("generation")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    assert_eq!(lock.driver.generation(), None);
+
+    let instant = lock.acquire_lock(&DynamoDbLockInput::default()).unwrap();
+    assert_eq!(lock.driver.generation(), Some(42));
+
+    // No clock is trusted in generation mode
+    assert_eq!(lock.remaining(instant), None);
+    assert!(!lock.has_lease());
+}
+
+#[test]
+fn is_current_holder_compares_generation_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_generation_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        generation_field_name: Some(String::from("generation")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.current_token = String::from("test RVN token");
+    lock.driver.owns_token = true;
+
+    // A higher generation was stored since we acquired
+    lock.driver.generation = Some(42);
+    assert_eq!(
+        lock.is_current_holder(&DynamoDbLockInput::default()),
+        Ok(false)
+    );
+
+    lock.driver.generation = Some(43);
+    assert_eq!(
+        lock.is_current_holder(&DynamoDbLockInput::default()),
+        Ok(true)
+    );
+}
+
+#[test]
+fn refresh_and_extend_keeps_token_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains("#token_field = :cond_current_token"));
+            assert!(!payload.contains(":new_token"));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.current_token = String::from("our RVN token");
+    lock.driver.owns_token = true;
+
+    assert!(lock
+        .refresh_and_extend(&DynamoDbLockInput::default())
+        .is_ok());
+    assert_eq!(lock.driver.current_token, String::from("our RVN token"));
+    assert_eq!(lock.driver.renew_count(), 1);
+}
+
+#[test]
+fn refresh_and_extend_not_held_fail() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let mock = MockRequestDispatcher::with_status(200).with_request_checker(move |_| {
+        counter.fetch_add(1, Ordering::SeqCst);
+    });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let result = lock.refresh_and_extend(&DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+    assert_eq!(calls.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn refresh_and_extend_lost_lock_reads_item_fail() {
+    let calls = Arc::new(AtomicUsize::new(0));
+    let counter = calls.clone();
+    let mock = MockRequestDispatcher::with_status(400)
+        .with_body(r#"{"__type": "ConditionalCheckFailedException"}"#)
+        .with_request_checker(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.current_token = String::from("our RVN token");
+    lock.driver.owns_token = true;
+
+    // The extension is followed by a read of the lock item
+    assert!(lock
+        .refresh_and_extend(&DynamoDbLockInput::default())
+        .is_err());
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+}
+
+#[cfg(feature = "iso8601")]
+#[test]
+fn acquire_lock_writes_iso8601_attributes_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains("#token_field = :new_token"));
+            assert!(payload.contains("#acquired_at_iso_field = :acquired_at_iso"));
+            assert!(payload.contains("#expires_at_iso_field = :expires_at_iso"));
+            assert!(payload.contains("\"acquired_at\""));
+            assert!(payload.contains("\"expires_at\""));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        acquired_at_iso_field_name: Some(String::from("acquired_at")),
+        expires_at_iso_field_name: Some(String::from("expires_at")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+}
+
+#[cfg(feature = "iso8601")]
+#[test]
+fn iso8601_formats_utc_timestamp_success() {
+    let time = UNIX_EPOCH + Duration::from_secs(1530000000);
+    assert_eq!(iso8601(time), String::from("2018-06-26T08:00:00Z"));
+}
+
+#[test]
+fn refresh_lock_confirm_reads_twice_success() {
+    let body =
+        MockResponseReader::read_response("test_resources/dynamodb", "get_lock_item_success.json");
+    let reads = Arc::new(AtomicUsize::new(0));
+    let counter = reads.clone();
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let lock_input = DynamoDbLockInput {
+        refresh_confirm: true,
+        ..Default::default()
+    };
+    assert!(lock.refresh_lock(&lock_input).is_ok());
+    assert_eq!(reads.load(Ordering::SeqCst), 2);
+    assert_eq!(lock.driver.current_token, String::from("test RVN token"));
+
+    // Strongly consistent refreshes are never confirmed
+    let lock_input = DynamoDbLockInput {
+        consistent_read: Some(true),
+        refresh_confirm: true,
+        ..Default::default()
+    };
+    assert!(lock.refresh_lock(&lock_input).is_ok());
+    assert_eq!(reads.load(Ordering::SeqCst), 3);
+}
+
+#[test]
+fn provider_name_is_dynamodb_success() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert_eq!(lock.provider_name(), "dynamodb");
+}
+
+#[test]
+fn fifo_position_orders_waiters_success() {
+    let lease = Duration::from_secs(10);
+    let mut waiters = vec![
+        String::from("waiter-a@1000"),
+        String::from("waiter-b@1001"),
+        String::from("waiter-c@1002"),
+    ];
+
+    // Only the head of the queue may try to acquire the lock
+    assert_eq!(
+        fifo_position(&waiters, "waiter-a@1000", 1005, lease),
+        Some(0)
+    );
+    assert_eq!(fifo_position(&waiters, "waiter-b@1001", 1005, lease), None);
+    assert_eq!(fifo_position(&waiters, "waiter-c@1002", 1005, lease), None);
+
+    // The next waiter goes once the head leaves the queue
+    waiters.remove(0);
+    assert_eq!(
+        fifo_position(&waiters, "waiter-b@1001", 1005, lease),
+        Some(0)
+    );
+    assert_eq!(fifo_position(&waiters, "waiter-c@1002", 1005, lease), None);
+
+    // Waiters last seen more than a lease ago are presumed dead and skipped
+    assert_eq!(
+        fifo_position(&waiters, "waiter-c@1002", 1012, lease),
+        Some(1)
+    );
+    assert_eq!(fifo_position(&waiters, "waiter-d@1003", 1012, lease), None);
+}
+
+#[test]
+fn acquire_fifo_waits_for_queue_head_fail() {
+    let body =
+        MockResponseReader::read_response("test_resources/dynamodb", "get_fifo_queue_success.json");
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.waiter_entry = Some(String::from("waiter-b@9999999999"));
+
+    let result = lock.acquire_fifo(&DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+    assert!(lock.driver.current_token.is_empty());
+    assert_eq!(
+        lock.driver.waiter_entry,
+        Some(String::from("waiter-b@9999999999"))
+    );
+}
+
+#[test]
+fn acquire_fifo_at_queue_head_success() {
+    let body =
+        MockResponseReader::read_response("test_resources/dynamodb", "get_fifo_queue_success.json");
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.waiter_entry = Some(String::from("waiter-a@9999999999"));
+
+    assert!(lock.acquire_fifo(&DynamoDbLockInput::default()).is_ok());
+    assert!(!lock.driver.current_token.is_empty());
+    assert_eq!(lock.driver.waiter_entry, None);
+}
+
+#[test]
+fn acquire_fifo_refreshes_last_seen_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_fifo_queue_long_waiter_success.json",
+    );
+    let mock = SequenceDispatcher::new(vec![
+        MockRequestDispatcher::with_status(200).with_body(&body),
+        MockRequestDispatcher::with_status(200)
+            .with_body(&body)
+            .with_request_checker(|request| {
+                let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+                assert!(payload.contains("SET #waiters_field[0] = :seen"));
+            }),
+        MockRequestDispatcher::with_status(200).with_body(&body),
+        MockRequestDispatcher::with_status(200)
+            .with_body(&body)
+            .with_request_checker(|request| {
+                let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+                assert!(payload.contains("REMOVE #waiters_field[0]"));
+                assert!(!payload.contains("\"waiter-a@1\""));
+            }),
+    ]);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    // We queued up long ago and kept polling, so the entry is still ours
+    lock.driver.waiter_entry = Some(String::from("waiter-a@1"));
+
+    assert!(lock.acquire_fifo(&DynamoDbLockInput::default()).is_ok());
+    assert!(!lock.driver.current_token.is_empty());
+    assert_eq!(lock.driver.waiter_entry, None);
+}
+
+#[test]
+fn acquire_fifo_enqueues_waiter_success() {
+    let body =
+        MockResponseReader::read_response("test_resources/dynamodb", "get_fifo_queue_success.json");
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    // The mocked queue never contains our own entry
+    let result = lock.acquire_fifo(&DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+    assert_eq!(lock.driver.waiter_entry, None);
+}
+
+#[test]
+fn refresh_lock_registers_intent_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_intents_success.json",
+    );
+    let mock = SequenceDispatcher::new(vec![
+        MockRequestDispatcher::with_status(200).with_body(&body),
+        MockRequestDispatcher::with_status(200)
+            .with_body("{}")
+            .with_request_checker(|request| {
+                let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+                assert!(payload.contains("SET #intents_field.#intent = :intent_expires_at"));
+                assert!(payload.contains("\"#intents_field\":\"intents\""));
+            }),
+    ]);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+    let lock_input = DynamoDbLockInput {
+        intent_ttl: Some(Duration::from_secs(30)),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.refresh_lock(&lock_input).is_ok());
+    assert_eq!(lock.observed_contenders(), 1);
+    assert!(lock.driver.intent.is_some());
+}
+
+#[test]
+fn refresh_lock_creates_intents_map_success() {
+    let body =
+        MockResponseReader::read_response("test_resources/dynamodb", "get_lock_item_success.json");
+    let mock = SequenceDispatcher::new(vec![
+        MockRequestDispatcher::with_status(200).with_body(&body),
+        MockRequestDispatcher::with_status(200)
+            .with_body("{}")
+            .with_request_checker(|request| {
+                let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+                assert!(payload.contains("if_not_exists(#intents_field, :empty_intents)"));
+            }),
+        MockRequestDispatcher::with_status(200)
+            .with_body("{}")
+            .with_request_checker(|request| {
+                let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+                assert!(payload.contains("SET #intents_field.#intent = :intent_expires_at"));
+            }),
+        MockRequestDispatcher::with_status(200)
+            .with_body(&body)
+            .with_request_checker(|request| {
+                let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+                assert!(!payload.contains("#intent"));
+            }),
+    ]);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+    let lock_input = DynamoDbLockInput {
+        intent_ttl: Some(Duration::from_secs(30)),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.refresh_lock(&lock_input).is_ok());
+    assert_eq!(lock.observed_contenders(), 0);
+    assert!(lock.driver.intent.is_some());
+
+    // The intent is fresh, the next poll doesn't renew it
+    let intent = lock.driver.intent.clone();
+    assert!(lock.refresh_lock(&lock_input).is_ok());
+    assert_eq!(lock.driver.intent, intent);
+}
+
+#[test]
+fn refresh_lock_without_intent_ttl_counts_intents_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_intents_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(!payload.contains("#intent"));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.observed_contenders(), 1);
+    assert_eq!(lock.driver.intent, None);
+}
+
+#[test]
+fn acquire_lock_withdraws_intent_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = SequenceDispatcher::new(vec![
+        MockRequestDispatcher::with_status(200).with_body(&body),
+        MockRequestDispatcher::with_status(200)
+            .with_body("{}")
+            .with_request_checker(|request| {
+                let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+                assert!(payload.contains("REMOVE #intents_field.#intent"));
+                assert!(payload.contains("\"#intent\":\"our intent\""));
+            }),
+    ]);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.intent = Some((String::from("our intent"), 9999999999));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.intent, None);
+}
+
+#[test]
+fn token_format_generates_uuid_renderings_success() {
+    let hyphenated = TokenFormat::Hyphenated.generate();
+    assert_eq!(hyphenated.len(), 36);
+    assert_eq!(hyphenated.matches('-').count(), 4);
+
+    let simple = TokenFormat::Simple.generate();
+    assert_eq!(simple.len(), 32);
+    assert!(!simple.contains('-'));
+
+    let urn = TokenFormat::Urn.generate();
+    assert!(urn.starts_with("urn:uuid:"));
+    assert_eq!(urn.len(), 45);
+}
+
+#[test]
+fn token_format_derives_deterministic_tokens_success() {
+    let token = TokenFormat::Hyphenated.derive("test_lock_table/resource/attempt-1");
+    assert_eq!(token.len(), 36);
+    assert_eq!(
+        token,
+        TokenFormat::Hyphenated.derive("test_lock_table/resource/attempt-1")
+    );
+    assert_ne!(
+        token,
+        TokenFormat::Hyphenated.derive("test_lock_table/resource/attempt-2")
+    );
+    assert_eq!(
+        TokenFormat::Simple.derive("test_lock_table/resource/attempt-1"),
+        token.replace("-", "")
+    );
+}
+
+#[test]
+fn validate_token_format_success() {
+    for format in &[
+        TokenFormat::Hyphenated,
+        TokenFormat::Simple,
+        TokenFormat::Urn,
+    ] {
+        assert!(validate_token_format(&format.generate(), *format));
+        assert!(validate_token_format(&format.derive("resource"), *format));
+    }
+
+    assert!(validate_token_format(
+        "67e55044-10b1-426f-9247-bb680e5fe0c8",
+        TokenFormat::Hyphenated
+    ));
+    assert!(validate_token_format(
+        "urn:uuid:67e55044-10b1-426f-9247-bb680e5fe0c8",
+        TokenFormat::Urn
+    ));
+}
+
+#[test]
+fn validate_token_format_fail() {
+    let token = TokenFormat::Hyphenated.generate();
+    assert!(!validate_token_format(&token, TokenFormat::Simple));
+    assert!(!validate_token_format(&token, TokenFormat::Urn));
+    assert!(!validate_token_format(
+        &token.to_uppercase(),
+        TokenFormat::Hyphenated
+    ));
+    assert!(!validate_token_format(&token[1..], TokenFormat::Hyphenated));
+    assert!(!validate_token_format(
+        "67e55044-10b1-426f-9247-bb680e5fe0cg",
+        TokenFormat::Hyphenated
+    ));
+    assert!(!validate_token_format(
+        "67e5504410b1-426f-9247-bb680e5fe0c8-",
+        TokenFormat::Hyphenated
+    ));
+    assert!(!validate_token_format("", TokenFormat::Simple));
+    assert!(!validate_token_format(
+        "test RVN token",
+        TokenFormat::Hyphenated
+    ));
+}
+
+#[test]
+fn acquire_lock_idempotency_key_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains("OR #token_field = :new_token"));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        partition_key_value: String::from("resource"),
+        ..Default::default()
+    };
+    let lock_input = DynamoDbLockInput {
+        idempotency_key: Some(String::from("attempt-1")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    // The token of a retried attempt doesn't change
+    let token = TokenFormat::Hyphenated.derive("test_lock_table/resource/attempt-1");
+    assert!(lock.acquire_lock(&lock_input).is_ok());
+    assert_eq!(lock.driver.current_token, token);
+    lock.driver.owns_token = false;
+    assert!(lock.acquire_lock(&lock_input).is_ok());
+    assert_eq!(lock.driver.current_token, token);
+}
+
+#[test]
+fn acquire_lock_uses_token_format_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        token_format: TokenFormat::Simple,
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.current_token.len(), 32);
+}
+
+#[test]
+fn contention_kind_detects_stale_epoch_success() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        epoch_field_name: Some(String::from("epoch")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let item = hashmap! {
+        String::from("rvn") => AttributeValue { s: Some(String::from("test RVN token")), ..Default::default() },
+        String::from("epoch") => AttributeValue { n: Some(String::from("5")), ..Default::default() },
+    };
+    assert_eq!(
+        driver.contention_kind(Some(&item), Some(4)),
+        DynaErrorKind::StaleEpoch
+    );
+    assert_eq!(
+        driver.contention_reason(Some(&item), Some(4)),
+        "stale epoch, presented (4) found (5)"
+    );
+    assert_eq!(
+        driver.contention_kind(Some(&item), Some(5)),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+    assert_eq!(
+        driver.contention_kind(Some(&item), None),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+}
+
+#[test]
+fn acquire_lock_with_epoch_writes_epoch_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains(expressions::EPOCH_CONDITION));
+            assert!(payload.contains(expressions::EPOCH_UPDATE));
+            assert!(payload.contains("\"N\":\"7\""));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        epoch_field_name: Some(String::from("epoch")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let lock_input = DynamoDbLockInput {
+        epoch: Some(7),
+        ..Default::default()
+    };
+    assert!(lock.acquire_lock(&lock_input).is_ok());
+}
+
+#[test]
+fn contention_reason_describes_failed_condition_success() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        drain_field_name: Some(String::from("drain")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let mut driver = DynamoDbDriver::new(client, &input);
+    driver.current_token = String::from("our RVN token");
+
+    assert_eq!(driver.contention_reason(None, None), "lock item is absent");
+
+    let mut item = hashmap! {
+        String::from("rvn") => AttributeValue { s: Some(String::from("test RVN token")), ..Default::default() },
+    };
+    assert_eq!(
+        driver.contention_reason(Some(&item), None),
+        "fence token mismatch, expected (our RVN token) found (test RVN token)"
+    );
+
+    driver.current_token = String::from("test RVN token");
+    assert_eq!(
+        driver.contention_reason(Some(&item), None),
+        "fence token matches, a custom condition failed"
+    );
+
+    item.insert(
+        String::from("drain"),
+        AttributeValue {
+            bool: Some(true),
+            ..Default::default()
+        },
+    );
+    assert_eq!(
+        driver.contention_reason(Some(&item), None),
+        "lock is being drained"
+    );
+}
+
+#[test]
+fn acquire_and_release_shared_lock_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains("#readers_field"));
+            assert!(!payload.contains(":new_token"));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let lock_input = DynamoDbLockInput {
+        mode: LockMode::Shared,
+        ..Default::default()
+    };
+    assert!(lock.acquire_lock(&lock_input).is_ok());
+    let reader_token = lock.driver.reader_token.clone().unwrap();
+    assert!(lock.driver.current_token.is_empty());
+
+    // Renewing keeps the same reader token
+    assert!(lock.acquire_lock(&lock_input).is_ok());
+    assert_eq!(lock.driver.reader_token, Some(reader_token));
+
+    assert!(lock.release_lock(&lock_input).is_ok());
+    assert_eq!(lock.driver.reader_token, None);
+}
+
+#[test]
+fn acquire_shared_lock_with_writer_fail() {
+    let create_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_condition_fail.json",
+    );
+    let mock = SequenceDispatcher::new(vec![
+        MockRequestDispatcher::with_status(200).with_body(&create_body),
+        MockRequestDispatcher::with_status(400).with_body(&body),
+    ]);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let lock_input = DynamoDbLockInput {
+        mode: LockMode::Shared,
+        ..Default::default()
+    };
+    let result = lock.acquire_lock(&lock_input);
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+    assert_eq!(lock.driver.reader_token, None);
+}
+
+#[test]
+fn downgrade_exclusive_lock_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            if payload.contains(expressions::SHARED_ACQUIRE_UPDATE) {
+                assert!(payload.contains(expressions::DOWNGRADE_CONDITION));
+            }
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert!(lock.downgrade(&DynamoDbLockInput::default()).is_ok());
+    assert!(lock.driver.reader_token.is_some());
+    assert!(lock.driver.current_token.is_empty());
+    assert!(!lock.driver.owns_token);
+
+    // A second downgrade has no exclusive lock left to downgrade
+    let result = lock.downgrade(&DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+}
+
+#[test]
+fn downgrade_lost_lock_fail() {
+    let create_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_condition_fail.json",
+    );
+    let mock = SequenceDispatcher::new(vec![
+        MockRequestDispatcher::with_status(200).with_body(&create_body),
+        MockRequestDispatcher::with_status(400).with_body(&body),
+    ]);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+    lock.driver.current_token = String::from("our RVN token");
+    lock.driver.owns_token = true;
+
+    let result = lock.downgrade(&DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+    assert_eq!(lock.driver.reader_token, None);
+    assert_eq!(lock.driver.current_token, String::from("our RVN token"));
+}
+
+#[test]
+fn acquire_exclusive_lock_requires_no_readers_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains(expressions::EXCLUSIVE_CONDITION));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+}
+
+#[test]
+fn acquire_exclusive_lock_overtakes_stale_reader_success() {
+    let get_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_stale_reader_success.json",
+    );
+    let update_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = SequenceDispatcher::new(vec![
+        MockRequestDispatcher::with_status(200).with_body(&get_body),
+        MockRequestDispatcher::with_status(200)
+            .with_body(&update_body)
+            .with_request_checker(|request| {
+                let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+                assert!(payload.contains(expressions::STALE_READERS_CONDITION));
+                assert!(payload.contains(expressions::READERS_CLEAR_UPDATE));
+                assert!(payload.contains("dead reader token"));
+            }),
+    ]);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    // The only reader left its lease run out without releasing the lock
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    assert!(lock.driver.stale_readers.is_some());
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert!(lock.driver.owns_token);
+    assert!(lock.driver.stale_readers.is_none());
+}
+
+#[test]
+fn acquire_exclusive_lock_with_live_reader_fail() {
+    let get_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_live_reader_success.json",
+    );
+    let update_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_condition_fail.json",
+    );
+    let mock = SequenceDispatcher::new(vec![
+        MockRequestDispatcher::with_status(200).with_body(&get_body),
+        MockRequestDispatcher::with_status(400)
+            .with_body(&update_body)
+            .with_request_checker(|request| {
+                let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+                assert!(!payload.contains(expressions::STALE_READERS_CONDITION));
+                assert!(!payload.contains(expressions::READERS_CLEAR_UPDATE));
+            }),
+    ]);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    // A single live reader keeps the expired one from being cleared out
+    assert!(lock.refresh_lock(&DynamoDbLockInput::default()).is_ok());
+    assert!(lock.driver.stale_readers.is_none());
+
+    let result = lock.acquire_lock(&DynamoDbLockInput::default());
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+}
+
+#[test]
+fn peek_many_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "batch_get_lock_items_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let keys = vec![
+        String::from("shard-1"),
+        String::from("shard-2"),
+        String::from("shard-3"),
+    ];
+    let views = driver
+        .peek_many(&keys, &DynamoDbLockInput::default())
+        .unwrap();
+    assert_eq!(views.len(), 3);
+
+    let held = views["shard-1"].as_ref().unwrap();
+    assert_eq!(held.token, Some(String::from("test RVN token")));
+    assert_eq!(held.lease, Some(Duration::from_secs(10)));
+    let now = UNIX_EPOCH + Duration::from_secs(1000000);
+    assert_eq!(held.remaining_at(now), Some(Duration::from_secs(5)));
+
+    let free = views["shard-2"].as_ref().unwrap();
+    assert_eq!(free.token, None);
+    assert_eq!(free.remaining_at(now), None);
+
+    assert_eq!(views["shard-3"], None);
+}
+
+#[test]
+fn peek_many_splits_batches_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "batch_get_lock_items_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+    let dispatcher = recording::RecordingDispatcher::new(mock);
+    let log = dispatcher.log();
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(dispatcher, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let keys: Vec<String> = (0..150).map(|i| format!("shard-{}", i)).collect();
+    let views = driver
+        .peek_many(&keys, &DynamoDbLockInput::default())
+        .unwrap();
+    assert_eq!(views.len(), 150);
+
+    let batches: Vec<usize> = log
+        .requests()
+        .iter()
+        .map(|request| {
+            assert_eq!(request.operation, "BatchGetItem");
+            request.input()["RequestItems"]["test_lock_table"]["Keys"]
+                .as_array()
+                .unwrap()
+                .len()
+        })
+        .collect();
+    assert_eq!(batches, vec![MAX_BATCH_GET_ITEMS, 50]);
+}
+
+#[test]
+fn peek_many_unprocessed_keys_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "batch_get_lock_items_unprocessed_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let sleeper = MockSleeper::default();
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input).with_sleeper(sleeper.clone());
+
+    let keys = vec![String::from("shard-1")];
+    let err = driver
+        .peek_many(&keys, &DynamoDbLockInput::default())
+        .unwrap_err();
+    assert_eq!(err.kind(), DynaErrorKind::ProviderError);
+
+    // The unprocessed keys were requested again with an exponential backoff
+    let sleeps = sleeper.sleeps();
+    assert_eq!(sleeps.len(), MAX_UNPROCESSED_RETRIES as usize);
+    assert_eq!(sleeps[0], Duration::from_millis(50));
+    assert_eq!(sleeps[1], Duration::from_millis(100));
+}
+
+#[test]
+fn peek_many_retries_are_rate_limited_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "batch_get_lock_items_unprocessed_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        max_ops_per_sec: Some(1),
+        rate_limit_mode: RateLimitMode::Fail,
+        ..Default::default()
+    };
+
+    let sleeper = MockSleeper::default();
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input).with_sleeper(sleeper.clone());
+
+    // The retry of the unprocessed keys needs a token of its own
+    let keys = vec![String::from("shard-1")];
+    let err = driver
+        .peek_many(&keys, &DynamoDbLockInput::default())
+        .unwrap_err();
+    assert_eq!(err.kind(), DynaErrorKind::RateLimited);
+    assert_eq!(sleeper.sleeps().len(), 1);
+}
+
+#[test]
+fn stats_count_acquisitions_and_holds_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let start = Instant::now();
+    let at = |secs| DynamoDbLockInput {
+        instant: Some(start + Duration::from_secs(secs)),
+        ..Default::default()
+    };
+
+    // The renewal at 4s neither counts as an acquisition nor restarts the hold
+    assert!(lock.acquire_lock(&at(0)).is_ok());
+    assert!(lock.acquire_lock(&at(4)).is_ok());
+    assert!(lock.release_lock(&at(6)).is_ok());
+    assert!(lock.acquire_lock(&at(10)).is_ok());
+    assert!(lock.release_lock(&at(12)).is_ok());
+
+    let stats = lock.driver.stats();
+    assert_eq!(stats.acquired, 2);
+    assert_eq!(stats.contended, 0);
+    assert_eq!(stats.released, 2);
+    assert_eq!(stats.average_hold(), Some(Duration::from_secs(4)));
+    assert_eq!(stats.longest_hold, Duration::from_secs(6));
+
+    lock.driver.reset_stats();
+    assert_eq!(lock.driver.stats().attempts(), 0);
+}
+
+#[test]
+fn stats_window_follows_clock_success() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let clock = MockClock::new();
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let mut driver = DynamoDbDriver::new(client, &input).with_clock(clock.clone());
+
+    clock.advance(Duration::from_secs(30));
+    assert_eq!(driver.stats().window, Duration::from_secs(30));
+
+    driver.reset_stats();
+    clock.advance(Duration::from_secs(5));
+    assert_eq!(driver.stats().window, Duration::from_secs(5));
+}
+
+#[test]
+fn stats_count_contended_attempts_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_condition_fail.json",
+    );
+    let mock = MockRequestDispatcher::with_status(400).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_err());
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_err());
+
+    let stats = lock.driver.stats();
+    assert_eq!(stats.acquired, 0);
+    assert_eq!(stats.contended, 2);
+    assert_eq!(stats.contention_ratio(), Some(1.0));
+}
+
+#[test]
+fn acquire_lock_without_duration_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(!payload.contains("#duration_field"));
+            assert!(!payload.contains(":lease"));
+            assert!(payload.contains("#expires_at_field = :expires_at"));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        write_duration: false,
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert!(lock
+        .refresh_and_extend(&DynamoDbLockInput::default())
+        .is_ok());
+}
+
+#[test]
+fn custom_expressions_without_duration_fail() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        write_duration: false,
+        ..Default::default()
+    };
+
+    // The lease duration action isn't spelled the way the driver can leave it out
+    let expressions = ExpressionSet {
+        acquire_update: expressions::ACQUIRE_UPDATE.replace(" = :lease", "=:lease"),
+        ..Default::default()
+    };
+    assert!(expressions.validate().is_ok());
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let result = DynamoDbDriver::new(client, &input).with_expressions(expressions);
+    assert_eq!(
+        result.err().unwrap().kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+}
+
+#[test]
+fn acquire_lock_writes_schema_version_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200)
+        .with_body(&body)
+        .with_request_checker(|request| {
+            let payload = String::from_utf8(request.payload.clone().unwrap()).unwrap();
+            assert!(payload.contains(expressions::SCHEMA_VERSION_CONDITION));
+            assert!(payload.contains(expressions::SCHEMA_VERSION_UPDATE));
+            assert!(payload.contains("\"#schema_version_field\":\"schema_version\""));
+            assert!(payload.contains(&format!("\"N\":\"{}\"", SCHEMA_VERSION)));
+        });
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        schema_version_field_name: Some(String::from("schema_version")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+}
+
+#[test]
+fn refresh_lock_newer_schema_version_fail() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_newer_schema_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        schema_version_field_name: Some(String::from("schema_version")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let err = lock
+        .refresh_lock(&DynamoDbLockInput::default())
+        .unwrap_err();
+    assert_eq!(err.kind(), DynaErrorKind::IncompatibleSchema);
+    assert_eq!(
+        err.to_string(),
+        "lock item schema version is incompatible: supported (1) found (2)"
+    );
+    assert!(lock.driver.current_token.is_empty());
+}
+
+#[test]
+fn contention_kind_newer_schema_version_success() {
+    let mock = MockRequestDispatcher::with_status(200);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        schema_version_field_name: Some(String::from("schema_version")),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+
+    let item = hashmap! {
+        String::from("rvn") => AttributeValue { s: Some(String::from("test RVN token")), ..Default::default() },
+        String::from("schema_version") => AttributeValue { n: Some(String::from("2")), ..Default::default() },
+    };
+    assert_eq!(
+        driver.contention_kind(Some(&item), None),
+        DynaErrorKind::IncompatibleSchema
+    );
+    assert_eq!(
+        driver.contention_reason(Some(&item), None),
+        "incompatible schema version, supported (1) found (2)"
+    );
+
+    // Lock items of the same or an older schema version are merely held
+    let item = hashmap! {
+        String::from("rvn") => AttributeValue { s: Some(String::from("test RVN token")), ..Default::default() },
+        String::from("schema_version") => AttributeValue { n: Some(String::from("1")), ..Default::default() },
+    };
+    assert_eq!(
+        driver.contention_kind(Some(&item), None),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+}
+
+#[test]
+fn refresh_lock_detects_external_extension_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "get_lock_item_with_expiry_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(2));
+
+    // We expect the lease to expire at 1000002 while the stored expiry is 1000005
+    let lock_input = DynamoDbLockInput {
+        system_time: Some(UNIX_EPOCH + Duration::from_secs(1000000)),
+        ..Default::default()
+    };
+    assert!(lock.acquire_lock(&lock_input).is_ok());
+    lock.driver.current_token = String::from("test RVN token");
+    assert_eq!(lock.driver.last_external_extension(), None);
+
+    assert!(lock.refresh_lock(&lock_input).is_ok());
+    assert!(lock.driver.owns_token);
+    assert_eq!(
+        lock.driver.last_external_extension(),
+        Some(Duration::from_secs(3))
+    );
+
+    // A stored expiry before the one we expect is no extension
+    lock.duration = Duration::from_secs(10);
+    assert!(lock.acquire_lock(&lock_input).is_ok());
+    assert!(lock.refresh_lock(&lock_input).is_ok());
+    assert_eq!(lock.driver.last_external_extension(), None);
+}
+
+#[test]
+fn random_bytes_token_generator_success() {
+    let generator = RandomBytesTokenGenerator::new(48);
+
+    let first = generator.generate().unwrap();
+    let second = generator.generate().unwrap();
+    assert_eq!(first.len(), 96);
+    assert!(first.bytes().all(|byte| (byte as char).is_digit(16)));
+    assert_ne!(first, second);
+
+    assert_eq!(
+        RandomBytesTokenGenerator::default()
+            .generate()
+            .unwrap()
+            .len(),
+        64
+    );
+}
+
+#[test]
+fn random_bytes_token_generator_too_short_fail() {
+    let generator = RandomBytesTokenGenerator::new(MIN_TOKEN_BYTES - 1);
+
+    assert_eq!(
+        generator.generate().err().unwrap().kind(),
+        DynaErrorKind::InvalidConfiguration
+    );
+}
+
+#[test]
+fn acquire_lock_with_token_generator_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input)
+        .with_token_generator(RandomBytesTokenGenerator::default());
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert_eq!(lock.driver.current_token.len(), 64);
+    assert!(!validate_token_format(
+        &lock.driver.current_token,
+        TokenFormat::Hyphenated
+    ));
+}
+
+#[test]
+fn touch_extends_remaining_success() {
+    let body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let mock = MockRequestDispatcher::with_status(200).with_body(&body);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    let acquired = lock.acquire_lock(&DynamoDbLockInput::default()).unwrap();
+    thread::sleep(Duration::from_millis(20));
+
+    let extended = lock.touch(&DynamoDbLockInput::default()).unwrap();
+    assert!(extended > acquired);
+    assert!(lock.remaining(extended).unwrap() > lock.remaining(acquired).unwrap());
+}
+
+#[test]
+fn touch_after_lease_lost_fail() {
+    let update_body = MockResponseReader::read_response(
+        "test_resources/dynamodb",
+        "update_lock_item_success.json",
+    );
+    let get_body =
+        MockResponseReader::read_response("test_resources/dynamodb", "get_lock_item_success.json");
+
+    // Our lease ran out and another processor acquired the lock before the extension
+    let mock = SequenceDispatcher::new(vec![
+        MockRequestDispatcher::with_status(200).with_body(&update_body),
+        MockRequestDispatcher::with_status(400)
+            .with_body(r#"{"__type": "ConditionalCheckFailedException"}"#),
+        MockRequestDispatcher::with_status(200).with_body(&get_body),
+    ]);
+
+    // Prepare input for DynamoDbDriver
+    let input = DynamoDbDriverInput {
+        table_name: String::from("test_lock_table"),
+        partition_key_field_name: String::from("lock_id"),
+        ..Default::default()
+    };
+
+    let client = DynamoDbClient::new(mock, MockCredentialsProvider, Region::UsEast1);
+    let driver = DynamoDbDriver::new(client, &input);
+    let mut lock = DistLock::new(driver, Duration::from_secs(10));
+
+    assert!(lock.acquire_lock(&DynamoDbLockInput::default()).is_ok());
+    assert!(lock.driver.owns_token);
+
+    assert_eq!(
+        lock.touch(&DynamoDbLockInput::default())
+            .err()
+            .unwrap()
+            .kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+    assert!(!lock.driver.owns_token);
+    assert_eq!(lock.driver.current_token, String::from("test RVN token"));
+
+    // The worker must stop, further extensions fail without calling DynamoDB
+    assert_eq!(
+        lock.touch(&DynamoDbLockInput::default())
+            .err()
+            .unwrap()
+            .kind(),
+        DynaErrorKind::LockAlreadyAcquired
+    );
+}
@@ -0,0 +1,94 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Pluggable generation of random fence tokens.
+//!
+//! The driver renders random fence tokens as v4 UUIDs in its `TokenFormat` by default,
+//! which carry 122 bits of entropy. A `TokenGenerator` installed with
+//! `DynamoDbDriver::with_token_generator` replaces it, e.g., a `RandomBytesTokenGenerator`
+//! for policies that require longer tokens read straight from the OS random number
+//! generator. Tokens derived from idempotency keys are still rendered in the
+//! `TokenFormat`, since they must be deterministic.
+
+use rand::{OsRng, Rng};
+
+use super::TokenFormat;
+use {DynaError, DynaErrorKind};
+
+/// The smallest number of random bytes a `RandomBytesTokenGenerator` accepts.
+pub const MIN_TOKEN_BYTES: usize = 16;
+
+/// The TokenGenerator trait generates new random fence tokens.
+pub trait TokenGenerator {
+    /// Generate a new fence token, which must not collide with any token generated
+    /// before by any processor.
+    fn generate(&self) -> Result<String, DynaError>;
+}
+
+impl TokenGenerator for TokenFormat {
+    fn generate(&self) -> Result<String, DynaError> {
+        Ok(TokenFormat::generate(self))
+    }
+}
+
+/// A generator of fence tokens made of `bytes` random bytes read from the OS random
+/// number generator, hex-encoded.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RandomBytesTokenGenerator {
+    /// The number of random bytes in every token, at least `MIN_TOKEN_BYTES` (default:
+    /// 32, i.e., 256 bits). Tokens are twice as long once hex-encoded.
+    pub bytes: usize,
+}
+
+impl RandomBytesTokenGenerator {
+    /// Create a generator of tokens made of `bytes` random bytes.
+    pub fn new(bytes: usize) -> Self {
+        RandomBytesTokenGenerator { bytes: bytes }
+    }
+}
+
+impl Default for RandomBytesTokenGenerator {
+    fn default() -> Self {
+        RandomBytesTokenGenerator::new(32)
+    }
+}
+
+impl TokenGenerator for RandomBytesTokenGenerator {
+    fn generate(&self) -> Result<String, DynaError> {
+        if self.bytes < MIN_TOKEN_BYTES {
+            return Err(DynaError::new(
+                DynaErrorKind::InvalidConfiguration,
+                Some(&format!(
+                    "tokens of {} random bytes are too short, {} required",
+                    self.bytes, MIN_TOKEN_BYTES
+                )),
+            ));
+        }
+
+        let mut rng = OsRng::new().map_err(|err| {
+            DynaError::new(
+                DynaErrorKind::ProviderError,
+                Some(&format!(
+                    "OS random number generator is unavailable: {}",
+                    err
+                )),
+            )
+        })?;
+
+        let mut random = vec![0u8; self.bytes];
+        rng.fill_bytes(&mut random);
+
+        Ok(random.iter().map(|byte| format!("{:02x}", byte)).collect())
+    }
+}
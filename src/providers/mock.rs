@@ -0,0 +1,366 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A dependency-free driver for unit tests.
+//!
+//! The `MockDriver` implements the `Locking` trait without any storage service, so
+//! code built on top of a `DistLock` can be unit tested without the dependencies of
+//! a real provider. Lock items live in a `MockStore`, keyed by partition key value,
+//! which drivers can share to simulate several processors contending for the same
+//! lock. Like lock items in a lock table, each one holds the fence token of its holder
+//! and the expiry of its lease.
+//!
+//! Acquisitions follow the Dynalock algorithm: the lock is granted when it is free,
+//! when its lease expired, or when the stored fence token is the one the driver knows
+//! of, either because it wrote it or because `refresh_lock` observed it.
+//!
+//! Leases are measured with the driver's `Clock`. Drivers sharing a store can share a
+//! `MockClock` too, so that advancing it expires leases without real delays.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clock::{Clock, MonotonicClock};
+use {DistLock, DynaError, DynaErrorKind, Locking};
+
+/// A lock item of a `MockStore`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+struct MockItem {
+    token: u64,
+    expires_at: Instant,
+}
+
+/// The lock items of a `MockStore` and the last fence token written.
+#[derive(Debug, Default)]
+struct MockItems {
+    items: HashMap<String, MockItem>,
+    last_token: u64,
+}
+
+/// The in-memory lock items of `MockDriver` instances.
+///
+/// Clones share the same lock items, so drivers created with clones of a store contend
+/// for the same locks.
+#[derive(Debug, Clone, Default)]
+pub struct MockStore {
+    inner: Arc<Mutex<MockItems>>,
+}
+
+impl MockStore {
+    /// Return the fence token stored on the lock item of `key`, if any.
+    pub fn token(&self, key: &str) -> Option<u64> {
+        self.inner
+            .lock()
+            .unwrap()
+            .items
+            .get(key)
+            .map(|item| item.token)
+    }
+}
+
+/// A driver keeping the lock state in memory.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use dynalock::{DistLock, DynaErrorKind, Locking};
+/// use dynalock::providers::mock::{MockDriver, MockStore};
+///
+/// let store = MockStore::default();
+/// let lease = Duration::from_secs(10);
+/// let mut first = DistLock::new(MockDriver::with_store(store.clone(), "job"), lease);
+/// let mut second = DistLock::new(MockDriver::with_store(store, "job"), lease);
+///
+/// let acquired = first.acquire_lock(&()).unwrap();
+/// assert!(first.remaining(acquired).is_some());
+///
+/// let err = second.acquire_lock(&()).unwrap_err();
+/// assert_eq!(err.kind(), DynaErrorKind::LockAlreadyAcquired);
+/// # assert_eq!(first.driver().current_token(), Some(1));
+/// ```
+pub struct MockDriver {
+    store: MockStore,
+    key: String,
+    current_token: Option<u64>,
+    acquisitions: u64,
+    fail_next: bool,
+    clock: Box<Clock + Send>,
+}
+
+impl MockDriver {
+    /// Initialize a new MockDriver structure with a store of its own.
+    pub fn new() -> Self {
+        MockDriver::with_store(MockStore::default(), "mock")
+    }
+
+    /// Initialize a new MockDriver structure locking `key` in `store`.
+    pub fn with_store(store: MockStore, key: &str) -> Self {
+        MockDriver {
+            store: store,
+            key: String::from(key),
+            current_token: None,
+            acquisitions: 0,
+            fail_next: false,
+            clock: Box::new(MonotonicClock),
+        }
+    }
+
+    /// Replace the clock leases are measured with (default: `MonotonicClock`), e.g., with
+    /// a `MockClock` shared by the drivers of a store to expire leases without delays.
+    pub fn with_clock<C>(mut self, clock: C) -> Self
+    where
+        C: Clock + Send + 'static,
+    {
+        self.clock = Box::new(clock);
+        self
+    }
+
+    /// Return the fence token we know of, `None` when no token is known.
+    pub fn current_token(&self) -> Option<u64> {
+        self.current_token
+    }
+
+    /// Return the number of successful acquisitions.
+    pub fn acquisitions(&self) -> u64 {
+        self.acquisitions
+    }
+
+    /// Make the next lock operation fail with `DynaErrorKind::ProviderError`, e.g., to
+    /// test retries.
+    pub fn fail_next(&mut self) {
+        self.fail_next = true;
+    }
+
+    /// Return the injected failure, if any, and reset it.
+    fn injected_failure(&mut self) -> Result<(), DynaError> {
+        if self.fail_next {
+            self.fail_next = false;
+            return Err(DynaError::new(
+                DynaErrorKind::ProviderError,
+                Some("injected failure"),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl Default for MockDriver {
+    fn default() -> Self {
+        MockDriver::new()
+    }
+}
+
+impl Locking for DistLock<MockDriver> {
+    type AcquireLockInputType = ();
+    type RefreshLockInputType = ();
+    type ReleaseLockInputType = ();
+
+    fn acquire_lock(&mut self, _input: &()) -> Result<Instant, DynaError> {
+        self.driver.injected_failure()?;
+
+        let store = self.driver.store.clone();
+        let mut inner = store.inner.lock().unwrap();
+        let now = self.driver.clock.now();
+
+        if let Some(item) = inner.items.get(&self.driver.key) {
+            if Some(item.token) != self.driver.current_token && item.expires_at > now {
+                return Err(DynaError::new(DynaErrorKind::LockAlreadyAcquired, None));
+            }
+        }
+
+        inner.last_token += 1;
+        let token = inner.last_token;
+        inner.items.insert(
+            self.driver.key.clone(),
+            MockItem {
+                token: token,
+                expires_at: now + self.duration,
+            },
+        );
+
+        self.driver.current_token = Some(token);
+        self.driver.acquisitions += 1;
+
+        Ok(now)
+    }
+
+    fn refresh_lock(&mut self, _input: &()) -> Result<(), DynaError> {
+        self.driver.injected_failure()?;
+
+        if let Some(token) = self.driver.store.token(&self.driver.key) {
+            self.driver.current_token = Some(token);
+        }
+
+        Ok(())
+    }
+
+    fn remaining(&self, instant: Instant) -> Option<Duration> {
+        self.duration
+            .checked_sub(self.driver.clock.elapsed(instant))
+    }
+
+    fn observed_token(&self) -> Option<String> {
+        self.driver.current_token.map(|token| token.to_string())
+    }
+
+    fn release_lock(&mut self, _input: &()) -> Result<(), DynaError> {
+        self.driver.injected_failure()?;
+
+        let store = self.driver.store.clone();
+        let mut inner = store.inner.lock().unwrap();
+
+        match inner.items.get(&self.driver.key).map(|item| item.token) {
+            Some(token) if Some(token) != self.driver.current_token => {
+                return Err(DynaError::new(DynaErrorKind::LockAlreadyAcquired, None));
+            }
+            Some(_) => {
+                inner.items.remove(&self.driver.key);
+            }
+            None => {}
+        }
+        self.driver.current_token = None;
+
+        Ok(())
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "mock"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clock::MockClock;
+    use contract::assert_locking_contract;
+    use std::thread;
+
+    #[test]
+    fn test_mock_acquire_writes_new_token_success() {
+        let mut lock = DistLock::new(MockDriver::new(), Duration::from_secs(10));
+        assert_eq!(lock.driver.current_token(), None);
+
+        assert!(lock.acquire_lock(&()).is_ok());
+        assert_eq!(lock.driver.current_token(), Some(1));
+        assert!(lock.acquire_lock(&()).is_ok());
+        assert_eq!(lock.driver.current_token(), Some(2));
+        assert_eq!(lock.driver.acquisitions(), 2);
+    }
+
+    #[test]
+    fn test_mock_release_clears_token_success() {
+        let mut lock = DistLock::new(MockDriver::new(), Duration::from_secs(10));
+
+        let acquired = lock.acquire_lock(&()).unwrap();
+        assert!(lock.remaining(acquired).unwrap() <= Duration::from_secs(10));
+        assert_eq!(lock.release_lock(&()), Ok(()));
+        assert_eq!(lock.driver.current_token(), None);
+    }
+
+    #[test]
+    fn test_mock_locking_contract_success() {
+        let store = MockStore::default();
+
+        assert_locking_contract(
+            || {
+                DistLock::new(
+                    MockDriver::with_store(store.clone(), "job"),
+                    Duration::from_secs(10),
+                )
+            },
+            &(),
+            &(),
+            &(),
+        );
+    }
+
+    #[test]
+    fn test_mock_contended_until_lease_expires_fail() {
+        let store = MockStore::default();
+        let mut holder = DistLock::new(
+            MockDriver::with_store(store.clone(), "job"),
+            Duration::from_millis(50),
+        );
+        let mut contender = DistLock::new(
+            MockDriver::with_store(store.clone(), "job"),
+            Duration::from_millis(50),
+        );
+        let mut other = DistLock::new(
+            MockDriver::with_store(store.clone(), "other job"),
+            Duration::from_millis(50),
+        );
+
+        assert!(holder.acquire_lock(&()).is_ok());
+        let err = contender.acquire_lock(&()).unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::LockAlreadyAcquired);
+        assert!(other.acquire_lock(&()).is_ok());
+
+        thread::sleep(Duration::from_millis(60));
+        assert!(contender.acquire_lock(&()).is_ok());
+        assert_eq!(store.token("job"), contender.driver.current_token());
+
+        // The previous holder can't release the lock it lost
+        let err = holder.release_lock(&()).unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::LockAlreadyAcquired);
+    }
+
+    #[test]
+    fn test_mock_clock_expires_lease_success() {
+        let store = MockStore::default();
+        let clock = MockClock::new();
+        let mut first = DistLock::new(
+            MockDriver::with_store(store.clone(), "job").with_clock(clock.clone()),
+            Duration::from_secs(5),
+        );
+        let mut second = DistLock::new(
+            MockDriver::with_store(store.clone(), "job").with_clock(clock.clone()),
+            Duration::from_secs(5),
+        );
+
+        let acquired = first.acquire_lock(&()).unwrap();
+        assert_eq!(first.remaining(acquired), Some(Duration::from_secs(5)));
+
+        clock.advance(Duration::from_secs(6));
+        assert_eq!(first.remaining(acquired), None);
+        assert!(second.acquire_lock(&()).is_ok());
+        assert_eq!(store.token("job"), second.driver.current_token());
+
+        // The first holder can't renew the lease it lost
+        let err = first.acquire_lock(&()).unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::LockAlreadyAcquired);
+    }
+
+    #[test]
+    fn test_mock_fail_next_fail() {
+        let mut lock = DistLock::new(MockDriver::new(), Duration::from_secs(10));
+
+        lock.driver.fail_next();
+        let err = lock.acquire_lock(&()).unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::ProviderError);
+
+        // Only the next operation fails
+        assert!(lock.acquire_lock(&()).is_ok());
+    }
+
+    #[test]
+    fn test_mock_remaining_expired_success() {
+        let lock = DistLock::new(MockDriver::new(), Duration::from_secs(1));
+        let acquired = Instant::now() - Duration::from_secs(2);
+
+        assert_eq!(lock.remaining(acquired), None);
+    }
+}
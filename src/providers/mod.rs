@@ -16,3 +16,6 @@
 
 #[cfg(feature = "dynamodb")]
 pub mod dynamodb;
+
+pub mod mock;
+pub mod shadow;
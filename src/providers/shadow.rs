@@ -0,0 +1,272 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A decorator driver that mirrors lock operations onto a second provider.
+//!
+//! The `ShadowDriver` is meant for migrations between providers. Every operation
+//! is carried out on the primary lock, which alone decides the outcome, and then
+//! mirrored on the shadow lock. Whenever the two providers disagree (e.g., the
+//! shadow grants a lock the primary reports as contended) the divergence is logged
+//! and counted, while errors from the shadow are never returned to the caller.
+
+use std::time::{Duration, Instant};
+
+use {DistLock, DynaError, DynaErrorKind, Locking};
+
+/// A driver that drives a `primary` lock and mirrors it on a `shadow` lock.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use dynalock::DistLock;
+/// use dynalock::providers::shadow::ShadowDriver;
+///
+/// let primary = DistLock::new("primary driver", Duration::from_secs(10));
+/// let shadow = DistLock::new("shadow driver", Duration::from_secs(10));
+///
+/// let mut lock = DistLock::new(
+///     ShadowDriver::new(primary, shadow),
+///     Duration::from_secs(10)
+///     );
+///
+/// # assert_eq!(lock.driver().divergences(), 0);
+/// ```
+#[derive(Debug)]
+pub struct ShadowDriver<Primary, Shadow> {
+    primary: DistLock<Primary>,
+    shadow: DistLock<Shadow>,
+    divergences: u64,
+}
+
+impl<Primary, Shadow> ShadowDriver<Primary, Shadow> {
+    /// Initialize a new ShadowDriver structure from the `primary` and `shadow` locks.
+    pub fn new(primary: DistLock<Primary>, shadow: DistLock<Shadow>) -> Self {
+        ShadowDriver {
+            primary: primary,
+            shadow: shadow,
+            divergences: 0,
+        }
+    }
+
+    /// Return a mutable reference to the authoritative lock.
+    pub fn primary(&mut self) -> &mut DistLock<Primary> {
+        &mut self.primary
+    }
+
+    /// Return a mutable reference to the mirrored lock.
+    pub fn shadow(&mut self) -> &mut DistLock<Shadow> {
+        &mut self.shadow
+    }
+
+    /// Return the number of operations the two providers disagreed on.
+    pub fn divergences(&self) -> u64 {
+        self.divergences
+    }
+}
+
+impl<Primary, Shadow> ShadowDriver<Primary, Shadow>
+where
+    DistLock<Primary>: Locking,
+    DistLock<Shadow>: Locking,
+{
+    /// Record a divergence if the primary and shadow outcomes of `operation` differ.
+    fn compare<T, U>(
+        &mut self,
+        operation: &str,
+        primary: &Result<T, DynaError>,
+        shadow: &Result<U, DynaError>,
+    ) {
+        let primary_kind = primary.as_ref().err().map(|err| err.kind());
+        let shadow_kind = shadow.as_ref().err().map(|err| err.kind());
+
+        if primary_kind != shadow_kind {
+            self.divergences += 1;
+            warn!(
+                "shadow lock diverged on {}, primary {} ({}) shadow {} ({})",
+                operation,
+                self.primary.provider_name(),
+                describe(primary_kind),
+                self.shadow.provider_name(),
+                describe(shadow_kind)
+            );
+        }
+    }
+}
+
+/// Describe the outcome of an operation for the divergence log.
+fn describe(kind: Option<DynaErrorKind>) -> &'static str {
+    match kind {
+        Some(DynaErrorKind::LockAlreadyAcquired) => "contended",
+        Some(_) => "failed",
+        None => "succeeded",
+    }
+}
+
+/// A structure that holds the inputs of both the primary and the shadow lock.
+#[derive(Debug, Clone, Default)]
+pub struct ShadowInput<P, S> {
+    /// The input of the primary lock operation.
+    pub primary: P,
+    /// The input of the mirrored shadow lock operation.
+    pub shadow: S,
+}
+
+impl<Primary, Shadow> Locking for DistLock<ShadowDriver<Primary, Shadow>>
+where
+    DistLock<Primary>: Locking,
+    DistLock<Shadow>: Locking,
+{
+    type AcquireLockInputType = ShadowInput<
+        <DistLock<Primary> as Locking>::AcquireLockInputType,
+        <DistLock<Shadow> as Locking>::AcquireLockInputType,
+    >;
+    type RefreshLockInputType = ShadowInput<
+        <DistLock<Primary> as Locking>::RefreshLockInputType,
+        <DistLock<Shadow> as Locking>::RefreshLockInputType,
+    >;
+    type ReleaseLockInputType = ShadowInput<
+        <DistLock<Primary> as Locking>::ReleaseLockInputType,
+        <DistLock<Shadow> as Locking>::ReleaseLockInputType,
+    >;
+
+    fn acquire_lock(&mut self, input: &Self::AcquireLockInputType) -> Result<Instant, DynaError> {
+        let primary = self.driver.primary.acquire_lock(&input.primary);
+        let shadow = self.driver.shadow.acquire_lock(&input.shadow);
+        self.driver.compare("acquire", &primary, &shadow);
+
+        primary
+    }
+
+    fn refresh_lock(&mut self, input: &Self::RefreshLockInputType) -> Result<(), DynaError> {
+        let primary = self.driver.primary.refresh_lock(&input.primary);
+        let shadow = self.driver.shadow.refresh_lock(&input.shadow);
+        self.driver.compare("refresh", &primary, &shadow);
+
+        primary
+    }
+
+    fn remaining(&self, instant: Instant) -> Option<Duration> {
+        self.driver.primary.remaining(instant)
+    }
+
+    fn observed_token(&self) -> Option<String> {
+        self.driver.primary.observed_token()
+    }
+
+    fn has_lease(&self) -> bool {
+        self.driver.primary.has_lease()
+    }
+
+    fn provider_name(&self) -> &'static str {
+        "shadow"
+    }
+
+    fn release_lock(&mut self, input: &Self::ReleaseLockInputType) -> Result<(), DynaError> {
+        let primary = self.driver.primary.release_lock(&input.primary);
+        let shadow = self.driver.shadow.release_lock(&input.shadow);
+        self.driver.compare("release", &primary, &shadow);
+
+        primary
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A driver that either always grants the lock or always reports contention.
+    #[derive(Debug)]
+    struct FixedDriver {
+        grant: bool,
+        calls: u32,
+    }
+
+    impl FixedDriver {
+        fn new(grant: bool) -> Self {
+            FixedDriver {
+                grant: grant,
+                calls: 0,
+            }
+        }
+    }
+
+    impl Locking for DistLock<FixedDriver> {
+        type AcquireLockInputType = ();
+        type RefreshLockInputType = ();
+        type ReleaseLockInputType = ();
+
+        fn acquire_lock(&mut self, _input: &()) -> Result<Instant, DynaError> {
+            self.driver.calls += 1;
+
+            if self.driver.grant {
+                Ok(Instant::now())
+            } else {
+                Err(DynaError::new(DynaErrorKind::LockAlreadyAcquired, None))
+            }
+        }
+
+        fn refresh_lock(&mut self, _input: &()) -> Result<(), DynaError> {
+            self.driver.calls += 1;
+            Ok(())
+        }
+
+        fn remaining(&self, instant: Instant) -> Option<Duration> {
+            self.duration.checked_sub(instant.elapsed())
+        }
+    }
+
+    fn shadow_lock(
+        primary_grants: bool,
+        shadow_grants: bool,
+    ) -> DistLock<ShadowDriver<FixedDriver, FixedDriver>> {
+        let primary = DistLock::new(FixedDriver::new(primary_grants), Duration::from_secs(10));
+        let shadow = DistLock::new(FixedDriver::new(shadow_grants), Duration::from_secs(30));
+
+        DistLock::new(ShadowDriver::new(primary, shadow), Duration::from_secs(10))
+    }
+
+    #[test]
+    fn test_shadow_agreement_success() {
+        let mut lock = shadow_lock(true, true);
+
+        let instant = lock.acquire_lock(&ShadowInput::default()).unwrap();
+        assert!(lock.refresh_lock(&ShadowInput::default()).is_ok());
+        assert!(lock.remaining(instant).unwrap() <= Duration::from_secs(10));
+        assert_eq!(lock.driver().divergences(), 0);
+        assert_eq!(lock.driver().shadow().driver().calls, 2);
+        assert_eq!(lock.provider_name(), "shadow");
+    }
+
+    #[test]
+    fn test_shadow_grants_while_primary_contended_fail() {
+        let mut lock = shadow_lock(false, true);
+
+        let result = lock.acquire_lock(&ShadowInput::default());
+        assert_eq!(
+            result.err().unwrap().kind(),
+            DynaErrorKind::LockAlreadyAcquired
+        );
+        assert_eq!(lock.driver().divergences(), 1);
+    }
+
+    #[test]
+    fn test_shadow_contended_does_not_affect_primary_success() {
+        let mut lock = shadow_lock(true, false);
+
+        assert!(lock.acquire_lock(&ShadowInput::default()).is_ok());
+        assert_eq!(lock.driver().divergences(), 1);
+        assert_eq!(lock.driver().primary().driver().calls, 1);
+    }
+}
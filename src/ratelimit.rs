@@ -0,0 +1,151 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A token bucket rate limiter driven by the monotonic clock.
+//!
+//! Providers use the `TokenBucket` structure to cap the rate of operations they
+//! issue against a storage service. The bucket holds up to `ops_per_sec` tokens,
+//! refills continuously at `ops_per_sec` tokens per second and every operation
+//! consumes a single token.
+
+use std::time::{Duration, Instant};
+
+/// What to do when an operation is attempted while the bucket is empty.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum RateLimitMode {
+    /// Sleep until a token becomes available and then carry on with the operation.
+    Block,
+    /// Fail the operation immediately with `DynaErrorKind::RateLimited`.
+    Fail,
+}
+
+impl Default for RateLimitMode {
+    fn default() -> Self {
+        RateLimitMode::Block
+    }
+}
+
+/// A token bucket that refills continuously at a fixed rate.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    capacity: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    /// Create a new full bucket allowing `ops_per_sec` operations per second,
+    /// starting to count from `now`.
+    ///
+    /// A rate of zero is treated as one operation per second.
+    pub fn new(ops_per_sec: u32, now: Instant) -> Self {
+        let capacity = if ops_per_sec == 0 { 1 } else { ops_per_sec } as f64;
+
+        TokenBucket {
+            capacity: capacity,
+            tokens: capacity,
+            last_refill: now,
+        }
+    }
+
+    /// Try to take a single token from the bucket at the point in time `now`.
+    ///
+    /// On failure the duration to wait until the next token becomes available is
+    /// returned in the `Err` variant.
+    pub fn try_take(&mut self, now: Instant) -> Result<(), Duration> {
+        self.refill(now);
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            return Ok(());
+        }
+
+        let wait = (1.0 - self.tokens) / self.capacity;
+        Err(Duration::new(
+            wait.trunc() as u64,
+            (wait.fract() * 1_000_000_000.0) as u32,
+        ))
+    }
+
+    /// Return the number of whole tokens currently available.
+    pub fn available(&self) -> u32 {
+        self.tokens as u32
+    }
+
+    fn refill(&mut self, now: Instant) {
+        // Instants earlier than the last refill are ignored, the bucket never drains
+        // because of the order in which callers sample the clock.
+        if now <= self.last_refill {
+            return;
+        }
+
+        let elapsed = now.duration_since(self.last_refill);
+        let elapsed_secs = elapsed.as_secs() as f64 + elapsed.subsec_nanos() as f64 / 1e9;
+
+        self.tokens = (self.tokens + elapsed_secs * self.capacity).min(self.capacity);
+        self.last_refill = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_bucket_starts_full_success() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(3, now);
+
+        assert_eq!(bucket.available(), 3);
+        assert!(bucket.try_take(now).is_ok());
+        assert!(bucket.try_take(now).is_ok());
+        assert!(bucket.try_take(now).is_ok());
+        assert!(bucket.try_take(now).is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_refills_over_time_success() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2, now);
+
+        assert!(bucket.try_take(now).is_ok());
+        assert!(bucket.try_take(now).is_ok());
+
+        let wait = bucket.try_take(now).unwrap_err();
+        assert_eq!(wait, Duration::from_millis(500));
+
+        let later = now + Duration::from_millis(500);
+        assert!(bucket.try_take(later).is_ok());
+        assert!(bucket.try_take(later).is_err());
+    }
+
+    #[test]
+    fn test_token_bucket_never_exceeds_capacity_success() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(2, now);
+
+        let later = now + Duration::from_secs(60);
+        assert!(bucket.try_take(later).is_ok());
+        assert_eq!(bucket.available(), 1);
+    }
+
+    #[test]
+    fn test_token_bucket_zero_rate_is_one_per_second_success() {
+        let now = Instant::now();
+        let mut bucket = TokenBucket::new(0, now);
+
+        assert!(bucket.try_take(now).is_ok());
+        assert_eq!(bucket.try_take(now).unwrap_err(), Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,546 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! A background thread keeping the lease of a lock alive.
+//!
+//! `DistLock::spawn_refresher` moves the lock to a thread that renews the lease with
+//! `acquire_lock` at a fixed interval, for critical sections that run longer than a
+//! single lease. The returned `RefreshHandle` exposes whether the lease is currently
+//! valid and the result of the last renewal, and gives the lock back once stopped.
+//!
+//! The refresher gives up as soon as a renewal fails with
+//! `DynaErrorKind::LockAlreadyAcquired`, as another processor then holds the lock.
+//! Other errors are retried at the next interval, or sooner when the lease would expire
+//! before it. `DistLock::spawn_refresher_with_flag` drives a flag the caller already
+//! shares with its workers, which is cleared the moment the lease is lost.
+//!
+//! Renewals can be paused and resumed through the handle without releasing the lock,
+//! e.g., to quiet the renewal traffic during maintenance.
+//!
+//! Long-lived leaders use `DistLock::spawn_self_healing_refresher` instead, which keeps
+//! trying to re-acquire a lost lock and resumes renewing once it wins it back.
+
+use std::cmp;
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use retry::{Jitter, RetryPolicy};
+use {DistLock, DynaErrorKind, Locking};
+
+/// How the lease renewed by a refresher was lost.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum LeaseLoss {
+    /// The lease ran out because renewals kept failing (or were paused).
+    Expired,
+    /// Another processor took the lock over.
+    TakenOver,
+}
+
+/// A command sent to the refresher thread.
+enum Command {
+    Pause,
+    Resume,
+    Stop,
+}
+
+/// A handle on the thread started by `DistLock::spawn_refresher`.
+pub struct RefreshHandle<Driver> {
+    valid: Arc<AtomicBool>,
+    last_result: Arc<Mutex<Option<Result<Instant, DynaErrorKind>>>>,
+    token: Arc<Mutex<Option<String>>>,
+    commands: Sender<Command>,
+    thread: JoinHandle<DistLock<Driver>>,
+}
+
+impl<Driver> RefreshHandle<Driver> {
+    /// Return true while the lease renewed by the refresher is valid.
+    pub fn is_valid(&self) -> bool {
+        self.valid.load(Ordering::SeqCst)
+    }
+
+    /// Return the flag telling whether the lease is valid, to be shared with workers.
+    ///
+    /// This is the flag given to `spawn_refresher_with_flag`, if any.
+    pub fn valid_flag(&self) -> Arc<AtomicBool> {
+        self.valid.clone()
+    }
+
+    /// Return the result of the last renewal, `None` before the first one completed.
+    ///
+    /// A renewal returns the `Instant` its lease starts at, or the kind of the error it
+    /// failed with. Errors are logged in full by the refresher.
+    pub fn last_result(&self) -> Option<Result<Instant, DynaErrorKind>> {
+        *self.last_result.lock().unwrap()
+    }
+
+    /// Return the fence token of the last successful renewal, as returned by
+    /// `Locking::observed_token`, `None` before the first one completed.
+    pub fn token(&self) -> Option<String> {
+        self.token.lock().unwrap().clone()
+    }
+
+    /// Pause the renewals without releasing the lock, until `resume` is called.
+    ///
+    /// A renewal in flight completes. While paused the validity flag is still cleared
+    /// once the lease of the last renewal ends, and locks without a lease are reported
+    /// invalid right away, since nothing tells whether they are still held.
+    pub fn pause(&self) {
+        // The thread may have already given up and dropped the receiver
+        let _ = self.commands.send(Command::Pause);
+    }
+
+    /// Resume the renewals paused by `pause`, starting with one right away.
+    ///
+    /// When the pause outlasted the lease, that renewal fails with
+    /// `DynaErrorKind::LockAlreadyAcquired` if another processor took the lock over in
+    /// the meantime, which stops the refresher and is reported by `last_result`.
+    pub fn resume(&self) {
+        let _ = self.commands.send(Command::Resume);
+    }
+
+    /// Stop the refresher, wait for its thread to exit and return the lock.
+    ///
+    /// The lease is no longer renewed from then on, and the validity flag is cleared.
+    /// The lock is not released, see `release_lock` for that.
+    pub fn stop(self) -> DistLock<Driver> {
+        // The thread may have already given up and dropped the receiver
+        let _ = self.commands.send(Command::Stop);
+
+        match self.thread.join() {
+            Ok(lock) => lock,
+            Err(err) => panic::resume_unwind(err),
+        }
+    }
+}
+
+impl<Driver> DistLock<Driver>
+where
+    DistLock<Driver>: Locking,
+    Driver: Send + 'static,
+    <DistLock<Driver> as Locking>::AcquireLockInputType: Send + 'static,
+{
+    /// Move the lock to a background thread renewing its lease with `acquire_lock` right
+    /// away and then every `interval`.
+    ///
+    /// The interval is clamped to half of the lease duration, so that a renewal is always
+    /// attempted well before the lease ends. A renewal failing with another error than
+    /// `DynaErrorKind::LockAlreadyAcquired` is retried at the next interval, or as soon
+    /// as the lease of the last successful renewal ends, whichever comes first. The
+    /// validity flag of the handle is updated after every renewal attempt.
+    ///
+    /// The refresher stops on its own and clears the validity flag when a renewal fails
+    /// with `DynaErrorKind::LockAlreadyAcquired`, in which case `last_result` returns that
+    /// error kind. Call `RefreshHandle::stop` to get the lock back in any case.
+    ///
+    /// Locks without a lease (see `Locking::has_lease`) have no `remaining` time to judge
+    /// validity by, their lease is reported valid as long as the last renewal succeeded,
+    /// since a renewal only succeeds while we still hold the lock.
+    pub fn spawn_refresher(
+        self,
+        input: <Self as Locking>::AcquireLockInputType,
+        interval: Duration,
+    ) -> RefreshHandle<Driver> {
+        self.spawn_refresher_with_flag(input, interval, Arc::new(AtomicBool::new(false)))
+    }
+
+    /// Like `spawn_refresher`, but report the validity of the lease on `valid`, e.g., a
+    /// cancellation flag the caller already shares with its workers.
+    ///
+    /// The flag is left as is until the first renewal completes. From then on it is
+    /// cleared the moment a renewal finds the lock taken over by another processor, or
+    /// once the lease expires because renewals keep failing, so workers can abort their
+    /// work without polling `remaining` themselves. It is also cleared when the refresher
+    /// stops.
+    pub fn spawn_refresher_with_flag(
+        self,
+        input: <Self as Locking>::AcquireLockInputType,
+        interval: Duration,
+        valid: Arc<AtomicBool>,
+    ) -> RefreshHandle<Driver> {
+        self.spawn_refresher_thread(
+            input,
+            interval,
+            valid,
+            None::<fn(Instant)>,
+            None::<fn(LeaseLoss)>,
+        )
+    }
+
+    /// Like `spawn_refresher`, but keep trying to re-acquire the lock once it was lost
+    /// instead of stopping, and call `on_reacquired` with the `Instant` the lease of every
+    /// re-acquisition starts at.
+    ///
+    /// The validity flag is cleared while the lock is lost and set again once it was
+    /// re-acquired. Each re-acquisition attempt waits out a whole lease first, so that the
+    /// processor which took the lock over keeps it for as long as it renews its lease,
+    /// plus an exponential backoff with full jitter capped at the lease duration, which
+    /// spreads the attempts of processors contending for the lock.
+    pub fn spawn_self_healing_refresher<F>(
+        self,
+        input: <Self as Locking>::AcquireLockInputType,
+        interval: Duration,
+        on_reacquired: F,
+    ) -> RefreshHandle<Driver>
+    where
+        F: FnMut(Instant) + Send + 'static,
+    {
+        let valid = Arc::new(AtomicBool::new(false));
+        self.spawn_refresher_thread(
+            input,
+            interval,
+            valid,
+            Some(on_reacquired),
+            None::<fn(LeaseLoss)>,
+        )
+    }
+
+    /// Start the refresher thread, re-acquiring lost locks if `on_reacquired` is given,
+    /// and calling `on_lost` whenever a valid lease is lost.
+    pub(crate) fn spawn_refresher_thread<F, G>(
+        self,
+        input: <Self as Locking>::AcquireLockInputType,
+        interval: Duration,
+        valid: Arc<AtomicBool>,
+        mut on_reacquired: Option<F>,
+        mut on_lost: Option<G>,
+    ) -> RefreshHandle<Driver>
+    where
+        F: FnMut(Instant) + Send + 'static,
+        G: FnMut(LeaseLoss) + Send + 'static,
+    {
+        let interval = cmp::min(interval, self.duration / 2);
+        let backoff = RetryPolicy {
+            max_attempts: u32::max_value(),
+            base_delay: interval,
+            max_delay: self.duration,
+            jitter: Jitter::Full,
+        };
+        let last_result = Arc::new(Mutex::new(None));
+        let token = Arc::new(Mutex::new(None));
+        let (commands, received) = mpsc::channel();
+
+        let thread_valid = valid.clone();
+        let thread_last_result = last_result.clone();
+        let thread_token = token.clone();
+        let mut lock = self;
+
+        let thread = thread::spawn(move || {
+            let mut acquired = None;
+            let mut renewed = false;
+            let mut paused = false;
+            let mut was_valid = thread_valid.load(Ordering::SeqCst);
+            // The number of attempts to re-acquire the lock since it was lost, if it was
+            let mut lost: Option<u32> = None;
+
+            loop {
+                let mut reacquired = None;
+
+                if !paused {
+                    let result = lock.acquire_lock(&input);
+                    if let Ok(instant) = result {
+                        acquired = Some(instant);
+                        *thread_token.lock().unwrap() = lock.observed_token();
+                    }
+                    *thread_last_result.lock().unwrap() = Some(
+                        result
+                            .as_ref()
+                            .map(|instant| *instant)
+                            .map_err(|err| err.kind()),
+                    );
+
+                    match result {
+                        Err(ref err) if err.kind() == DynaErrorKind::LockAlreadyAcquired => {
+                            if was_valid {
+                                if let Some(ref mut on_lost) = on_lost {
+                                    on_lost(LeaseLoss::TakenOver);
+                                }
+                            }
+                            if on_reacquired.is_none() {
+                                warn!("{} lock lost, stopping the refresher", lock.provider_name());
+                                break;
+                            }
+                            if lost.is_none() {
+                                warn!(
+                                    "{} lock lost, trying to re-acquire it",
+                                    lock.provider_name()
+                                );
+                            }
+                            acquired = None;
+                            lost = Some(lost.unwrap_or(0) + 1);
+                        }
+                        Err(ref err) => {
+                            warn!("{} lock renewal failed: {}", lock.provider_name(), err);
+                            lost = lost.map(|attempts| attempts + 1);
+                        }
+                        Ok(instant) => {
+                            if lost.take().is_some() {
+                                info!("{} lock re-acquired", lock.provider_name());
+                                reacquired = Some(instant);
+                            }
+                        }
+                    }
+                    renewed = result.is_ok();
+                }
+
+                let remaining = acquired.and_then(|instant| lock.remaining(instant));
+                let valid = if lock.has_lease() {
+                    remaining.is_some()
+                } else {
+                    renewed && !paused
+                };
+                thread_valid.store(valid, Ordering::SeqCst);
+
+                // A lock taken over was reported above, before `acquired` was cleared
+                if was_valid && !valid && lost.is_none() && lock.has_lease() {
+                    if let Some(ref mut on_lost) = on_lost {
+                        on_lost(LeaseLoss::Expired);
+                    }
+                }
+                was_valid = valid;
+
+                // Only report a re-acquisition once the lease is reported valid again
+                if let Some(instant) = reacquired {
+                    if let Some(ref mut on_reacquired) = on_reacquired {
+                        on_reacquired(instant);
+                    }
+                }
+
+                let wait = match (lost, remaining) {
+                    (Some(attempts), _) => lock.duration + backoff.delay(attempts),
+                    (None, Some(remaining)) => cmp::min(interval, remaining),
+                    (None, None) => interval,
+                };
+                match received.recv_timeout(wait) {
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Ok(Command::Pause) => paused = true,
+                    Ok(Command::Resume) => paused = false,
+                    Ok(Command::Stop) | Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+
+            thread_valid.store(false, Ordering::SeqCst);
+            lock
+        });
+
+        RefreshHandle {
+            valid: valid,
+            last_result: last_result,
+            token: token,
+            commands: commands,
+            thread: thread,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use DynaError;
+
+    /// A driver whose lock is taken over by another processor after `renewals`
+    /// acquisitions, and given back right away if `recovers` is set.
+    #[derive(Debug, Default)]
+    struct TakenOverDriver {
+        acquired: u32,
+        renewals: Option<u32>,
+        recovers: bool,
+        leaseless: bool,
+    }
+
+    impl Locking for DistLock<TakenOverDriver> {
+        type AcquireLockInputType = ();
+        type RefreshLockInputType = ();
+        type ReleaseLockInputType = ();
+
+        fn acquire_lock(&mut self, _input: &()) -> Result<Instant, DynaError> {
+            if Some(self.driver.acquired) == self.driver.renewals {
+                if self.driver.recovers {
+                    self.driver.renewals = None;
+                }
+                return Err(DynaError::new(DynaErrorKind::LockAlreadyAcquired, None));
+            }
+
+            self.driver.acquired += 1;
+            Ok(Instant::now())
+        }
+
+        fn refresh_lock(&mut self, _input: &()) -> Result<(), DynaError> {
+            Ok(())
+        }
+
+        fn remaining(&self, instant: Instant) -> Option<Duration> {
+            if self.driver.leaseless {
+                return None;
+            }
+
+            self.duration.checked_sub(instant.elapsed())
+        }
+
+        fn has_lease(&self) -> bool {
+            !self.driver.leaseless
+        }
+    }
+
+    #[test]
+    fn test_spawn_refresher_renews_until_stopped_success() {
+        let lock = DistLock::new(TakenOverDriver::default(), Duration::from_secs(10));
+
+        let handle = lock.spawn_refresher((), Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(100));
+        assert!(handle.is_valid());
+        assert!(handle.last_result().unwrap().is_ok());
+
+        let flag = handle.valid_flag();
+        let lock = handle.stop();
+        assert!(lock.driver.acquired > 1);
+        assert!(!flag.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn test_spawn_refresher_with_flag_lost_lock_fail() {
+        let driver = TakenOverDriver {
+            renewals: Some(2),
+            ..Default::default()
+        };
+        let lock = DistLock::new(driver, Duration::from_secs(10));
+
+        // The worker's flag is cleared as soon as the lock is taken over
+        let valid = Arc::new(AtomicBool::new(true));
+        let handle = lock.spawn_refresher_with_flag((), Duration::from_millis(10), valid.clone());
+        thread::sleep(Duration::from_millis(100));
+        assert!(!valid.load(Ordering::SeqCst));
+        assert!(Arc::ptr_eq(&handle.valid_flag(), &valid));
+
+        let lock = handle.stop();
+        assert_eq!(lock.driver.acquired, 2);
+    }
+
+    #[test]
+    fn test_spawn_self_healing_refresher_reacquires_success() {
+        let driver = TakenOverDriver {
+            renewals: Some(2),
+            recovers: true,
+            ..Default::default()
+        };
+        let lock = DistLock::new(driver, Duration::from_millis(40));
+
+        // The lock is re-acquired once the lease of the other processor was waited out
+        let (reacquired, received) = mpsc::channel();
+        let handle =
+            lock.spawn_self_healing_refresher((), Duration::from_millis(10), move |instant| {
+                reacquired.send(instant).unwrap();
+            });
+        assert!(received.recv_timeout(Duration::from_secs(1)).is_ok());
+        assert!(handle.is_valid());
+        assert!(handle.last_result().unwrap().is_ok());
+
+        let lock = handle.stop();
+        assert!(lock.driver.acquired > 2);
+    }
+
+    #[test]
+    fn test_spawn_refresher_without_lease_success() {
+        let driver = TakenOverDriver {
+            leaseless: true,
+            ..Default::default()
+        };
+        let lock = DistLock::new(driver, Duration::from_secs(10));
+
+        // Successful renewals keep the lock valid despite the lack of a lease
+        let handle = lock.spawn_refresher((), Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(100));
+        assert!(handle.is_valid());
+
+        let lock = handle.stop();
+        assert!(lock.driver.acquired > 1);
+    }
+
+    #[test]
+    fn test_spawn_refresher_pause_and_resume_success() {
+        let lock = DistLock::new(TakenOverDriver::default(), Duration::from_secs(10));
+
+        let handle = lock.spawn_refresher((), Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(50));
+        handle.pause();
+        thread::sleep(Duration::from_millis(20));
+
+        // No renewal happens while paused, but the lease is still valid
+        let last_result = handle.last_result();
+        thread::sleep(Duration::from_millis(50));
+        assert_eq!(handle.last_result(), last_result);
+        assert!(handle.is_valid());
+
+        handle.resume();
+        thread::sleep(Duration::from_millis(50));
+        assert!(handle.last_result() != last_result);
+        assert!(handle.is_valid());
+
+        handle.stop();
+    }
+
+    #[test]
+    fn test_spawn_refresher_paused_past_lease_fail() {
+        let lock = DistLock::new(TakenOverDriver::default(), Duration::from_millis(40));
+
+        let handle = lock.spawn_refresher((), Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(30));
+        handle.pause();
+
+        // The lease ends while paused, until the renewal following the resume
+        thread::sleep(Duration::from_millis(100));
+        assert!(!handle.is_valid());
+
+        handle.resume();
+        thread::sleep(Duration::from_millis(30));
+        assert!(handle.is_valid());
+
+        handle.stop();
+    }
+
+    #[test]
+    fn test_spawn_refresher_clamps_interval_success() {
+        let lock = DistLock::new(TakenOverDriver::default(), Duration::from_millis(40));
+
+        // Renewals happen every 20 milliseconds instead of every minute
+        let handle = lock.spawn_refresher((), Duration::from_secs(60));
+        thread::sleep(Duration::from_millis(100));
+
+        let lock = handle.stop();
+        assert!(lock.driver.acquired > 1);
+    }
+
+    #[test]
+    fn test_spawn_refresher_lost_lock_fail() {
+        let driver = TakenOverDriver {
+            renewals: Some(2),
+            ..Default::default()
+        };
+        let lock = DistLock::new(driver, Duration::from_secs(10));
+
+        let handle = lock.spawn_refresher((), Duration::from_millis(10));
+        thread::sleep(Duration::from_millis(100));
+        assert!(!handle.is_valid());
+        assert_eq!(
+            handle.last_result(),
+            Some(Err(DynaErrorKind::LockAlreadyAcquired))
+        );
+
+        let lock = handle.stop();
+        assert_eq!(lock.driver.acquired, 2);
+    }
+}
@@ -0,0 +1,947 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Retrying lock acquisition until a stop condition triggers.
+//!
+//! Callers either want to "try 5 times" or to "try for 30 seconds", the
+//! `StopCondition` structure expresses both and stops at whichever comes first.
+//! A shutting down caller cancels a retrying acquisition through the `cancelled`
+//! flag of the `StopCondition`. Arbitrary stop logic is expressed with a predicate
+//! through `acquire_while`.
+//!
+//! A `RetryBudget` caps the retries of every lock it is handed to as a whole, so a
+//! storm of contended or failing acquisitions can't issue unbounded retries.
+//!
+//! Transient provider failures, as opposed to contention, are retried with an
+//! exponential backoff described by a `RetryPolicy` through `acquire_lock_with_retry`.
+//!
+//! Waiters polling a contended lock back off when `Locking::observed_contenders`
+//! reports other waiters: each poll interval is lengthened by a random share of one
+//! interval per other waiter, which spreads the polls of all waiters over as many
+//! intervals instead of having them stampede the lock together. This is advisory, and
+//! only as accurate as the provider's count (e.g., the DynamoDB lock intents).
+
+use std::cmp;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use ratelimit::TokenBucket;
+use sleep::Sleeper;
+use {as_millis, DistLock, DynaError, DynaErrorKind, Locking};
+
+/// When to give up retrying an operation.
+#[derive(Debug, Clone, Default)]
+pub struct StopCondition {
+    /// The maximum number of attempts (default: None, i.e., unlimited).
+    pub max_attempts: Option<u32>,
+    /// The point in time after which no attempt is started (default: None, i.e., never).
+    pub deadline: Option<Instant>,
+    /// A flag that cancels the operation once set (default: None). It is checked before
+    /// every attempt and after every sleep, an attempt in flight is not interrupted.
+    pub cancelled: Option<Arc<AtomicBool>>,
+}
+
+impl StopCondition {
+    /// Return true if no attempt should follow the `attempts` already made at `now`.
+    pub fn should_stop(&self, attempts: u32, now: Instant) -> bool {
+        self.max_attempts.map_or(false, |max| attempts >= max)
+            || self.deadline.map_or(false, |deadline| now >= deadline)
+    }
+
+    /// Return true if the `cancelled` flag is set.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+            .as_ref()
+            .map_or(false, |cancelled| cancelled.load(Ordering::SeqCst))
+    }
+}
+
+/// The random variation applied to the backoff delays of a `RetryPolicy`.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Jitter {
+    /// Sleep for the exact backoff delay.
+    None,
+    /// Sleep for a random duration between zero and the backoff delay, which spreads
+    /// the retries of processors that failed at the same time.
+    Full,
+}
+
+/// How to retry an operation failing with transient errors.
+///
+/// The n-th retry is delayed by `base_delay * 2^(n - 1)`, capped at `max_delay`, and
+/// then jittered.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the first one (default: 3).
+    pub max_attempts: u32,
+    /// The delay before the first retry (default: 100 milliseconds).
+    pub base_delay: Duration,
+    /// The maximum delay between two attempts (default: 1 second).
+    pub max_delay: Duration,
+    /// The random variation of the delays (default: `Jitter::Full`).
+    pub jitter: Jitter,
+}
+
+impl RetryPolicy {
+    /// Return the delay before the retry following the `attempts` attempts made so far.
+    pub fn delay(&self, attempts: u32) -> Duration {
+        let factor = 1u32
+            .checked_shl(attempts.saturating_sub(1))
+            .unwrap_or(u32::max_value());
+        let backoff = self
+            .base_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| cmp::min(delay, self.max_delay));
+
+        match self.jitter {
+            Jitter::None => backoff,
+            Jitter::Full => {
+                let nanos = backoff.as_secs() * 1_000_000_000 + u64::from(backoff.subsec_nanos());
+                let jittered = random_u64() % (nanos + 1);
+
+                Duration::new(jittered / 1_000_000_000, (jittered % 1_000_000_000) as u32)
+            }
+        }
+    }
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_secs(1),
+            jitter: Jitter::Full,
+        }
+    }
+}
+
+/// Return a random number, good enough to jitter delays.
+///
+/// Every `RandomState` is seeded differently, which saves a dependency on a random
+/// number generator.
+fn random_u64() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    hasher.write_u64(0);
+    hasher.finish()
+}
+
+/// Return the poll interval of a waiter that observed `contenders` other waiters:
+/// `interval` plus a random duration between zero and `interval * contenders`.
+fn spread_interval(interval: Duration, contenders: u32) -> Duration {
+    if contenders == 0 {
+        return interval;
+    }
+
+    let spread = interval.checked_mul(contenders).unwrap_or(interval);
+    let jitter = RetryPolicy {
+        max_attempts: 1,
+        base_delay: spread,
+        max_delay: spread,
+        jitter: Jitter::Full,
+    };
+
+    interval.checked_add(jitter.delay(1)).unwrap_or(interval)
+}
+
+/// Return true for the errors worth retrying after a while: provider failures (e.g.,
+/// timeouts), throttling by the provider and client-side rate limiting.
+fn is_transient(kind: DynaErrorKind) -> bool {
+    match kind {
+        DynaErrorKind::ProviderError | DynaErrorKind::Throttled | DynaErrorKind::RateLimited => {
+            true
+        }
+        _ => false,
+    }
+}
+
+/// The fence token a waiting processor observes through `refresh_lock`, and since when.
+///
+/// Following the Dynalock algorithm, a processor may only condition an acquisition on a
+/// fence token once it observed it unchanged for a whole lease duration, as the holder
+/// of the token could otherwise still be within its lease.
+#[derive(Debug)]
+struct HolderWatch {
+    token: Option<String>,
+    since: Instant,
+}
+
+impl HolderWatch {
+    /// Start watching at `now`, without any token observed yet.
+    fn new(now: Instant) -> Self {
+        HolderWatch {
+            token: None,
+            since: now,
+        }
+    }
+
+    /// Record the `token` observed at `now`, the lease is waited out from scratch when
+    /// it changed.
+    fn observe(&mut self, token: Option<String>, now: Instant) {
+        if token != self.token {
+            self.token = token;
+            self.since = now;
+        }
+    }
+
+    /// Wait out the lease of the observed token from scratch, e.g., after a contended
+    /// attempt.
+    fn restart(&mut self, now: Instant) {
+        self.since = now;
+    }
+
+    /// Return true if an acquisition may be attempted at `now`: no token was observed,
+    /// or the observed one stayed unchanged for at least `duration`.
+    fn is_due(&self, duration: Duration, now: Instant) -> bool {
+        self.token.is_none() || now.duration_since(self.since) >= duration
+    }
+}
+
+/// A token bucket of retries shared by the locks it is handed to.
+///
+/// Clones share the same bucket, a clone handed to every lock of a process caps the
+/// retries of all of them together.
+#[derive(Debug, Clone)]
+pub struct RetryBudget {
+    bucket: Arc<Mutex<TokenBucket>>,
+}
+
+impl RetryBudget {
+    /// Create a new full budget allowing `retries_per_sec` retries per second, starting
+    /// to count from `now`.
+    pub fn new(retries_per_sec: u32, now: Instant) -> Self {
+        RetryBudget {
+            bucket: Arc::new(Mutex::new(TokenBucket::new(retries_per_sec, now))),
+        }
+    }
+
+    /// Try to spend a single retry at the point in time `now`, return false if the
+    /// budget is exhausted.
+    pub fn try_spend(&self, now: Instant) -> bool {
+        self.bucket.lock().unwrap().try_take(now).is_ok()
+    }
+}
+
+impl<Driver> DistLock<Driver> {
+    /// Make the retrying helpers (`acquire_until`, `acquire_while` and
+    /// `acquire_lock_with_retry`) spend a retry from `budget` before every retry, failing
+    /// with `DynaErrorKind::RateLimited` once the budget is exhausted.
+    pub fn with_retry_budget(mut self, budget: RetryBudget) -> Self {
+        self.retry_budget = Some(budget);
+        self
+    }
+
+    /// Spend a retry from the retry budget, if any, after `attempts` attempts.
+    fn spend_retry(&self, attempts: u32) -> Result<(), DynaError> {
+        match self.retry_budget {
+            Some(ref budget) if !budget.try_spend(Instant::now()) => {
+                warn!("retry budget exhausted after {} attempt(s)", attempts);
+                Err(DynaError::new(
+                    DynaErrorKind::RateLimited,
+                    Some(&format!(
+                        "retry budget exhausted after {} attempt(s)",
+                        attempts
+                    )),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl<Driver> DistLock<Driver>
+where
+    DistLock<Driver>: Locking,
+{
+    /// Try to acquire the lock until it succeeds or `stop` triggers.
+    ///
+    /// Contended attempts (`DynaErrorKind::LockAlreadyAcquired`) are followed by polls
+    /// sleeping for `interval` with `sleeper` and calling `refresh_lock` with
+    /// `refresh_input`, and the acquisition is retried once the lease of the holder was
+    /// waited out as described by `acquire_blocking`. Other errors, including those of
+    /// `refresh_lock`, are returned right away. The last sleep is shortened so that it
+    /// ends at the deadline. Once `stop` triggers, `DynaErrorKind::AcquireTimeout` is
+    /// returned with the number of attempts made, polls not counting as attempts. Once
+    /// the `cancelled` flag is set, `DynaErrorKind::Cancelled` is returned before the next
+    /// attempt or poll. A default `StopCondition` retries forever.
+    pub fn acquire_until<S>(
+        &mut self,
+        input: &<Self as Locking>::AcquireLockInputType,
+        refresh_input: &<Self as Locking>::RefreshLockInputType,
+        stop: &StopCondition,
+        interval: Duration,
+        sleeper: &S,
+    ) -> Result<Instant, DynaError>
+    where
+        S: Sleeper,
+    {
+        let mut attempts = 0;
+        let mut watch = HolderWatch::new(Instant::now());
+
+        loop {
+            if stop.is_cancelled() {
+                debug!("lock acquisition cancelled after {} attempt(s)", attempts);
+                return Err(DynaError::new(DynaErrorKind::Cancelled, None));
+            }
+
+            if watch.is_due(self.duration, Instant::now()) {
+                match self.acquire_lock(input) {
+                    Err(ref err) if err.kind() == DynaErrorKind::LockAlreadyAcquired => {}
+                    result => return result,
+                }
+                attempts += 1;
+                watch.restart(Instant::now());
+            }
+
+            let now = Instant::now();
+            if stop.should_stop(attempts, now) {
+                debug!("giving up lock acquisition after {} attempt(s)", attempts);
+                return Err(DynaError::new(
+                    DynaErrorKind::AcquireTimeout,
+                    Some(&format!("gave up after {} attempt(s)", attempts)),
+                ));
+            }
+
+            self.spend_retry(attempts)?;
+
+            let interval = spread_interval(interval, self.observed_contenders());
+            let wait = match stop.deadline {
+                Some(deadline) => cmp::min(interval, deadline.duration_since(now)),
+                None => interval,
+            };
+            self.watch_holder(refresh_input, &mut watch, wait, sleeper)?;
+        }
+    }
+
+    /// Try to acquire the lock for as long as `should_keep_trying` allows it.
+    ///
+    /// After every failed attempt the predicate is called with the number of attempts
+    /// made so far and the error of the last one. When it returns `true` the lock is
+    /// polled, sleeping for `interval` with `sleeper` and calling `refresh_lock` with
+    /// `refresh_input`, and the acquisition is retried once the lease of the holder was
+    /// waited out as described by `acquire_blocking`. Otherwise the last error is
+    /// returned. Unlike `acquire_until` every error, including those of `refresh_lock`,
+    /// is handed over to the predicate, so it decides which errors are worth retrying.
+    pub fn acquire_while<S, F>(
+        &mut self,
+        input: &<Self as Locking>::AcquireLockInputType,
+        refresh_input: &<Self as Locking>::RefreshLockInputType,
+        interval: Duration,
+        sleeper: &S,
+        mut should_keep_trying: F,
+    ) -> Result<Instant, DynaError>
+    where
+        S: Sleeper,
+        F: FnMut(u32, &DynaError) -> bool,
+    {
+        let mut attempts = 0;
+        let mut watch = HolderWatch::new(Instant::now());
+
+        loop {
+            if watch.is_due(self.duration, Instant::now()) {
+                let err = match self.acquire_lock(input) {
+                    Ok(instant) => return Ok(instant),
+                    Err(err) => err,
+                };
+                attempts += 1;
+                watch.restart(Instant::now());
+
+                if !should_keep_trying(attempts, &err) {
+                    debug!("giving up lock acquisition after {} attempt(s)", attempts);
+                    return Err(err);
+                }
+            }
+
+            self.spend_retry(attempts)?;
+
+            let wait = spread_interval(interval, self.observed_contenders());
+            if let Err(err) = self.watch_holder(refresh_input, &mut watch, wait, sleeper) {
+                if !should_keep_trying(attempts, &err) {
+                    debug!("giving up lock acquisition after {} attempt(s)", attempts);
+                    return Err(err);
+                }
+            }
+        }
+    }
+
+    /// Block until the lock is acquired or `deadline` passes, polling every
+    /// `poll_interval`.
+    ///
+    /// This waits for the holder to release the lock or for its lease to end, as
+    /// described by the Dynalock algorithm. After a contended attempt
+    /// (`DynaErrorKind::LockAlreadyAcquired`) the lock is polled: the driver sleeps with
+    /// `sleeper` and calls `refresh_lock` with `refresh_input`, and the point in time a
+    /// fence token is first observed through `observed_token` is recorded. The next
+    /// attempt is conditioned on the stored fence token, so it is only made once that
+    /// token stayed unchanged for a whole lease duration, and a holder renewing its lease
+    /// keeps the lock. A lock without a fence token is attempted at the next poll.
+    ///
+    /// Sleeps are shortened so that they never go past the deadline.
+    /// `DynaErrorKind::AcquireTimeout` is returned once the deadline passed, while other
+    /// errors, including those of `refresh_lock`, are returned right away.
+    pub fn acquire_blocking<S>(
+        &mut self,
+        input: &<Self as Locking>::AcquireLockInputType,
+        refresh_input: &<Self as Locking>::RefreshLockInputType,
+        deadline: Instant,
+        poll_interval: Duration,
+        sleeper: &S,
+    ) -> Result<Instant, DynaError>
+    where
+        S: Sleeper,
+    {
+        let mut attempts = 0;
+        let mut watch = HolderWatch::new(Instant::now());
+
+        loop {
+            if watch.is_due(self.duration, Instant::now()) {
+                match self.acquire_lock(input) {
+                    Err(ref err) if err.kind() == DynaErrorKind::LockAlreadyAcquired => {}
+                    result => return result,
+                }
+                attempts += 1;
+                watch.restart(Instant::now());
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                debug!(
+                    "lock still held at the deadline after {} attempt(s)",
+                    attempts
+                );
+                return Err(DynaError::new(
+                    DynaErrorKind::AcquireTimeout,
+                    Some(&format!("lock still held after {} attempt(s)", attempts)),
+                ));
+            }
+
+            self.spend_retry(attempts)?;
+            let poll_interval = spread_interval(poll_interval, self.observed_contenders());
+            let wait = cmp::min(poll_interval, deadline.duration_since(now));
+            self.watch_holder(refresh_input, &mut watch, wait, sleeper)?;
+        }
+    }
+
+    /// Sleep for `wait` with `sleeper`, then call `refresh_lock` with `refresh_input` and
+    /// record the fence token it observed in `watch`.
+    fn watch_holder<S>(
+        &mut self,
+        refresh_input: &<Self as Locking>::RefreshLockInputType,
+        watch: &mut HolderWatch,
+        wait: Duration,
+        sleeper: &S,
+    ) -> Result<(), DynaError>
+    where
+        S: Sleeper,
+    {
+        sleeper.sleep(wait);
+        self.refresh_lock(refresh_input)?;
+        watch.observe(self.observed_token(), Instant::now());
+
+        Ok(())
+    }
+
+    /// Try to acquire the lock, retrying transient failures as described by `policy`.
+    ///
+    /// Provider errors (e.g., timeouts), throttling (`DynaErrorKind::Throttled`) and
+    /// client-side rate limiting (`DynaErrorKind::RateLimited`) are retried after sleeping
+    /// with `sleeper` for the backoff delay of the policy. Any other error, including
+    /// `DynaErrorKind::LockAlreadyAcquired`, is returned right away, as is the last error
+    /// once `max_attempts` attempts were made. Use `acquire_until` to wait out contention.
+    pub fn acquire_lock_with_retry<S>(
+        &mut self,
+        input: &<Self as Locking>::AcquireLockInputType,
+        policy: &RetryPolicy,
+        sleeper: &S,
+    ) -> Result<Instant, DynaError>
+    where
+        S: Sleeper,
+    {
+        let mut attempts = 0;
+
+        loop {
+            let err = match self.acquire_lock(input) {
+                Ok(instant) => return Ok(instant),
+                Err(err) => err,
+            };
+            attempts += 1;
+
+            if !is_transient(err.kind()) || attempts >= policy.max_attempts {
+                debug!("giving up lock acquisition after {} attempt(s)", attempts);
+                return Err(err);
+            }
+
+            self.spend_retry(attempts)?;
+
+            let delay = policy.delay(attempts);
+            debug!(
+                "lock acquisition failed ({}), retrying in {}ms",
+                err,
+                as_millis(delay)
+            );
+            sleeper.sleep(delay);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use providers::mock::{MockDriver, MockStore};
+    use sleep::{MockSleeper, ThreadSleeper};
+
+    /// A driver that fails with `kind` (contention by default) for the first `contended`
+    /// attempts, and whose polls observe `contenders` other waiters.
+    #[derive(Debug)]
+    struct ContendedDriver {
+        contended: u32,
+        attempts: u32,
+        kind: DynaErrorKind,
+        refreshes: u32,
+        contenders: u32,
+    }
+
+    impl Locking for DistLock<ContendedDriver> {
+        type AcquireLockInputType = ();
+        type RefreshLockInputType = ();
+        type ReleaseLockInputType = ();
+
+        fn acquire_lock(&mut self, _input: &()) -> Result<Instant, DynaError> {
+            self.driver.attempts += 1;
+
+            if self.driver.attempts > self.driver.contended {
+                Ok(Instant::now())
+            } else {
+                Err(DynaError::new(self.driver.kind, None))
+            }
+        }
+
+        fn refresh_lock(&mut self, _input: &()) -> Result<(), DynaError> {
+            self.driver.refreshes += 1;
+            Ok(())
+        }
+
+        fn remaining(&self, instant: Instant) -> Option<Duration> {
+            self.duration.checked_sub(instant.elapsed())
+        }
+
+        fn observed_contenders(&self) -> u32 {
+            self.driver.contenders
+        }
+    }
+
+    fn contended_lock(contended: u32) -> DistLock<ContendedDriver> {
+        let driver = ContendedDriver {
+            contended: contended,
+            attempts: 0,
+            kind: DynaErrorKind::LockAlreadyAcquired,
+            refreshes: 0,
+            contenders: 0,
+        };
+
+        DistLock::new(driver, Duration::from_secs(10))
+    }
+
+    #[test]
+    fn test_stop_condition_should_stop_success() {
+        let now = Instant::now();
+        let stop = StopCondition {
+            max_attempts: Some(3),
+            deadline: Some(now + Duration::from_secs(30)),
+            cancelled: None,
+        };
+
+        assert!(!stop.should_stop(2, now));
+        assert!(stop.should_stop(3, now));
+        assert!(stop.should_stop(1, now + Duration::from_secs(30)));
+        assert!(!StopCondition::default().should_stop(1000, now));
+    }
+
+    #[test]
+    fn test_acquire_until_succeeds_after_retries_success() {
+        let mut lock = contended_lock(2);
+        let sleeper = MockSleeper::default();
+        let stop = StopCondition {
+            max_attempts: Some(5),
+            deadline: None,
+            cancelled: None,
+        };
+
+        let result = lock.acquire_until(&(), &(), &stop, Duration::from_millis(100), &sleeper);
+        assert!(result.is_ok());
+        assert_eq!(lock.driver().attempts, 3);
+        assert_eq!(sleeper.sleeps(), vec![Duration::from_millis(100); 2]);
+    }
+
+    #[test]
+    fn test_acquire_until_max_attempts_fail() {
+        let mut lock = contended_lock(10);
+        let sleeper = MockSleeper::default();
+        let stop = StopCondition {
+            max_attempts: Some(3),
+            deadline: None,
+            cancelled: None,
+        };
+
+        let err = lock
+            .acquire_until(&(), &(), &stop, Duration::from_millis(100), &sleeper)
+            .unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::AcquireTimeout);
+        assert_eq!(
+            err.to_string(),
+            "lock acquisition timed out: gave up after 3 attempt(s)"
+        );
+        assert_eq!(sleeper.sleeps().len(), 2);
+    }
+
+    #[test]
+    fn test_acquire_while_succeeds_after_retries_success() {
+        let mut lock = contended_lock(3);
+        let sleeper = MockSleeper::default();
+        let mut seen = Vec::new();
+
+        let result = lock.acquire_while(
+            &(),
+            &(),
+            Duration::from_millis(50),
+            &sleeper,
+            |attempts, err| {
+                seen.push((attempts, err.kind()));
+                true
+            },
+        );
+        assert!(result.is_ok());
+        assert_eq!(
+            seen,
+            vec![
+                (1, DynaErrorKind::LockAlreadyAcquired),
+                (2, DynaErrorKind::LockAlreadyAcquired),
+                (3, DynaErrorKind::LockAlreadyAcquired),
+            ]
+        );
+        assert_eq!(sleeper.sleeps().len(), 3);
+    }
+
+    #[test]
+    fn test_acquire_while_predicate_stops_fail() {
+        let mut lock = contended_lock(10);
+        let sleeper = MockSleeper::default();
+
+        let result = lock.acquire_while(
+            &(),
+            &(),
+            Duration::from_millis(50),
+            &sleeper,
+            |attempts, _| attempts < 2,
+        );
+        assert_eq!(
+            result.err().unwrap().kind(),
+            DynaErrorKind::LockAlreadyAcquired
+        );
+        assert_eq!(lock.driver().attempts, 2);
+        assert_eq!(sleeper.sleeps().len(), 1);
+    }
+
+    /// A sleeper that sets a cancellation flag, as a shutdown would while sleeping.
+    struct CancellingSleeper(Arc<AtomicBool>);
+
+    impl Sleeper for CancellingSleeper {
+        fn sleep(&self, _duration: Duration) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[test]
+    fn test_acquire_until_cancelled_fail() {
+        let mut lock = contended_lock(10);
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let sleeper = CancellingSleeper(cancelled.clone());
+        let stop = StopCondition {
+            cancelled: Some(cancelled),
+            ..Default::default()
+        };
+
+        let err = lock
+            .acquire_until(&(), &(), &stop, Duration::from_millis(100), &sleeper)
+            .unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::Cancelled);
+        assert_eq!(lock.driver().attempts, 1);
+    }
+
+    #[test]
+    fn test_retry_budget_shared_by_locks_fail() {
+        let budget = RetryBudget::new(2, Instant::now());
+        let sleeper = MockSleeper::default();
+        let stop = StopCondition::default();
+
+        // The first lock spends the whole budget
+        let mut lock = contended_lock(2).with_retry_budget(budget.clone());
+        assert!(lock
+            .acquire_until(&(), &(), &stop, Duration::from_millis(100), &sleeper)
+            .is_ok());
+
+        let mut lock = contended_lock(10).with_retry_budget(budget);
+        let err = lock
+            .acquire_while(&(), &(), Duration::from_millis(100), &sleeper, |_, _| true)
+            .unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::RateLimited);
+        assert_eq!(lock.driver().attempts, 1);
+        assert_eq!(sleeper.sleeps().len(), 2);
+    }
+
+    #[test]
+    fn test_acquire_until_deadline_fail() {
+        let mut lock = contended_lock(10);
+        let sleeper = MockSleeper::default();
+        let stop = StopCondition {
+            max_attempts: None,
+            deadline: Some(Instant::now()),
+            cancelled: None,
+        };
+
+        let err = lock
+            .acquire_until(&(), &(), &stop, Duration::from_millis(100), &sleeper)
+            .unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::AcquireTimeout);
+        assert!(sleeper.sleeps().is_empty());
+    }
+
+    fn failing_lock(failures: u32, kind: DynaErrorKind) -> DistLock<ContendedDriver> {
+        let mut lock = contended_lock(failures);
+        lock.driver.kind = kind;
+        lock
+    }
+
+    #[test]
+    fn test_retry_policy_delay_success() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            base_delay: Duration::from_millis(100),
+            max_delay: Duration::from_millis(500),
+            jitter: Jitter::None,
+        };
+
+        assert_eq!(policy.delay(1), Duration::from_millis(100));
+        assert_eq!(policy.delay(2), Duration::from_millis(200));
+        assert_eq!(policy.delay(3), Duration::from_millis(400));
+        assert_eq!(policy.delay(4), Duration::from_millis(500));
+        assert_eq!(policy.delay(100), Duration::from_millis(500));
+
+        // Full jitter never exceeds the backoff delay
+        let policy = RetryPolicy::default();
+        for attempts in 1..10 {
+            assert!(policy.delay(attempts) <= Duration::from_secs(1));
+        }
+    }
+
+    #[test]
+    fn test_acquire_lock_with_retry_succeeds_after_retries_success() {
+        let mut lock = failing_lock(2, DynaErrorKind::Throttled);
+        let sleeper = MockSleeper::default();
+        let policy = RetryPolicy {
+            jitter: Jitter::None,
+            ..Default::default()
+        };
+
+        let result = lock.acquire_lock_with_retry(&(), &policy, &sleeper);
+        assert!(result.is_ok());
+        assert_eq!(lock.driver().attempts, 3);
+        assert_eq!(
+            sleeper.sleeps(),
+            vec![Duration::from_millis(100), Duration::from_millis(200)]
+        );
+    }
+
+    #[test]
+    fn test_acquire_lock_with_retry_max_attempts_fail() {
+        let mut lock = failing_lock(10, DynaErrorKind::ProviderError);
+        let sleeper = MockSleeper::default();
+
+        let err = lock
+            .acquire_lock_with_retry(&(), &RetryPolicy::default(), &sleeper)
+            .unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::ProviderError);
+        assert_eq!(lock.driver().attempts, 3);
+        assert_eq!(sleeper.sleeps().len(), 2);
+    }
+
+    #[test]
+    fn test_acquire_lock_with_retry_contended_fail() {
+        let mut lock = contended_lock(10);
+        let sleeper = MockSleeper::default();
+
+        let err = lock
+            .acquire_lock_with_retry(&(), &RetryPolicy::default(), &sleeper)
+            .unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::LockAlreadyAcquired);
+        assert_eq!(lock.driver().attempts, 1);
+        assert!(sleeper.sleeps().is_empty());
+    }
+
+    #[test]
+    fn test_acquire_blocking_refreshes_between_attempts_success() {
+        let mut lock = contended_lock(2);
+        let sleeper = MockSleeper::default();
+        let deadline = Instant::now() + Duration::from_secs(60);
+
+        let result =
+            lock.acquire_blocking(&(), &(), deadline, Duration::from_millis(100), &sleeper);
+        assert!(result.is_ok());
+        assert_eq!(lock.driver().attempts, 3);
+        assert_eq!(lock.driver().refreshes, 2);
+        assert_eq!(sleeper.sleeps(), vec![Duration::from_millis(100); 2]);
+    }
+
+    #[test]
+    fn test_acquire_blocking_spreads_polls_over_contenders_success() {
+        let mut lock = contended_lock(20);
+        lock.driver().contenders = 3;
+        let sleeper = MockSleeper::default();
+        let deadline = Instant::now() + Duration::from_secs(60);
+
+        let result =
+            lock.acquire_blocking(&(), &(), deadline, Duration::from_millis(100), &sleeper);
+        assert!(result.is_ok());
+        assert_eq!(sleeper.sleeps().len(), 20);
+        for sleep in sleeper.sleeps() {
+            assert!(sleep >= Duration::from_millis(100));
+            assert!(sleep <= Duration::from_millis(400));
+        }
+        assert!(sleeper
+            .sleeps()
+            .iter()
+            .any(|&sleep| sleep > Duration::from_millis(100)));
+    }
+
+    #[test]
+    fn test_acquire_blocking_never_sleeps_past_deadline_fail() {
+        let mut lock = contended_lock(u32::max_value());
+        let sleeper = MockSleeper::default();
+        let deadline = Instant::now() + Duration::from_millis(50);
+
+        // The mock sleeper returns right away, so attempts repeat until the deadline
+        let err = lock
+            .acquire_blocking(&(), &(), deadline, Duration::from_secs(10), &sleeper)
+            .unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::AcquireTimeout);
+        assert!(Instant::now() >= deadline);
+
+        let sleeps = sleeper.sleeps();
+        assert!(!sleeps.is_empty());
+        assert!(sleeps
+            .iter()
+            .all(|sleep| *sleep <= Duration::from_millis(50)));
+    }
+
+    fn mock_lock(store: &MockStore, lease: Duration) -> DistLock<MockDriver> {
+        DistLock::new(MockDriver::with_store(store.clone(), "job"), lease)
+    }
+
+    #[test]
+    fn test_acquire_blocking_live_holder_fail() {
+        let store = MockStore::default();
+        let mut holder = mock_lock(&store, Duration::from_secs(60));
+        let mut waiter = mock_lock(&store, Duration::from_secs(60));
+
+        let acquired = holder.acquire_lock(&()).unwrap();
+        let deadline = Instant::now() + Duration::from_millis(100);
+
+        // The waiter observes the fence token but must not take the lock over
+        let err = waiter
+            .acquire_blocking(
+                &(),
+                &(),
+                deadline,
+                Duration::from_millis(10),
+                &ThreadSleeper,
+            )
+            .unwrap_err();
+        assert_eq!(err.kind(), DynaErrorKind::AcquireTimeout);
+        assert_eq!(waiter.driver().acquisitions(), 0);
+        assert!(holder.remaining(acquired).is_some());
+        assert_eq!(store.token("job"), holder.driver().current_token());
+    }
+
+    #[test]
+    fn test_acquire_blocking_takes_over_after_lease_success() {
+        let store = MockStore::default();
+        let lease = Duration::from_millis(100);
+        let mut holder = mock_lock(&store, lease);
+        let mut waiter = mock_lock(&store, lease);
+
+        // The holder never renews its lease
+        let acquired = holder.acquire_lock(&()).unwrap();
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        let taken = waiter
+            .acquire_blocking(
+                &(),
+                &(),
+                deadline,
+                Duration::from_millis(10),
+                &ThreadSleeper,
+            )
+            .unwrap();
+        assert!(taken.duration_since(acquired) >= lease);
+        assert!(holder.remaining(acquired).is_none());
+        assert_eq!(store.token("job"), waiter.driver().current_token());
+    }
+
+    #[test]
+    fn test_acquire_until_holder_lease_expires_mid_wait_success() {
+        let store = MockStore::default();
+        let lease = Duration::from_millis(100);
+        let mut holder = mock_lock(&store, lease);
+        let mut waiter = mock_lock(&store, lease);
+        let stop = StopCondition {
+            max_attempts: Some(3),
+            deadline: Some(Instant::now() + Duration::from_secs(5)),
+            cancelled: None,
+        };
+
+        // The holder crashes right after acquiring, the waiter polls until its lease ends
+        let acquired = holder.acquire_lock(&()).unwrap();
+        let taken = waiter
+            .acquire_until(&(), &(), &stop, Duration::from_millis(10), &ThreadSleeper)
+            .unwrap();
+        assert!(taken.duration_since(acquired) >= lease);
+        assert_eq!(waiter.driver().acquisitions(), 1);
+        assert_eq!(store.token("job"), waiter.driver().current_token());
+    }
+
+    #[test]
+    fn test_acquire_while_holder_lease_expires_mid_wait_success() {
+        let store = MockStore::default();
+        let lease = Duration::from_millis(100);
+        let mut holder = mock_lock(&store, lease);
+        let mut waiter = mock_lock(&store, lease);
+
+        // The holder crashes right after acquiring, the waiter polls until its lease ends
+        let acquired = holder.acquire_lock(&()).unwrap();
+        let taken = waiter
+            .acquire_while(
+                &(),
+                &(),
+                Duration::from_millis(10),
+                &ThreadSleeper,
+                |attempts, _| attempts < 3,
+            )
+            .unwrap();
+        assert!(taken.duration_since(acquired) >= lease);
+        assert_eq!(store.token("job"), waiter.driver().current_token());
+    }
+}
@@ -0,0 +1,74 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Pluggable sleeping for the blocking helpers.
+//!
+//! Helpers that wait (e.g., a rate limiter in `RateLimitMode::Block` mode) sleep
+//! through a `Sleeper`. The `ThreadSleeper` puts the current thread to sleep, while
+//! the `MockSleeper` records the requested durations and returns immediately, which
+//! lets tests assert the exact waiting schedule without real delays.
+
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+/// The Sleeper trait abstracts waiting for a duration.
+pub trait Sleeper {
+    /// Wait for `duration` before returning.
+    fn sleep(&self, duration: Duration);
+}
+
+/// A sleeper that puts the current thread to sleep.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct ThreadSleeper;
+
+impl Sleeper for ThreadSleeper {
+    fn sleep(&self, duration: Duration) {
+        thread::sleep(duration);
+    }
+}
+
+/// A sleeper that records the requested durations and returns immediately.
+///
+/// Clones share the same record, so a clone can be handed over to a driver while the
+/// original is kept to inspect the sleeps.
+///
+/// # Examples
+///
+/// ```rust
+/// use std::time::Duration;
+/// use dynalock::sleep::{MockSleeper, Sleeper};
+///
+/// let sleeper = MockSleeper::default();
+/// sleeper.clone().sleep(Duration::from_millis(100));
+///
+/// assert_eq!(sleeper.sleeps(), vec![Duration::from_millis(100)]);
+/// ```
+#[derive(Debug, Default, Clone)]
+pub struct MockSleeper {
+    sleeps: Arc<Mutex<Vec<Duration>>>,
+}
+
+impl MockSleeper {
+    /// Return the durations requested so far, in order.
+    pub fn sleeps(&self) -> Vec<Duration> {
+        self.sleeps.lock().unwrap().clone()
+    }
+}
+
+impl Sleeper for MockSleeper {
+    fn sleep(&self, duration: Duration) {
+        self.sleeps.lock().unwrap().push(duration);
+    }
+}
@@ -0,0 +1,214 @@
+//   Copyright 2018 Chorus One, Inc.
+//
+//   Licensed under the Apache License, Version 2.0 (the "License");
+//   you may not use this file except in compliance with the License.
+//   You may obtain a copy of the License at
+//
+//       http://www.apache.org/licenses/LICENSE-2.0
+//
+//   Unless required by applicable law or agreed to in writing, software
+//   distributed under the License is distributed on an "AS IS" BASIS,
+//   WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+//   See the License for the specific language governing permissions and
+//   limitations under the License.
+
+//! Contention statistics driven by the monotonic clock.
+//!
+//! Providers use the `ContentionTracker` structure to count the acquisitions of a lock,
+//! the attempts that found it held by another processor, and how long it was held from
+//! acquisition to release. Its `ContentionStats` tell how hot a lock is, e.g., to find
+//! the locks worth sharding. Counters only grow until the tracker is reset.
+
+use std::time::{Duration, Instant};
+
+/// The contention statistics of a lock over the `window` they were collected in.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct ContentionStats {
+    /// The number of acquisitions, not counting the renewals of a held lock.
+    pub acquired: u64,
+    /// The number of acquisition attempts that found the lock held by another processor.
+    pub contended: u64,
+    /// The number of holds that ended with a release.
+    pub released: u64,
+    /// The total time the released holds lasted, from acquisition to release.
+    pub total_hold: Duration,
+    /// The longest time a released hold lasted.
+    pub longest_hold: Duration,
+    /// The time the statistics were collected over.
+    pub window: Duration,
+}
+
+impl ContentionStats {
+    /// Return the number of acquisition attempts, successful or contended.
+    pub fn attempts(&self) -> u64 {
+        self.acquired + self.contended
+    }
+
+    /// Return the share of acquisition attempts that found the lock held, from 0 to 1,
+    /// or `None` when nothing was attempted.
+    pub fn contention_ratio(&self) -> Option<f64> {
+        match self.attempts() {
+            0 => None,
+            attempts => Some(self.contended as f64 / attempts as f64),
+        }
+    }
+
+    /// Return the average time the released holds lasted, or `None` when nothing was
+    /// released.
+    pub fn average_hold(&self) -> Option<Duration> {
+        match self.released {
+            0 => None,
+            released if released > u64::from(u32::max_value()) => None,
+            released => Some(self.total_hold / released as u32),
+        }
+    }
+
+    /// Return the average number of acquisitions per minute over the window, or `None`
+    /// when the window is empty.
+    pub fn acquisitions_per_minute(&self) -> Option<f64> {
+        let secs = self.window.as_secs() as f64 + f64::from(self.window.subsec_nanos()) / 1e9;
+        if secs == 0.0 {
+            return None;
+        }
+
+        Some(self.acquired as f64 * 60.0 / secs)
+    }
+}
+
+/// An accumulator of the `ContentionStats` of a lock.
+#[derive(Debug, Clone)]
+pub struct ContentionTracker {
+    stats: ContentionStats,
+    since: Instant,
+    held_since: Option<Instant>,
+}
+
+impl ContentionTracker {
+    /// Create a new tracker collecting statistics from `now`.
+    pub fn new(now: Instant) -> Self {
+        ContentionTracker {
+            stats: ContentionStats::default(),
+            since: now,
+            held_since: None,
+        }
+    }
+
+    /// Record a successful acquisition at the point in time `now`. The renewal of a lease
+    /// we hold neither counts as an acquisition nor restarts the hold.
+    pub fn record_acquired(&mut self, now: Instant, renewing: bool) {
+        if renewing && self.held_since.is_some() {
+            return;
+        }
+
+        self.stats.acquired += 1;
+        self.held_since = Some(now);
+    }
+
+    /// Record an acquisition attempt that found the lock held by another processor.
+    pub fn record_contended(&mut self) {
+        self.stats.contended += 1;
+    }
+
+    /// Record the release at the point in time `now` of the lock acquired last.
+    pub fn record_released(&mut self, now: Instant) {
+        if let Some(start) = self.held_since.take() {
+            let hold = now.duration_since(start);
+
+            self.stats.released += 1;
+            self.stats.total_hold += hold;
+            if hold > self.stats.longest_hold {
+                self.stats.longest_hold = hold;
+            }
+        }
+    }
+
+    /// Forget about the hold in progress, e.g., once the lock was lost to another
+    /// processor without being released.
+    pub fn record_lost(&mut self) {
+        self.held_since = None;
+    }
+
+    /// Return the statistics collected until `now`.
+    pub fn stats(&self, now: Instant) -> ContentionStats {
+        ContentionStats {
+            window: now.duration_since(self.since),
+            ..self.stats
+        }
+    }
+
+    /// Clear the statistics and start collecting them again from `now`. A hold in
+    /// progress is kept and counted once released.
+    pub fn reset(&mut self, now: Instant) {
+        self.stats = ContentionStats::default();
+        self.since = now;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_contention_tracker_success() {
+        let start = Instant::now();
+        let mut tracker = ContentionTracker::new(start);
+
+        tracker.record_acquired(start, false);
+        tracker.record_acquired(start + Duration::from_secs(5), true);
+        tracker.record_contended();
+        tracker.record_released(start + Duration::from_secs(10));
+
+        tracker.record_contended();
+        tracker.record_acquired(start + Duration::from_secs(20), false);
+        tracker.record_released(start + Duration::from_secs(40));
+
+        let stats = tracker.stats(start + Duration::from_secs(60));
+        assert_eq!(stats.acquired, 2);
+        assert_eq!(stats.contended, 2);
+        assert_eq!(stats.released, 2);
+        assert_eq!(stats.total_hold, Duration::from_secs(30));
+        assert_eq!(stats.longest_hold, Duration::from_secs(20));
+        assert_eq!(stats.window, Duration::from_secs(60));
+
+        assert_eq!(stats.attempts(), 4);
+        assert_eq!(stats.contention_ratio(), Some(0.5));
+        assert_eq!(stats.average_hold(), Some(Duration::from_secs(15)));
+        assert_eq!(stats.acquisitions_per_minute(), Some(2.0));
+    }
+
+    #[test]
+    fn test_contention_tracker_lost_hold_success() {
+        let start = Instant::now();
+        let mut tracker = ContentionTracker::new(start);
+
+        tracker.record_acquired(start, false);
+        tracker.record_lost();
+        tracker.record_released(start + Duration::from_secs(10));
+
+        let stats = tracker.stats(start + Duration::from_secs(10));
+        assert_eq!(stats.acquired, 1);
+        assert_eq!(stats.released, 0);
+        assert_eq!(stats.average_hold(), None);
+    }
+
+    #[test]
+    fn test_contention_tracker_reset_success() {
+        let start = Instant::now();
+        let mut tracker = ContentionTracker::new(start);
+
+        tracker.record_acquired(start, false);
+        tracker.record_contended();
+        tracker.reset(start + Duration::from_secs(10));
+
+        let stats = tracker.stats(start + Duration::from_secs(10));
+        assert_eq!(stats, ContentionStats::default());
+        assert_eq!(stats.contention_ratio(), None);
+        assert_eq!(stats.acquisitions_per_minute(), None);
+
+        // The hold in progress is still counted
+        tracker.record_released(start + Duration::from_secs(15));
+        let stats = tracker.stats(start + Duration::from_secs(20));
+        assert_eq!(stats.released, 1);
+        assert_eq!(stats.total_hold, Duration::from_secs(15));
+    }
+}